@@ -1,6 +1,6 @@
-use cast_schedule::{draw::calendar::*, draw::*, model::*};
+use cast_schedule::{colors::EventColors, display::*, frames::*, model::*};
 use chrono::{prelude::*, *};
-use draw::{render::bitmap::PngRenderer, render::save, *};
+use draw::{render::bitmap::PngRenderer, render::save};
 use std::path::PathBuf;
 use tempdir::TempDir;
 
@@ -24,36 +24,15 @@ fn midnight_today() -> DateTime<Local> {
 }
 
 fn create_image(tempdir: &TempDir) -> std::io::Result<Box<PathBuf>> {
-    let drawer = CalendarDrawer::new(DefiniteTimeRange {
+    let profile = std::env::args()
+        .nth(1)
+        .map(|model| DisplayProfile::for_model(&model))
+        .unwrap_or_default();
+    let range = DefiniteTimeRange {
         start: midnight_today() + Duration::hours(8),
         end: midnight_today() + Duration::hours(18) + Duration::days(1),
-    });
-    let model = mock_model();
-    let mut canvas = Canvas::new(720, 480);
-    let bounds = DrawingBounds {
-        left: 0,
-        top: 0,
-        height: 480,
-        width: 720,
     };
-    let mut drawings = drawer.draw(&model.events, &bounds);
-    canvas.display_list.add(
-        Drawing::new()
-            .with_shape(Shape::Rectangle {
-                width: 720,
-                height: 480,
-            })
-            .with_style(Style {
-                fill: Some(Fill {
-                    color: RGB::new(255, 255, 255),
-                }),
-                stroke: Some(Stroke {
-                    width: 5,
-                    color: RGB::new(0, 0, 0),
-                }),
-            }),
-    );
-    canvas.display_list.drawings.append(&mut drawings);
+    let canvas = schedule_frame(&mock_model(), range, &profile, &EventColors::new());
     let path = tempdir.path().join("image.png");
     save(&canvas, path.to_str().unwrap(), PngRenderer::new())?;
     Ok(Box::new(path))
@@ -71,7 +50,7 @@ fn mock_model() -> Model {
         },
         events: vec![
             CalendarEvent {
-                name: "Brunch".to_string(),
+                summary: "Brunch".to_string(),
                 times: IndefiniteTimeRange {
                     start: Some(
                         today
@@ -88,9 +67,10 @@ fn mock_model() -> Model {
                             .unwrap(),
                     ),
                 },
+                ..Default::default()
             },
             CalendarEvent {
-                name: "Reading".to_string(),
+                summary: "Reading".to_string(),
                 times: IndefiniteTimeRange {
                     start: Some(
                         today
@@ -107,7 +87,28 @@ fn mock_model() -> Model {
                             .unwrap(),
                     ),
                 },
+                ..Default::default()
             },
         ],
+        tasks: vec![
+            Task {
+                summary: "Send the invoice".to_string(),
+                due: Some(today),
+                priority: Some(1),
+                ..Default::default()
+            },
+            Task {
+                summary: "Water the plants".to_string(),
+                due: Some(today - Duration::days(1)),
+                ..Default::default()
+            },
+        ],
+        annotations: vec![DayAnnotation {
+            date: today + Duration::days(1),
+            label: "Company holiday".to_string(),
+            color: None,
+        }],
+        stale_since: None,
+        failed_calendars: Vec::new(),
     }
 }
@@ -0,0 +1,156 @@
+use super::calendar::*;
+use super::errors::*;
+use super::filter::EventPattern;
+use crate::model::*;
+use chrono::prelude::*;
+use std::collections::HashMap;
+
+/// A calendar whose all-day events from some of its calendars, like Google's "Holidays in ..." ones,
+/// are annotations to note on the day rather than events to draw
+pub struct AnnotatingCalendar<C: Calendar> {
+    calendar: C,
+    patterns: Vec<EventPattern>,
+}
+
+impl<C: Calendar> AnnotatingCalendar<C> {
+    /// Doesn't annotate anything until patterns are added
+    pub fn new(calendar: C) -> AnnotatingCalendar<C> {
+        AnnotatingCalendar {
+            calendar,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Annotates with the all-day events that match any of the patterns, usually by calendar
+    pub fn with_patterns(
+        mut self,
+        patterns: impl IntoIterator<Item = EventPattern>,
+    ) -> AnnotatingCalendar<C> {
+        self.patterns.extend(patterns);
+        self
+    }
+
+    fn mark(&self, events: Vec<CalendarEvent>) -> Vec<CalendarEvent> {
+        events
+            .into_iter()
+            .map(|event| {
+                match event.is_all_day()
+                    && self.patterns.iter().any(|pattern| pattern.matches(&event))
+                {
+                    true => CalendarEvent {
+                        kind: EventKind::Annotation,
+                        // They don't take up any time, even if the calendar says they do
+                        transparency: Transparency::Transparent,
+                        ..event
+                    },
+                    false => event,
+                }
+            })
+            .collect()
+    }
+}
+
+impl<C: Calendar> Calendar for AnnotatingCalendar<C> {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        Ok(self.mark(self.calendar.get_events_on(when).await?))
+    }
+
+    async fn get_cached_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<CachedEvents, CalendarError> {
+        let cached = self.calendar.get_cached_events_on(when).await?;
+        Ok(CachedEvents {
+            events: self.mark(cached.events),
+            ..cached
+        })
+    }
+
+    async fn get_changes(
+        &self,
+        when: &DefiniteTimeRange<Local>,
+        sync_tokens: &HashMap<String, String>,
+    ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+        let changes = match self.calendar.get_changes(when, sync_tokens).await? {
+            Ok(changes) => changes,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Ok(changes
+            .into_iter()
+            .map(|changes| CalendarChanges {
+                changed: self.mark(changes.changed),
+                ..changes
+            })
+            .collect()))
+    }
+
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        self.calendar.get_busy_times(when).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    struct StubCalendar(Vec<CalendarEvent>);
+
+    impl Calendar for StubCalendar {
+        async fn get_events_on(
+            &self,
+            _when: DefiniteTimeRange<Local>,
+        ) -> Result<Vec<CalendarEvent>, CalendarError> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn event(summary: &str, calendar: &str, all_day: bool) -> CalendarEvent {
+        let day = NaiveDate::from_ymd_opt(2023, 9, 4).unwrap();
+        CalendarEvent {
+            summary: summary.to_string(),
+            all_day: all_day.then(|| DateRange::single_day(day)),
+            source: Some(EventSource {
+                calendar_name: calendar.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[async_std::test]
+    async fn marks_all_day_events_from_matching_calendars() {
+        let calendar = StubCalendar(vec![
+            event("Labor Day", "Holidays in United States", true),
+            event("Offsite", "Team", true),
+            event("Holiday party", "Holidays in United States", false),
+        ]);
+        let pattern = EventPattern::Calendar(Regex::new("^Holidays in").unwrap());
+        let annotating = AnnotatingCalendar::new(calendar).with_patterns(vec![pattern]);
+
+        let start = Local.with_ymd_and_hms(2023, 9, 4, 0, 0, 0).unwrap();
+        let events = annotating
+            .get_events_on(DefiniteTimeRange {
+                start,
+                end: start + chrono::Duration::days(1),
+            })
+            .await
+            .unwrap();
+
+        let kinds: Vec<EventKind> = events.into_iter().map(|event| event.kind).collect();
+        assert_eq!(
+            vec![
+                EventKind::Annotation,
+                EventKind::Default,
+                EventKind::Default
+            ],
+            kinds
+        );
+    }
+}
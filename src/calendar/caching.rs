@@ -0,0 +1,507 @@
+use super::calendar::*;
+use super::errors::*;
+use super::tokens::write_private_file;
+use crate::model::*;
+use async_std::sync::Mutex;
+use async_std::task::spawn_blocking;
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How long after the last successful fetch cached events are still shown
+const DEFAULT_MAX_AGE_HOURS: i64 = 24;
+
+/// Remembers the last events fetched from a calendar, and shows them when it can't be fetched from.
+/// Calendars that can sync incrementally only send what's changed since the last fetch.
+pub struct CachingCalendar<C: Calendar> {
+    calendar: C,
+    path: Option<PathBuf>,
+    max_age: Duration,
+    cache: Mutex<Option<CachedCalendar>>,
+}
+
+/// What's kept between fetches, and between runs if there's a cache file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedCalendar {
+    /// The time range that `events` has every event from
+    range: DefiniteTimeRange<Local>,
+    fetched_at: DateTime<Utc>,
+    /// For each calendar that can sync incrementally, what to sync from next time
+    sync_tokens: HashMap<String, String>,
+    events: Vec<CalendarEvent>,
+}
+
+impl<C: Calendar> CachingCalendar<C> {
+    /// Caches events in memory only
+    pub fn new(calendar: C) -> CachingCalendar<C> {
+        CachingCalendar {
+            calendar,
+            path: None,
+            max_age: Duration::hours(DEFAULT_MAX_AGE_HOURS),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Keeps the cache in a file too, so it's there after a restart
+    pub fn with_cache_file(self, path: PathBuf) -> CachingCalendar<C> {
+        CachingCalendar {
+            path: Some(path),
+            ..self
+        }
+    }
+
+    /// Stops showing cached events this long after they were fetched
+    pub fn with_max_age(self, max_age: Duration) -> CachingCalendar<C> {
+        CachingCalendar { max_age, ..self }
+    }
+
+    /// Syncs from the cached events if they cover `when`, or else starts over
+    async fn fetch(
+        &self,
+        previous: Option<&CachedCalendar>,
+        when: &DefiniteTimeRange<Local>,
+    ) -> Result<CachedCalendar, CalendarError> {
+        let previous = previous.filter(|cached| cached.covers(when));
+        let no_tokens = HashMap::new();
+        let sync_tokens = previous.map_or(&no_tokens, |cached| &cached.sync_tokens);
+
+        let Some(changes) = self.calendar.get_changes(when, sync_tokens).await else {
+            let events = self.calendar.get_events_on(when.clone()).await?;
+            return Ok(CachedCalendar::new(when.clone(), events));
+        };
+        let changes = changes?;
+
+        let mut updated = match previous {
+            Some(previous) => CachedCalendar {
+                fetched_at: Utc::now(),
+                ..previous.clone()
+            },
+            None => CachedCalendar::new(when.clone(), Vec::new()),
+        };
+        // Forget calendars that aren't shown anymore
+        updated.events.retain(|event| {
+            let calendar_id = event
+                .source
+                .as_ref()
+                .map_or("", |source| source.calendar_id.as_str());
+            changes
+                .iter()
+                .any(|change| change.calendar_id == calendar_id)
+        });
+        updated.sync_tokens.retain(|calendar_id, _| {
+            changes
+                .iter()
+                .any(|change| &change.calendar_id == calendar_id)
+        });
+        for change in changes {
+            updated.apply(change);
+        }
+        Ok(updated)
+    }
+
+    /// The cache file is read and written on a blocking thread, so a slow disk doesn't hold up other requests
+    async fn load(&self) -> Option<CachedCalendar> {
+        let path = self.path.clone()?;
+        spawn_blocking(move || read_cache(&path)).await
+    }
+
+    async fn save(&self, cached: &CachedCalendar) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let contents = match serde_json::to_string(cached) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("could not cache events in {}: {:?}", path.display(), e);
+                return;
+            }
+        };
+        // Event titles can be private, so the cache is as private as the tokens
+        spawn_blocking(move || {
+            if let Err(e) = write_private_file(&path, &contents) {
+                eprintln!("could not cache events in {}: {:?}", path.display(), e);
+            }
+        })
+        .await
+    }
+}
+
+fn read_cache(path: &Path) -> Option<CachedCalendar> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            eprintln!(
+                "could not read cached events from {}: {:?}",
+                path.display(),
+                e
+            );
+            return None;
+        }
+    };
+    match serde_json::from_str(&contents) {
+        Ok(cached) => Some(cached),
+        Err(e) => {
+            eprintln!(
+                "ignoring unreadable cached events in {}: {:?}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+impl<C: Calendar> Calendar for CachingCalendar<C> {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        Ok(self.get_cached_events_on(when).await?.events)
+    }
+
+    /// Fetches events, or falls back to the cached ones if that fails.
+    /// Login problems aren't covered up, since they won't go away on their own.
+    async fn get_cached_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<CachedEvents, CalendarError> {
+        let mut cache = self.cache.lock().await;
+        if cache.is_none() {
+            *cache = self.load().await;
+        }
+
+        match self.fetch(cache.as_ref(), &when).await {
+            Ok(fresh) => {
+                self.save(&fresh).await;
+                let events = fresh.events_in(&when);
+                *cache = Some(fresh);
+                Ok(CachedEvents {
+                    events,
+                    stale_since: None,
+                    failed_calendars: Vec::new(),
+                })
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::LoginError | ErrorKind::LoggedOut) => Err(e),
+            Err(e) => match cache
+                .as_ref()
+                .filter(|cached| Utc::now() - cached.fetched_at < self.max_age)
+                .filter(|cached| cached.covers(&when))
+            {
+                Some(cached) => {
+                    eprintln!(
+                        "could not fetch events, showing the ones from {}: {}",
+                        cached.fetched_at.with_timezone(&Local).format("%H:%M"),
+                        e
+                    );
+                    Ok(CachedEvents {
+                        events: cached.events_in(&when),
+                        stale_since: Some(cached.fetched_at),
+                        failed_calendars: Vec::new(),
+                    })
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Asks the calendar, or works it out from the cached events if that fails
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        match self.calendar.get_busy_times(when.clone()).await {
+            Err(e) if !matches!(e.kind(), ErrorKind::LoginError | ErrorKind::LoggedOut) => {
+                let cache = self.cache.lock().await;
+                match cache
+                    .as_ref()
+                    .filter(|cached| Utc::now() - cached.fetched_at < self.max_age)
+                    .filter(|cached| cached.covers(&when))
+                {
+                    Some(cached) => Ok(BusyTimes::of_events(&cached.events_in(&when))),
+                    None => Err(e),
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+impl CachedCalendar {
+    fn new(range: DefiniteTimeRange<Local>, events: Vec<CalendarEvent>) -> CachedCalendar {
+        CachedCalendar {
+            range,
+            fetched_at: Utc::now(),
+            sync_tokens: HashMap::new(),
+            events,
+        }
+    }
+
+    fn covers(&self, when: &DefiniteTimeRange<Local>) -> bool {
+        self.range.start <= when.start && when.end <= self.range.end
+    }
+
+    fn apply(&mut self, change: CalendarChanges) {
+        let in_calendar = |event: &CalendarEvent| {
+            event
+                .source
+                .as_ref()
+                .is_some_and(|s| s.calendar_id == change.calendar_id)
+        };
+        if change.full {
+            self.events.retain(|event| !in_calendar(event));
+        } else {
+            let replaced = |event: &CalendarEvent| {
+                event.id.as_ref().is_some_and(|id| {
+                    change.removed.contains(id)
+                        || change
+                            .changed
+                            .iter()
+                            .any(|changed| changed.id.as_ref() == Some(id))
+                })
+            };
+            self.events
+                .retain(|event| !(in_calendar(event) && replaced(event)));
+        }
+
+        match change.sync_token {
+            Some(sync_token) => self.sync_tokens.insert(change.calendar_id, sync_token),
+            None => self.sync_tokens.remove(&change.calendar_id),
+        };
+        self.events.extend(change.changed);
+    }
+
+    /// Incremental syncs include changes from any time, so only the ones in `when` are shown
+    fn events_in(&self, when: &DefiniteTimeRange<Local>) -> Vec<CalendarEvent> {
+        let mut events: Vec<CalendarEvent> = self
+            .events
+            .iter()
+            .filter(|event| match (event.times.start, event.times.end) {
+                (Some(start), Some(end)) if start == end => when.start <= start && start < when.end,
+                (Some(start), Some(end)) => start < when.end && when.start < end,
+                _ => false,
+            })
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| event.times.start);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use tempdir::TempDir;
+
+    /// A calendar that syncs incrementally, answering with scripted changes and remembering the tokens it was given
+    #[derive(Default)]
+    struct ScriptedCalendar {
+        responses: std::sync::Mutex<VecDeque<Result<Vec<CalendarChanges>, CalendarError>>>,
+        tokens_seen: std::sync::Mutex<Vec<HashMap<String, String>>>,
+    }
+
+    impl ScriptedCalendar {
+        fn then(self, response: Result<Vec<CalendarChanges>, CalendarError>) -> ScriptedCalendar {
+            self.responses.lock().unwrap().push_back(response);
+            self
+        }
+    }
+
+    impl Calendar for ScriptedCalendar {
+        async fn get_events_on(
+            &self,
+            _: DefiniteTimeRange<Local>,
+        ) -> Result<Vec<CalendarEvent>, CalendarError> {
+            unreachable!("everything should go through get_changes")
+        }
+
+        async fn get_changes(
+            &self,
+            _: &DefiniteTimeRange<Local>,
+            sync_tokens: &HashMap<String, String>,
+        ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+            self.tokens_seen.lock().unwrap().push(sync_tokens.clone());
+            self.responses.lock().unwrap().pop_front()
+        }
+
+        /// Free/busy isn't scripted, so asking for it fails as if the server were down
+        async fn get_busy_times(
+            &self,
+            _: DefiniteTimeRange<Local>,
+        ) -> Result<BusyTimes, CalendarError> {
+            Err(CalendarError::fetch("server is down"))
+        }
+    }
+
+    fn today() -> DefiniteTimeRange<Local> {
+        let start = Local::now()
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+        DefiniteTimeRange {
+            start,
+            end: start + Duration::days(1),
+        }
+    }
+
+    fn event(id: &str, summary: &str, hour: i64) -> CalendarEvent {
+        let start = today().start + Duration::hours(hour);
+        CalendarEvent {
+            id: Some(id.to_string()),
+            summary: summary.to_string(),
+            times: IndefiniteTimeRange {
+                start: Some(start),
+                end: Some(start + Duration::hours(1)),
+            },
+            source: Some(EventSource {
+                calendar_id: "work".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn changes(
+        full: bool,
+        changed: Vec<CalendarEvent>,
+        removed: &[&str],
+        sync_token: &str,
+    ) -> Vec<CalendarChanges> {
+        vec![CalendarChanges {
+            calendar_id: "work".to_string(),
+            full,
+            changed,
+            removed: removed.iter().map(|id| id.to_string()).collect(),
+            sync_token: Some(sync_token.to_string()),
+        }]
+    }
+
+    fn summaries(events: &[CalendarEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.summary.as_str()).collect()
+    }
+
+    #[async_std::test]
+    async fn applies_incremental_changes_to_the_last_sync() {
+        let scripted = ScriptedCalendar::default()
+            .then(Ok(changes(
+                true,
+                vec![
+                    event("review", "Review", 14),
+                    event("standup", "Standup", 9),
+                ],
+                &[],
+                "first",
+            )))
+            .then(Ok(changes(
+                false,
+                vec![
+                    event("review", "Review (moved)", 11),
+                    event("lunch", "Lunch", 12),
+                ],
+                &["standup"],
+                "second",
+            )));
+        let calendar = CachingCalendar::new(scripted);
+
+        assert_eq!(
+            vec!["Standup", "Review"],
+            summaries(&calendar.get_events_on(today()).await.unwrap())
+        );
+        assert_eq!(
+            vec!["Review (moved)", "Lunch"],
+            summaries(&calendar.get_events_on(today()).await.unwrap())
+        );
+
+        let tokens_seen = calendar.calendar.tokens_seen.lock().unwrap().clone();
+        assert_eq!(None, tokens_seen[0].get("work"));
+        assert_eq!(
+            Some("first"),
+            tokens_seen[1].get("work").map(String::as_str)
+        );
+    }
+
+    #[async_std::test]
+    async fn serves_stale_events_from_disk_when_fetching_fails() {
+        let dir = TempDir::new("cache").unwrap();
+        let path = dir.path().join("work.json");
+
+        let scripted = ScriptedCalendar::default().then(Ok(changes(
+            true,
+            vec![event("standup", "Standup", 9)],
+            &[],
+            "first",
+        )));
+        CachingCalendar::new(scripted)
+            .with_cache_file(path.clone())
+            .get_events_on(today())
+            .await
+            .unwrap();
+
+        let scripted = ScriptedCalendar::default()
+            .then(Err(CalendarError::fetch("server is down")))
+            .then(Err(CalendarError::logged_out("token was revoked")));
+        let calendar = CachingCalendar::new(scripted).with_cache_file(path.clone());
+        let cached = calendar.get_cached_events_on(today()).await.unwrap();
+        assert_eq!(vec!["Standup"], summaries(&cached.events));
+        assert!(cached.stale_since.is_some());
+        assert_eq!(
+            Some("first"),
+            calendar.calendar.tokens_seen.lock().unwrap()[0]
+                .get("work")
+                .map(String::as_str)
+        );
+        // Waiting won't fix a login, so that's not covered up
+        assert_eq!(
+            ErrorKind::LoggedOut,
+            calendar.get_events_on(today()).await.unwrap_err().kind()
+        );
+
+        let scripted =
+            ScriptedCalendar::default().then(Err(CalendarError::fetch("server is down")));
+        let calendar = CachingCalendar::new(scripted)
+            .with_cache_file(path)
+            .with_max_age(Duration::zero());
+        assert_eq!(
+            ErrorKind::FetchError,
+            calendar.get_events_on(today()).await.unwrap_err().kind()
+        );
+    }
+
+    #[async_std::test]
+    async fn fails_rather_than_serving_cached_events_from_another_day() {
+        let scripted = ScriptedCalendar::default()
+            .then(Ok(changes(
+                true,
+                vec![event("standup", "Standup", 9)],
+                &[],
+                "first",
+            )))
+            .then(Err(CalendarError::fetch("server is down")));
+        let calendar = CachingCalendar::new(scripted);
+        calendar.get_events_on(today()).await.unwrap();
+        assert!(calendar.get_busy_times(today()).await.is_ok());
+
+        // e.g. just after midnight, with the network down since yesterday
+        let tomorrow = DefiniteTimeRange {
+            start: today().end,
+            end: today().end + Duration::days(1),
+        };
+        assert_eq!(
+            ErrorKind::FetchError,
+            calendar
+                .get_events_on(tomorrow.clone())
+                .await
+                .unwrap_err()
+                .kind()
+        );
+        assert_eq!(
+            ErrorKind::FetchError,
+            calendar.get_busy_times(tomorrow).await.unwrap_err().kind()
+        );
+    }
+}
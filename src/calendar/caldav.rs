@@ -0,0 +1,540 @@
+use super::calendar::*;
+use super::errors::*;
+use super::ics::parse_events;
+use crate::model::*;
+use async_std::sync::Mutex;
+use chrono::prelude::*;
+use futures_util::future::join_all;
+use reqwest::{Method, StatusCode, Url};
+use std::time::Instant;
+
+const DAV: &str = "DAV:";
+const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+/// Where Apple (and Nextcloud, and Radicale) keep a calendar's color
+const APPLE_ICAL: &str = "http://apple.com/ns/ical/";
+
+const PRINCIPAL_QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:current-user-principal/></d:prop></d:propfind>"#;
+
+const CALENDAR_HOME_QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav"><d:prop><c:calendar-home-set/></d:prop></d:propfind>"#;
+
+const CALENDARS_QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav" xmlns:a="http://apple.com/ns/ical/">
+<d:prop><d:resourcetype/><d:displayname/><a:calendar-color/><c:supported-calendar-component-set/></d:prop>
+</d:propfind>"#;
+
+#[derive(Debug, Clone)]
+pub enum CalDavAuth {
+    None,
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+/// A calendar collection on the server
+#[derive(Debug, Clone)]
+pub struct CalDavCollection {
+    pub url: Url,
+    pub name: String,
+    pub color: Option<Color>,
+}
+
+/// A CalDAV server like Nextcloud or Radicale. Calendars are discovered from the URL
+/// through the user's principal and calendar home, as RFC 4791 and RFC 5397 describe.
+pub struct CalDavCalendar {
+    http: reqwest::Client,
+    url: Url,
+    auth: CalDavAuth,
+    selectors: Vec<CalendarSelector>,
+    /// The calendars the selectors picked, and when they were discovered
+    selected: Mutex<Option<(Instant, Vec<CalDavCollection>)>>,
+}
+
+impl CalDavCalendar {
+    pub fn new(url: Url, auth: CalDavAuth) -> CalDavCalendar {
+        CalDavCalendar {
+            http: reqwest::Client::new(),
+            url,
+            auth,
+            selectors: Vec::new(),
+            selected: Mutex::new(None),
+        }
+    }
+
+    /// Which calendars to show, by URL or name. With no selectors, every calendar with events is shown.
+    pub fn with_calendars(self, selectors: Vec<CalendarSelector>) -> CalDavCalendar {
+        CalDavCalendar { selectors, ..self }
+    }
+
+    /// Every calendar in the user's calendar home that can hold events
+    pub async fn list_calendars(&self) -> Result<Vec<CalDavCollection>, CalendarError> {
+        let principal = self
+            .propfind(&self.url, "0", PRINCIPAL_QUERY)
+            .await?
+            .into_iter()
+            .find_map(|response| response.principal)
+            .ok_or_else(|| missing("current-user-principal", &self.url))?;
+        let principal = self.resolve(&principal)?;
+
+        let home = self
+            .propfind(&principal, "0", CALENDAR_HOME_QUERY)
+            .await?
+            .into_iter()
+            .find_map(|response| response.calendar_home)
+            .ok_or_else(|| missing("calendar-home-set", &principal))?;
+        let home = self.resolve(&home)?;
+
+        self.propfind(&home, "1", CALENDARS_QUERY)
+            .await?
+            .into_iter()
+            .filter(|response| response.is_calendar && response.supports_events)
+            .map(|response| {
+                let url = self.resolve(&response.href)?;
+                Ok(CalDavCollection {
+                    name: response
+                        .display_name
+                        .unwrap_or_else(|| url.path().to_string()),
+                    color: response.color.as_deref().and_then(parse_color),
+                    url,
+                })
+            })
+            .collect()
+    }
+
+    /// The calendars to show, which are only discovered again once they're an hour old
+    /// or one of them has gone away
+    async fn selected_calendars(&self) -> Result<Vec<CalDavCollection>, CalendarError> {
+        let mut selected = self.selected.lock().await;
+        if let Some((discovered_at, calendars)) = selected.as_ref() {
+            if discovered_at.elapsed() < CALENDAR_LIST_MAX_AGE {
+                return Ok(calendars.clone());
+            }
+        }
+        let calendars: Vec<CalDavCollection> = self
+            .list_calendars()
+            .await?
+            .into_iter()
+            .filter(|calendar| {
+                self.selectors.is_empty()
+                    || self
+                        .selectors
+                        .iter()
+                        .any(|selector| selector.matches(calendar.url.as_str(), &calendar.name))
+            })
+            .collect();
+        *selected = Some((Instant::now(), calendars.clone()));
+        Ok(calendars)
+    }
+
+    async fn get_collection_events(
+        &self,
+        calendar: &CalDavCollection,
+        date: &DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let responses = self
+            .request("REPORT", &calendar.url, "1", calendar_query(date))
+            .await?;
+        let source = EventSource {
+            calendar_id: calendar.url.to_string(),
+            calendar_name: calendar.name.clone(),
+            background_color: calendar.color,
+            ..Default::default()
+        };
+
+        let mut events = Vec::new();
+        for response in responses {
+            let Some(data) = response.calendar_data else {
+                continue;
+            };
+            match parse_events(&data, calendar.url.as_str(), date) {
+                Ok(parsed) => events.extend(parsed),
+                // One broken event shouldn't hide the rest of the calendar
+                Err(e) => eprintln!("skipping unreadable event {}: {:?}", response.href, e),
+            }
+        }
+        for event in &mut events {
+            event.source = Some(source.clone());
+        }
+        Ok(events)
+    }
+
+    async fn propfind(
+        &self,
+        url: &Url,
+        depth: &str,
+        body: &str,
+    ) -> Result<Vec<DavResponse>, CalendarError> {
+        self.request("PROPFIND", url, depth, body.to_string()).await
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        url: &Url,
+        depth: &str,
+        body: String,
+    ) -> Result<Vec<DavResponse>, CalendarError> {
+        let method = Method::from_bytes(method.as_bytes()).map_err(|e| {
+            CalendarError::fetch(format!("invalid method {}", method)).with_cause(e)
+        })?;
+        let request = self
+            .http
+            .request(method.clone(), url.clone())
+            .header("Depth", depth)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/xml; charset=utf-8",
+            )
+            .body(body);
+        let request = match &self.auth {
+            CalDavAuth::None => request,
+            CalDavAuth::Basic { username, password } => {
+                request.basic_auth(username, Some(password))
+            }
+            CalDavAuth::Bearer(token) => request.bearer_auth(token),
+        };
+
+        let response = request.send().await.map_err(|e| {
+            CalendarError::fetch(format!("could not {} {}", method, url)).with_cause(e)
+        })?;
+        match response.status() {
+            status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+                return Err(CalendarError::login(format!(
+                    "CalDAV server refused our credentials for {}",
+                    url
+                ))
+                .with_status(status));
+            }
+            status if !status.is_success() => {
+                return Err(CalendarError::from_response(
+                    format!("could not {} {}", method, url),
+                    &response,
+                ));
+            }
+            _ => {}
+        }
+
+        let text = response.text().await.map_err(|e| {
+            CalendarError::fetch(format!("could not read response to {} {}", method, url))
+                .with_cause(e)
+        })?;
+        parse_multistatus(&text).map_err(|e| {
+            CalendarError::fetch(format!("could not parse response to {} {}", method, url))
+                .with_cause(e)
+        })
+    }
+
+    /// Servers usually send paths, which are relative to the server we asked
+    fn resolve(&self, href: &str) -> Result<Url, CalendarError> {
+        self.url.join(href).map_err(|e| {
+            CalendarError::fetch(format!("invalid href {:?} from CalDAV server", href))
+                .with_cause(e)
+        })
+    }
+}
+
+impl Calendar for CalDavCalendar {
+    /// Only fails if every selected calendar did; otherwise the ones that failed are left out
+    async fn get_events_on(
+        &self,
+        date: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let calendars = self.selected_calendars().await?;
+        let results = join_all(
+            calendars
+                .iter()
+                .map(|calendar| self.get_collection_events(calendar, &date)),
+        )
+        .await;
+        if any_calendar_gone(&results) {
+            *self.selected.lock().await = None;
+        }
+        Ok(
+            unless_every_calendar_failed(calendars.iter().map(|calendar| &calendar.name), results)?
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect(),
+        )
+    }
+}
+
+fn missing(property: &str, url: &Url) -> CalendarError {
+    CalendarError::fetch(format!(
+        "CalDAV server didn't say what the {} of {} is",
+        property, url
+    ))
+}
+
+/// Asks for every event that overlaps the time range, including repeating events with an occurrence in it.
+/// They come back unexpanded, as the whole series, and are expanded like any other iCalendar file;
+/// `<c:expand>` isn't asked for because not every server supports it.
+fn calendar_query(date: &DefiniteTimeRange<Local>) -> String {
+    let format = |t: &DateTime<Local>| t.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string();
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+<d:prop><c:calendar-data/></d:prop>
+<c:filter><c:comp-filter name="VCALENDAR"><c:comp-filter name="VEVENT">
+<c:time-range start="{}" end="{}"/>
+</c:comp-filter></c:comp-filter></c:filter>
+</c:calendar-query>"#,
+        format(&date.start),
+        format(&date.end)
+    )
+}
+
+/// Apple's colors are `#RRGGBB` or `#RRGGBBAA`
+fn parse_color(color: &str) -> Option<Color> {
+    let color = color.trim();
+    Color::from_hex(color.get(..7).unwrap_or(color))
+}
+
+/// The properties we ask for, from one `<d:response>` of a multistatus
+#[derive(Debug, Default)]
+struct DavResponse {
+    href: String,
+    principal: Option<String>,
+    calendar_home: Option<String>,
+    display_name: Option<String>,
+    is_calendar: bool,
+    supports_events: bool,
+    color: Option<String>,
+    calendar_data: Option<String>,
+}
+
+fn parse_multistatus(text: &str) -> Result<Vec<DavResponse>, roxmltree::Error> {
+    let document = roxmltree::Document::parse(text)?;
+    Ok(document
+        .descendants()
+        .filter(|node| is(node, DAV, "response"))
+        .map(|node| {
+            let mut response = DavResponse {
+                href: child(node, DAV, "href")
+                    .and_then(|href| href.text())
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                // Servers leave the component set out when a calendar can hold anything
+                supports_events: true,
+                ..DavResponse::default()
+            };
+            let found_props = node
+                .children()
+                .filter(|propstat| is(propstat, DAV, "propstat"))
+                // Properties the server doesn't have come back in a propstat of their own, with a 404
+                .filter(|propstat| {
+                    match child(*propstat, DAV, "status").and_then(|status| status.text()) {
+                        Some(status) => status.contains(" 200 "),
+                        None => true,
+                    }
+                })
+                .filter_map(|propstat| child(propstat, DAV, "prop"))
+                .flat_map(|prop| prop.children().filter(|n| n.is_element()));
+            for prop in found_props {
+                let href = || {
+                    child(prop, DAV, "href")
+                        .and_then(|href| href.text())
+                        .map(|href| href.trim().to_string())
+                };
+                match (prop.tag_name().namespace(), prop.tag_name().name()) {
+                    (Some(DAV), "current-user-principal") => response.principal = href(),
+                    (Some(CALDAV), "calendar-home-set") => response.calendar_home = href(),
+                    (Some(DAV), "displayname") => {
+                        response.display_name = prop.text().map(str::to_string)
+                    }
+                    (Some(DAV), "resourcetype") => {
+                        response.is_calendar = child(prop, CALDAV, "calendar").is_some()
+                    }
+                    (Some(CALDAV), "supported-calendar-component-set") => {
+                        response.supports_events = prop.children().any(|comp| {
+                            is(&comp, CALDAV, "comp") && comp.attribute("name") == Some("VEVENT")
+                        })
+                    }
+                    (Some(APPLE_ICAL), "calendar-color") => {
+                        response.color = prop.text().map(str::to_string)
+                    }
+                    (Some(CALDAV), "calendar-data") => {
+                        response.calendar_data = prop.text().map(str::to_string)
+                    }
+                    _ => {}
+                }
+            }
+            response
+        })
+        .collect())
+}
+
+fn is(node: &roxmltree::Node, namespace: &str, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().namespace() == Some(namespace)
+        && node.tag_name().name() == name
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| is(child, namespace, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    const EVENT: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:review\r\nSUMMARY:Design review\r\nDTSTART:20230905T130000Z\r\nDTEND:20230905T140000Z\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+    fn multistatus(responses: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?><d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav" xmlns:a="http://apple.com/ns/ical/">{}</d:multistatus>"#,
+            responses
+        )
+    }
+
+    /// A server with a principal, a calendar home, and in it a calendar, one that can't be read, a task list, and an inbox
+    fn fake_server(check_auth: impl Fn(&StubRequest) -> bool + Send + 'static) -> StubServer {
+        StubServer::start(move |request| {
+            if !check_auth(request) {
+                return StubResponse::new(401, "");
+            }
+            let body = match (request.method.as_str(), request.path.as_str()) {
+                ("PROPFIND", "/dav/") => multistatus(
+                    "<d:response><d:href>/dav/</d:href><d:propstat><d:prop>
+                     <d:current-user-principal><d:href>/dav/principals/me/</d:href></d:current-user-principal>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+                ),
+                ("PROPFIND", "/dav/principals/me/") => multistatus(
+                    "<d:response><d:href>/dav/principals/me/</d:href><d:propstat><d:prop>
+                     <c:calendar-home-set><d:href>/dav/calendars/me/</d:href></c:calendar-home-set>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+                ),
+                ("PROPFIND", "/dav/calendars/me/") => multistatus(
+                    "<d:response><d:href>/dav/calendars/me/</d:href><d:propstat><d:prop>
+                     <d:resourcetype><d:collection/></d:resourcetype>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>
+                     <d:response><d:href>/dav/calendars/me/work/</d:href><d:propstat><d:prop>
+                     <d:resourcetype><d:collection/><c:calendar/></d:resourcetype>
+                     <d:displayname>Work</d:displayname><a:calendar-color>#FF2968FF</a:calendar-color>
+                     <c:supported-calendar-component-set><c:comp name=\"VEVENT\"/></c:supported-calendar-component-set>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>
+                     <d:response><d:href>/dav/calendars/me/personal/</d:href><d:propstat><d:prop>
+                     <d:resourcetype><d:collection/><c:calendar/></d:resourcetype>
+                     <d:displayname>Personal</d:displayname>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>
+                     <d:response><d:href>/dav/calendars/me/tasks/</d:href><d:propstat><d:prop>
+                     <d:resourcetype><d:collection/><c:calendar/></d:resourcetype>
+                     <d:displayname>Tasks</d:displayname>
+                     <c:supported-calendar-component-set><c:comp name=\"VTODO\"/></c:supported-calendar-component-set>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>
+                     <d:response><d:href>/dav/calendars/me/inbox/</d:href><d:propstat><d:prop>
+                     <d:resourcetype><d:collection/><c:schedule-inbox/></d:resourcetype>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+                ),
+                ("REPORT", "/dav/calendars/me/work/") => multistatus(&format!(
+                    "<d:response><d:href>/dav/calendars/me/work/review.ics</d:href><d:propstat><d:prop>
+                     <c:calendar-data>{}</c:calendar-data>
+                     </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+                    EVENT
+                )),
+                ("REPORT", "/dav/calendars/me/personal/") => return StubResponse::new(500, ""),
+                _ => return StubResponse::new(404, ""),
+            };
+            StubResponse::new(207, &body)
+                .with_header("Content-Type", "application/xml; charset=utf-8")
+        })
+    }
+
+    #[async_std::test]
+    async fn discovers_calendars_and_fetches_their_events() {
+        let server =
+            fake_server(|request| request.header("Authorization") == Some("Basic bWU6c2VjcmV0"));
+        let auth = CalDavAuth::Basic {
+            username: "me".to_string(),
+            password: "secret".to_string(),
+        };
+        let calendar = CalDavCalendar::new(Url::parse(&server.url("/dav/")).unwrap(), auth);
+
+        let calendars = calendar.list_calendars().await.unwrap();
+        assert_eq!(
+            vec!["Work", "Personal"],
+            calendars
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(Color {
+                r: 0xff,
+                g: 0x29,
+                b: 0x68
+            }),
+            calendars[0].color
+        );
+
+        let events = calendar.get_events_on(september_5th()).await.unwrap();
+        assert_eq!(
+            vec!["Design review"],
+            events
+                .iter()
+                .map(|e| e.summary.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("Work", events[0].source.as_ref().unwrap().calendar_name);
+
+        let report = server
+            .requests()
+            .into_iter()
+            .find(|r| r.method == "REPORT" && r.path == "/dav/calendars/me/work/")
+            .unwrap();
+        assert_eq!(Some("1"), report.header("Depth"));
+        assert!(report
+            .body
+            .contains(r#"<c:time-range start="20230905T000000Z" end="20230906T000000Z"/>"#));
+    }
+
+    #[async_std::test]
+    async fn shows_the_other_calendars_when_one_fails_without_discovering_them_again() {
+        let server = fake_server(|_| true);
+        let calendar =
+            CalDavCalendar::new(Url::parse(&server.url("/dav/")).unwrap(), CalDavAuth::None);
+
+        for _ in 0..2 {
+            let events = calendar.get_events_on(september_5th()).await.unwrap();
+            assert_eq!(
+                vec!["Design review"],
+                events
+                    .iter()
+                    .map(|e| e.summary.as_str())
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        let requests = server.requests();
+        let count = |method: &str| requests.iter().filter(|r| r.method == method).count();
+        assert_eq!(3, count("PROPFIND"));
+        assert_eq!(4, count("REPORT"));
+    }
+
+    #[async_std::test]
+    async fn sends_bearer_token_and_reports_refusal() {
+        let server =
+            fake_server(|request| request.header("Authorization") == Some("Bearer letmein"));
+
+        let calendar = CalDavCalendar::new(
+            Url::parse(&server.url("/dav/")).unwrap(),
+            CalDavAuth::Bearer("letmein".to_string()),
+        );
+        assert_eq!(2, calendar.list_calendars().await.unwrap().len());
+
+        let calendar = CalDavCalendar::new(
+            Url::parse(&server.url("/dav/")).unwrap(),
+            CalDavAuth::Bearer("wrong".to_string()),
+        );
+        assert_eq!(
+            ErrorKind::LoginError,
+            calendar.list_calendars().await.unwrap_err().kind()
+        );
+    }
+}
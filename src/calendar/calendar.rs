@@ -1,13 +1,216 @@
-
-use chrono::Local;
 use super::errors::*;
 use crate::model::*;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use regex::Regex;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::fmt::Display;
 
 pub trait Calendar {
-    async fn get_events_on(&self, when: DefiniteTimeRange<Local>) -> Result<Vec<CalendarEvent>, CalendarError>;
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError>;
+
+    /// The events in `when`, and when they were fetched if they're from a cache because fetching them failed
+    async fn get_cached_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<CachedEvents, CalendarError> {
+        Ok(CachedEvents {
+            events: self.get_events_on(when).await?,
+            stale_since: None,
+            failed_calendars: Vec::new(),
+        })
+    }
+
+    /// What changed in each calendar since the syncs that returned `sync_tokens`, keyed by calendar ID.
+    /// Calendars without a token get everything in `when`, and a token to sync from next time.
+    /// Returns nothing if the calendar can't sync incrementally.
+    async fn get_changes(
+        &self,
+        _when: &DefiniteTimeRange<Local>,
+        _sync_tokens: &HashMap<String, String>,
+    ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+        None
+    }
+
+    /// When the calendar's owner is busy during `when`.
+    /// Worked out from the events unless the provider can say directly.
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        Ok(BusyTimes::of_events(&self.get_events_on(when).await?))
+    }
+}
+
+/// Lets a calendar be wrapped for a while without giving it up, e.g. so it can be changed in between
+impl<C: Calendar> Calendar for &C {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        (*self).get_events_on(when).await
+    }
+
+    async fn get_cached_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<CachedEvents, CalendarError> {
+        (*self).get_cached_events_on(when).await
+    }
+
+    async fn get_changes(
+        &self,
+        when: &DefiniteTimeRange<Local>,
+        sync_tokens: &HashMap<String, String>,
+    ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+        (*self).get_changes(when, sync_tokens).await
+    }
+
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        (*self).get_busy_times(when).await
+    }
+}
+
+/// Events that might be from a cache, and when they were fetched if that wasn't just now
+#[derive(Debug)]
+pub struct CachedEvents {
+    pub events: Vec<CalendarEvent>,
+    /// When the events were last fetched, if fetching them again just failed
+    pub stale_since: Option<DateTime<Utc>>,
+    /// The calendars that were left out because they couldn't be fetched, when others could
+    pub failed_calendars: Vec<String>,
+}
+
+/// What changed in one calendar since the last sync
+#[derive(Debug, Default)]
+pub struct CalendarChanges {
+    pub calendar_id: String,
+    /// Whether `changed` is every event, rather than only the ones that changed
+    pub full: bool,
+    pub changed: Vec<CalendarEvent>,
+    /// IDs of events that were deleted or cancelled
+    pub removed: Vec<String>,
+    /// What to pass next time to get what's changed since this
+    pub sync_token: Option<String>,
+}
+
+/// A to-do list, read alongside the calendar
+pub trait TaskList {
+    /// Tasks that aren't done yet and are due on or before `by`, so overdue ones are included
+    async fn get_tasks_due(&self, by: NaiveDate) -> Result<Vec<Task>, CalendarError>;
 }
 
 pub trait CalendarProvider {
     type Calendar: Calendar;
-    fn login(&mut self) -> Result<Self::Calendar, CalendarError>;
+    async fn login(&mut self) -> Result<Self::Calendar, CalendarError>;
+}
+
+/// How many events are fetched for one request unless a calendar is told otherwise
+pub(crate) const DEFAULT_MAX_EVENTS: usize = 2500;
+
+/// Keeps a calendar that's following pages of results from fetching more than `max_events` for one request.
+/// Returns whether `events` reached the limit, in which case they're cut down to it and no more pages
+/// should be fetched.
+pub(crate) fn reached_max_events<T>(
+    events: &mut Vec<T>,
+    max_events: usize,
+    calendar: &str,
+) -> bool {
+    if events.len() < max_events {
+        return false;
+    }
+    eprintln!(
+        "warning: stopped after {} events from calendar {}; later events are missing",
+        max_events, calendar
+    );
+    events.truncate(max_events);
+    true
+}
+
+/// How long to go on using a provider's list of calendars before fetching it again, to see ones that were added or renamed
+pub(crate) const CALENDAR_LIST_MAX_AGE: std::time::Duration =
+    std::time::Duration::from_secs(60 * 60);
+
+/// Leaves out the calendars that couldn't be fetched, e.g. because they were unshared, and only fails if every one of them failed.
+/// `names` are the calendars the results are from, in the same order, to say which ones are left out.
+pub(crate) fn unless_every_calendar_failed<T>(
+    names: impl IntoIterator<Item = impl Display>,
+    results: Vec<Result<T, CalendarError>>,
+) -> Result<Vec<Option<T>>, CalendarError> {
+    let every_one_failed = !results.is_empty() && results.iter().all(Result::is_err);
+    let mut fetched = Vec::new();
+    for (name, result) in names.into_iter().zip(results) {
+        match result {
+            Ok(value) => fetched.push(Some(value)),
+            Err(e) if every_one_failed => return Err(e),
+            Err(e) => {
+                eprintln!(
+                    "could not fetch calendar {}; showing the other calendars: {}",
+                    name, e
+                );
+                fetched.push(None);
+            }
+        }
+    }
+    Ok(fetched)
+}
+
+/// Whether any of the calendars was deleted or unshared, in which case the list of calendars is out of date
+pub(crate) fn any_calendar_gone<T>(results: &[Result<T, CalendarError>]) -> bool {
+    results.iter().any(|result| {
+        matches!(result, Err(e) if matches!(e.status(), Some(StatusCode::NOT_FOUND | StatusCode::GONE)))
+    })
+}
+
+/// Picks calendars out of the ones a user has access to
+#[derive(Debug, Clone)]
+pub enum CalendarSelector {
+    Id(String),
+    Name(Regex),
+}
+
+impl CalendarSelector {
+    pub fn matches(&self, id: &str, name: &str) -> bool {
+        match self {
+            CalendarSelector::Id(selected) => selected == id,
+            CalendarSelector::Name(pattern) => pattern.is_match(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_calendars_by_exact_id_or_name_pattern() {
+        let by_id = CalendarSelector::Id("team@group.calendar.google.com".to_string());
+        assert!(by_id.matches("team@group.calendar.google.com", "Team"));
+        assert!(!by_id.matches("team", "team@group.calendar.google.com"));
+        assert!(!by_id.matches("TEAM@group.calendar.google.com", "Team"));
+
+        let by_name = CalendarSelector::Name(Regex::new("^Holidays in").unwrap());
+        assert!(by_name.matches(
+            "en.usa#holiday@group.v.calendar.google.com",
+            "Holidays in United States"
+        ));
+        assert!(!by_name.matches("Holidays in United States", "US holidays"));
+    }
+
+    #[test]
+    fn cuts_events_down_to_the_most_there_can_be() {
+        let mut events = vec![1, 2];
+        assert!(!reached_max_events(&mut events, 3, "Team"));
+        assert_eq!(vec![1, 2], events);
+
+        events.extend([3, 4]);
+        assert!(reached_max_events(&mut events, 3, "Team"));
+        assert_eq!(vec![1, 2, 3], events);
+    }
 }
@@ -0,0 +1,370 @@
+//! Events from any program that can print them as JSON, for schedules that don't have a standard API.
+//!
+//! The program is run with the time range to list in `CAST_SCHEDULE_START` and `CAST_SCHEDULE_END`,
+//! as RFC 3339 times, and in place of `{start}` and `{end}` in its arguments. It should print:
+//!
+//! ```json
+//! {
+//!   "events": [
+//!     {
+//!       "id": "rotation-42",
+//!       "title": "On call",
+//!       "start": "2023-09-05T09:00:00+02:00",
+//!       "end": "2023-09-05T17:00:00+02:00",
+//!       "description": "Primary",
+//!       "location": "Lab 2",
+//!       "free": false,
+//!       "color": "#d50000",
+//!       "conference_url": "https://meet.google.com/abc-defg-hij"
+//!     },
+//!     { "title": "Lab closed", "start": "2023-09-08", "end": "2023-09-09" }
+//!   ]
+//! }
+//! ```
+//!
+//! Only `title`, `start` and `end` are needed. Events with dates instead of times are all-day events,
+//! and as in iCalendar their `end` date is the day after the last one.
+
+use super::calendar::*;
+use super::errors::*;
+use crate::model::*;
+use async_std::task::{sleep, spawn_blocking};
+use chrono::prelude::*;
+use serde::Deserialize;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_OUTPUT: usize = 1024 * 1024;
+/// How often to check whether the program has exited, once it's closed its output
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A calendar whose events come from running a program
+#[derive(Debug, Clone)]
+pub struct CommandCalendar {
+    program: PathBuf,
+    args: Vec<String>,
+    name: String,
+    timeout: Duration,
+    max_output: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandOutput {
+    events: Vec<CommandEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandEvent {
+    id: Option<String>,
+    title: String,
+    start: CommandTime,
+    end: CommandTime,
+    description: Option<String>,
+    location: Option<String>,
+    #[serde(default)]
+    free: bool,
+    color: Option<String>,
+    conference_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum CommandTime {
+    DateTime(DateTime<FixedOffset>),
+    Date(NaiveDate),
+}
+
+impl CommandTime {
+    /// Dates start at midnight
+    fn local(&self) -> Option<DateTime<Local>> {
+        match self {
+            CommandTime::DateTime(time) => Some(time.with_timezone(&Local)),
+            CommandTime::Date(date) => date
+                .and_time(NaiveTime::MIN)
+                .and_local_timezone(Local)
+                .earliest(),
+        }
+    }
+}
+
+impl CommandCalendar {
+    pub fn new(program: PathBuf) -> CommandCalendar {
+        let name = program
+            .file_name()
+            .map_or_else(|| program.to_string_lossy(), |name| name.to_string_lossy())
+            .into_owned();
+        CommandCalendar {
+            program,
+            args: Vec::new(),
+            name,
+            timeout: DEFAULT_TIMEOUT,
+            max_output: DEFAULT_MAX_OUTPUT,
+        }
+    }
+
+    /// Arguments to run the program with. `{start}` and `{end}` are replaced with the time range to list.
+    pub fn with_args(self, args: Vec<String>) -> CommandCalendar {
+        CommandCalendar { args, ..self }
+    }
+
+    /// What to call the calendar on screen; the program's file name by default
+    pub fn with_name(self, name: &str) -> CommandCalendar {
+        CommandCalendar {
+            name: name.to_string(),
+            ..self
+        }
+    }
+
+    /// Stops the program if it hasn't finished after this long
+    pub fn with_timeout(self, timeout: Duration) -> CommandCalendar {
+        CommandCalendar { timeout, ..self }
+    }
+
+    /// Stops the program if it prints more than this many bytes
+    pub fn with_max_output(self, max_output: usize) -> CommandCalendar {
+        CommandCalendar { max_output, ..self }
+    }
+
+    fn context(&self) -> String {
+        format!("could not get events from {}", self.program.display())
+    }
+
+    /// Runs the program and returns what it printed, if it exits successfully in time
+    async fn run(&self, when: &DefiniteTimeRange<Local>) -> Result<Vec<u8>, CalendarError> {
+        let (start, end) = (when.start.to_rfc3339(), when.end.to_rfc3339());
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.replace("{start}", &start).replace("{end}", &end));
+        let mut child = Command::new(&self.program)
+            .args(args)
+            .env("CAST_SCHEDULE_START", &start)
+            .env("CAST_SCHEDULE_END", &end)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            // Not as the io::Error, since trying again won't make a missing program appear
+            .map_err(|e| CalendarError::fetch(self.context()).with_cause(e.to_string()))?;
+        let deadline = Instant::now() + self.timeout;
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        // One byte more than allowed, to tell output that's exactly the limit from output that's over it
+        let limit = self.max_output as u64 + 1;
+        let reading = spawn_blocking(move || {
+            let mut output = Vec::new();
+            stdout.take(limit).read_to_end(&mut output).map(|_| output)
+        });
+        let output = match async_std::future::timeout(self.timeout, reading).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                return Err(self.stop(child, CalendarError::fetch(self.context()).with_cause(e)))
+            }
+            Err(_) => return Err(self.stop(child, self.timed_out())),
+        };
+        if output.len() > self.max_output {
+            let cause = format!("it printed more than {} bytes", self.max_output);
+            return Err(self.stop(
+                child,
+                CalendarError::fetch(self.context()).with_cause(cause),
+            ));
+        }
+
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => sleep(EXIT_POLL_INTERVAL).await,
+                Ok(None) => return Err(self.stop(child, self.timed_out())),
+                Err(e) => {
+                    return Err(self.stop(child, CalendarError::fetch(self.context()).with_cause(e)))
+                }
+            }
+        };
+        if !status.success() {
+            return Err(CalendarError::fetch(self.context())
+                .with_cause(format!("it exited with {}", status)));
+        }
+        Ok(output)
+    }
+
+    fn timed_out(&self) -> CalendarError {
+        let cause = std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("it didn't finish within {}s", self.timeout.as_secs_f32()),
+        );
+        CalendarError::fetch(self.context()).with_cause(cause)
+    }
+
+    /// Kills the program, so it doesn't keep running after we've given up on it
+    fn stop(&self, mut child: Child, e: CalendarError) -> CalendarError {
+        if let Err(kill_error) = child.kill() {
+            eprintln!("could not stop {}: {}", self.program.display(), kill_error);
+        }
+        let _ = child.wait();
+        e
+    }
+
+    fn parse_event(&self, event: CommandEvent) -> CalendarEvent {
+        let all_day = match (&event.start, &event.end) {
+            (CommandTime::Date(start), CommandTime::Date(end)) => Some(DateRange {
+                start: *start,
+                end: *end,
+            }),
+            _ => None,
+        };
+        let times = IndefiniteTimeRange {
+            start: event.start.local(),
+            end: event.end.local(),
+        };
+        CalendarEvent {
+            id: event.id,
+            summary: event.title,
+            times,
+            all_day,
+            description: event.description,
+            location: event.location,
+            transparency: match event.free {
+                true => Transparency::Transparent,
+                false => Transparency::Opaque,
+            },
+            color: event.color.as_deref().and_then(Color::from_hex),
+            conference_link: event.conference_url.as_deref().map(ConferenceLink::new),
+            source: Some(EventSource {
+                calendar_id: self.program.to_string_lossy().into_owned(),
+                calendar_name: self.name.clone(),
+                background_color: None,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl Calendar for CommandCalendar {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let output = self.run(&when).await?;
+        let parsed: CommandOutput = serde_json::from_slice(&output).map_err(|e| {
+            CalendarError::fetch(format!(
+                "could not parse events from {}",
+                self.program.display()
+            ))
+            .with_cause(e)
+        })?;
+        Ok(parsed
+            .events
+            .into_iter()
+            .map(|event| self.parse_event(event))
+            // Programs that don't look at the range might print events outside it
+            .filter(|event| {
+                event.times.start.is_none_or(|start| start < when.end)
+                    && event.times.end.is_none_or(|end| when.start < end)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    fn sh(script: &str) -> CommandCalendar {
+        CommandCalendar::new(PathBuf::from("sh")).with_args(vec![
+            "-c".to_string(),
+            script.to_string(),
+            "sh".to_string(),
+        ])
+    }
+
+    #[async_std::test]
+    async fn reads_events_for_the_requested_range() {
+        let script = r##"
+            test "$1" = "$CAST_SCHEDULE_START" || exit 1
+            test "$1" = "$2" || exit 2
+            cat <<EOF
+            {"events": [
+                {"id": "shift-1", "title": "On call", "start": "2023-09-05T09:00:00Z", "end": "2023-09-05T17:00:00Z", "free": true},
+                {"title": "Lab closed", "start": "2023-09-05", "end": "2023-09-06", "color": "#d50000"},
+                {"title": "Next week", "start": "2023-09-12T09:00:00Z", "end": "2023-09-12T10:00:00Z"}
+            ]}
+EOF
+        "##;
+        let expected_start = september_5th().start.to_rfc3339();
+        let args = vec!["-c", script, "sh", "{start}", expected_start.as_str()]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let calendar = CommandCalendar::new(PathBuf::from("sh"))
+            .with_args(args)
+            .with_name("Rotations");
+        let events = calendar.get_events_on(september_5th()).await.unwrap();
+
+        let summaries: Vec<&str> = events.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(vec!["On call", "Lab closed"], summaries);
+        assert_eq!(Some("shift-1"), events[0].id.as_deref());
+        assert_eq!(Transparency::Transparent, events[0].transparency);
+        assert_eq!(
+            Some(
+                Utc.with_ymd_and_hms(2023, 9, 5, 9, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Local)
+            ),
+            events[0].times.start
+        );
+        assert_eq!(
+            "Rotations",
+            events[0].source.as_ref().unwrap().calendar_name
+        );
+        assert_eq!(
+            Some(DateRange::single_day(
+                NaiveDate::from_ymd_opt(2023, 9, 5).unwrap()
+            )),
+            events[1].all_day
+        );
+        assert_eq!(Some(Color { r: 213, g: 0, b: 0 }), events[1].color);
+    }
+
+    #[async_std::test]
+    async fn gives_up_on_programs_that_fail_hang_or_print_too_much() {
+        let e = sh("echo oops >&2; exit 3")
+            .get_events_on(september_5th())
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("exited"), "{}", e);
+        assert!(!e.is_retryable());
+
+        let started = Instant::now();
+        let e = sh("sleep 10")
+            .with_timeout(Duration::from_millis(200))
+            .get_events_on(september_5th())
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(e.is_retryable(), "{}", e);
+
+        let e = sh("yes")
+            .with_max_output(1000)
+            .get_events_on(september_5th())
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("more than 1000 bytes"), "{}", e);
+
+        let e = sh("echo not json")
+            .get_events_on(september_5th())
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("could not parse"), "{}", e);
+
+        let e = CommandCalendar::new(PathBuf::from("/nonexistent/schedule"))
+            .get_events_on(september_5th())
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::FetchError, e.kind());
+        assert!(!e.is_retryable());
+    }
+}
@@ -0,0 +1,52 @@
+//! Finds links to join video calls in events that don't say outright that they have one
+
+use crate::model::*;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static URL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https://[^\s<>"']+"#).unwrap());
+
+/// The first Meet, Zoom or Teams link in some text, like an event's location or description.
+/// Other links are left alone, since most of them aren't for joining anything.
+pub fn find_conference_link(text: &str) -> Option<ConferenceLink> {
+    URL_REGEX
+        .find_iter(text)
+        // Punctuation after a link in a sentence isn't part of it
+        .map(|url| ConferenceLink::new(url.as_str().trim_end_matches(['.', ',', ';', ')', ']'])))
+        .find(|link| link.platform != ConferencePlatform::Other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_meeting_links_in_text() {
+        let description = "Agenda: https://docs.example.com/agenda\n\
+            Join Zoom Meeting (https://us02web.zoom.us/j/85521234567?pwd=abc123).\n\
+            Or by phone: +1 669 900 6833";
+        let link = find_conference_link(description).unwrap();
+        assert_eq!(ConferencePlatform::Zoom, link.platform);
+        assert_eq!("https://us02web.zoom.us/j/85521234567?pwd=abc123", link.url);
+
+        let location =
+            "Room 4 / <https://teams.microsoft.com/l/meetup-join/19%3ameeting_abc%40thread.v2/0>";
+        assert_eq!(
+            ConferencePlatform::Teams,
+            find_conference_link(location).unwrap().platform
+        );
+        assert_eq!(
+            ConferencePlatform::Meet,
+            find_conference_link(
+                "meet.google.com/abc-defg-hij, https://meet.google.com/abc-defg-hij"
+            )
+            .unwrap()
+            .platform
+        );
+        assert_eq!(
+            None,
+            find_conference_link("https://example.com/zoom.us/j/1")
+        );
+        assert_eq!(None, find_conference_link("Room 4"));
+    }
+}
@@ -1,5 +1,214 @@
-pub enum CalendarError {
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+type Cause = Box<dyn Error + Send + Sync>;
+
+/// Something that went wrong reading a calendar: what was being done, and what happened
+#[derive(Debug)]
+pub struct CalendarError {
+    kind: ErrorKind,
+    context: String,
+    status: Option<StatusCode>,
+    retry_after: Option<Duration>,
+    cause: Option<Cause>,
+}
+
+/// What kind of thing went wrong, which decides what can be done about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Logging in didn't work, e.g. because the code expired or the password is wrong
     LoginError,
+    /// The events couldn't be fetched, or couldn't be understood
     FetchError,
+    /// The login stopped working, so the user has to log in again
     LoggedOut,
+    /// The server wants fewer requests
+    RateLimited,
+    /// The token from the last incremental sync is too old to use, so everything has to be fetched again
+    SyncExpired,
+}
+
+impl CalendarError {
+    /// `context` says what was being done, like "could not fetch events for work"
+    pub fn new(kind: ErrorKind, context: impl Into<String>) -> CalendarError {
+        CalendarError {
+            kind,
+            context: context.into(),
+            status: None,
+            retry_after: None,
+            cause: None,
+        }
+    }
+
+    pub fn login(context: impl Into<String>) -> CalendarError {
+        CalendarError::new(ErrorKind::LoginError, context)
+    }
+
+    pub fn fetch(context: impl Into<String>) -> CalendarError {
+        CalendarError::new(ErrorKind::FetchError, context)
+    }
+
+    pub fn logged_out(context: impl Into<String>) -> CalendarError {
+        CalendarError::new(ErrorKind::LoggedOut, context)
+    }
+
+    /// An unsuccessful response, with how long it says to wait before trying again
+    pub fn from_response(
+        context: impl Into<String>,
+        response: &reqwest::Response,
+    ) -> CalendarError {
+        let kind = match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => ErrorKind::RateLimited,
+            _ => ErrorKind::FetchError,
+        };
+        CalendarError {
+            retry_after: parse_retry_after(response.headers()),
+            ..CalendarError::new(kind, context).with_status(response.status())
+        }
+    }
+
+    pub fn with_cause(self, cause: impl Into<Cause>) -> CalendarError {
+        CalendarError {
+            cause: Some(cause.into()),
+            ..self
+        }
+    }
+
+    pub fn with_status(self, status: StatusCode) -> CalendarError {
+        CalendarError {
+            status: Some(status),
+            ..self
+        }
+    }
+
+    pub fn with_kind(self, kind: ErrorKind) -> CalendarError {
+        CalendarError { kind, ..self }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The HTTP status of the response that failed, if there was one
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// How long the server asked us to wait before trying again
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Whether the same request might work if it's made again a bit later:
+    /// the network was down, the server was overloaded, or it wanted fewer requests
+    pub fn is_retryable(&self) -> bool {
+        match self.kind {
+            ErrorKind::RateLimited => true,
+            // Asking with the same sync token again won't work either
+            ErrorKind::LoginError | ErrorKind::LoggedOut | ErrorKind::SyncExpired => false,
+            ErrorKind::FetchError => match self.status {
+                Some(status) => status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT,
+                None => self.is_network_error(),
+            },
+        }
+    }
+
+    fn is_network_error(&self) -> bool {
+        let mut cause = self.source();
+        while let Some(e) = cause {
+            if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+                return e.is_connect() || e.is_timeout() || e.is_request();
+            }
+            if e.is::<std::io::Error>() {
+                return true;
+            }
+            cause = e.source();
+        }
+        false
+    }
+}
+
+impl fmt::Display for CalendarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.context)?;
+        if let Some(status) = self.status {
+            write!(f, " (HTTP {})", status)?;
+        }
+        if let Some(cause) = &self.cause {
+            write!(f, ": {}", cause)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for CalendarError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.cause
+            .as_deref()
+            .map(|cause| cause as &(dyn Error + 'static))
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    Some((date - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn classifies_errors_by_what_can_be_done_about_them() {
+        assert!(CalendarError::fetch("could not fetch")
+            .with_status(StatusCode::BAD_GATEWAY)
+            .is_retryable());
+        assert!(!CalendarError::fetch("could not fetch")
+            .with_status(StatusCode::NOT_FOUND)
+            .is_retryable());
+        assert!(!CalendarError::logged_out("token was revoked").is_retryable());
+        assert!(CalendarError::fetch("could not read")
+            .with_cause(std::io::Error::other("reset"))
+            .is_retryable());
+        assert!(!CalendarError::fetch("could not parse")
+            .with_cause("unexpected end of input")
+            .is_retryable());
+
+        let error = CalendarError::fetch("could not fetch events")
+            .with_status(StatusCode::BAD_GATEWAY)
+            .with_cause("upstream died");
+        assert_eq!(
+            "could not fetch events (HTTP 502 Bad Gateway): upstream died",
+            error.to_string()
+        );
+        assert_eq!("upstream died", error.source().unwrap().to_string());
+    }
+
+    #[test]
+    fn reads_retry_after_in_seconds_or_as_a_date() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(Some(Duration::from_secs(120)), parse_retry_after(&headers));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(Some(Duration::ZERO), parse_retry_after(&headers));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(None, parse_retry_after(&headers));
+    }
 }
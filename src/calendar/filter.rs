@@ -0,0 +1,358 @@
+use super::calendar::*;
+use super::errors::*;
+use crate::model::*;
+use chrono::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Something about an event to match a regex against
+#[derive(Debug, Clone)]
+pub enum EventPattern {
+    Title(Regex),
+    /// The calendar's name or ID
+    Calendar(Regex),
+    /// The organizer's email address or name
+    Organizer(Regex),
+}
+
+impl EventPattern {
+    pub fn matches(&self, event: &CalendarEvent) -> bool {
+        match self {
+            EventPattern::Title(regex) => regex.is_match(&event.summary),
+            EventPattern::Calendar(regex) => event.source.as_ref().is_some_and(|source| {
+                regex.is_match(&source.calendar_name) || regex.is_match(&source.calendar_id)
+            }),
+            EventPattern::Organizer(regex) => event.organizer.as_ref().is_some_and(|organizer| {
+                regex.is_match(&organizer.email)
+                    || organizer
+                        .display_name
+                        .as_deref()
+                        .is_some_and(|name| regex.is_match(name))
+            }),
+        }
+    }
+
+    /// Matches a task's title, or the name or ID of the list it's from. Tasks don't have organizers.
+    pub fn matches_task(&self, task: &Task) -> bool {
+        match self {
+            EventPattern::Title(regex) => regex.is_match(&task.summary),
+            EventPattern::Calendar(regex) => task.source.as_ref().is_some_and(|source| {
+                regex.is_match(&source.calendar_name) || regex.is_match(&source.calendar_id)
+            }),
+            EventPattern::Organizer(_) => false,
+        }
+    }
+}
+
+/// One step of an [`EventFilter`], which hides some events
+#[derive(Debug, Clone)]
+pub enum FilterRule {
+    /// Events the calendar's owner said they won't go to
+    Declined,
+    /// Events that don't block time, like FYI holds.
+    /// Working locations and annotations like holidays are free too, but they're shown in the day header rather than as events.
+    Free,
+    Cancelled,
+    /// Events that match the pattern
+    Exclude(EventPattern),
+    /// Events that don't match any of the patterns
+    IncludeOnly(Vec<EventPattern>),
+}
+
+impl FilterRule {
+    fn hides(&self, event: &CalendarEvent) -> bool {
+        match self {
+            FilterRule::Declined => event.self_response() == Some(ResponseStatus::Declined),
+            FilterRule::Free => {
+                event.transparency == Transparency::Transparent
+                    && !matches!(
+                        event.kind,
+                        EventKind::WorkingLocation(_) | EventKind::Annotation
+                    )
+            }
+            FilterRule::Cancelled => event.status == EventStatus::Cancelled,
+            FilterRule::Exclude(pattern) => pattern.matches(event),
+            FilterRule::IncludeOnly(patterns) => {
+                !patterns.iter().any(|pattern| pattern.matches(event))
+            }
+        }
+    }
+
+    /// Tasks that are done are never listed, so only the patterns apply
+    fn hides_task(&self, task: &Task) -> bool {
+        match self {
+            FilterRule::Declined | FilterRule::Free | FilterRule::Cancelled => false,
+            FilterRule::Exclude(pattern) => pattern.matches_task(task),
+            FilterRule::IncludeOnly(patterns) => {
+                !patterns.iter().any(|pattern| pattern.matches_task(task))
+            }
+        }
+    }
+}
+
+/// Decides which events are worth showing. An event is shown unless one of the rules hides it.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    rules: Vec<FilterRule>,
+}
+
+impl EventFilter {
+    /// Shows everything, until rules are added
+    pub fn new() -> EventFilter {
+        EventFilter::default()
+    }
+
+    pub fn with_rule(mut self, rule: FilterRule) -> EventFilter {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn shows(&self, event: &CalendarEvent) -> bool {
+        !self.rules.iter().any(|rule| rule.hides(event))
+    }
+
+    pub fn apply(&self, events: Vec<CalendarEvent>) -> Vec<CalendarEvent> {
+        events
+            .into_iter()
+            .filter(|event| self.shows(event))
+            .collect()
+    }
+
+    pub fn shows_task(&self, task: &Task) -> bool {
+        !self.rules.iter().any(|rule| rule.hides_task(task))
+    }
+
+    pub fn apply_to_tasks(&self, tasks: Vec<Task>) -> Vec<Task> {
+        tasks
+            .into_iter()
+            .filter(|task| self.shows_task(task))
+            .collect()
+    }
+}
+
+/// A calendar with only the events a filter shows
+pub struct FilteredCalendar<C: Calendar> {
+    calendar: C,
+    filter: EventFilter,
+}
+
+impl<C: Calendar> FilteredCalendar<C> {
+    pub fn new(calendar: C, filter: EventFilter) -> FilteredCalendar<C> {
+        FilteredCalendar { calendar, filter }
+    }
+}
+
+impl<C: Calendar> Calendar for FilteredCalendar<C> {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        Ok(self.filter.apply(self.calendar.get_events_on(when).await?))
+    }
+
+    async fn get_cached_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<CachedEvents, CalendarError> {
+        let cached = self.calendar.get_cached_events_on(when).await?;
+        Ok(CachedEvents {
+            events: self.filter.apply(cached.events),
+            ..cached
+        })
+    }
+
+    /// Events that were changed so that they're hidden now (e.g. declined) count as removed
+    async fn get_changes(
+        &self,
+        when: &DefiniteTimeRange<Local>,
+        sync_tokens: &HashMap<String, String>,
+    ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+        let changes = match self.calendar.get_changes(when, sync_tokens).await? {
+            Ok(changes) => changes,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Ok(changes
+            .into_iter()
+            .map(|mut changes| {
+                let (shown, hidden): (Vec<_>, Vec<_>) = changes
+                    .changed
+                    .into_iter()
+                    .partition(|event| self.filter.shows(event));
+                changes.changed = shown;
+                changes
+                    .removed
+                    .extend(hidden.into_iter().filter_map(|event| event.id));
+                changes
+            })
+            .collect()))
+    }
+
+    /// Hidden events still take up time, so this asks the calendar itself
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        self.calendar.get_busy_times(when).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(summary: &str) -> CalendarEvent {
+        CalendarEvent {
+            summary: summary.to_string(),
+            source: Some(EventSource {
+                calendar_id: "team@example.com".to_string(),
+                calendar_name: "Team".to_string(),
+                background_color: None,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn regex(pattern: &str) -> Regex {
+        Regex::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn hides_declined_free_and_cancelled_events() {
+        let declined = CalendarEvent {
+            attendees: vec![Attendee {
+                response_status: ResponseStatus::Declined,
+                is_self: true,
+                ..Default::default()
+            }],
+            ..event("Declined")
+        };
+        let someone_else_declined = CalendarEvent {
+            attendees: vec![Attendee {
+                response_status: ResponseStatus::Declined,
+                ..Default::default()
+            }],
+            ..event("Someone else declined")
+        };
+        let free = CalendarEvent {
+            transparency: Transparency::Transparent,
+            ..event("Free")
+        };
+        let cancelled = CalendarEvent {
+            status: EventStatus::Cancelled,
+            ..event("Cancelled")
+        };
+        let home = CalendarEvent {
+            transparency: Transparency::Transparent,
+            kind: EventKind::WorkingLocation(WorkingLocation::Home),
+            ..event("Home")
+        };
+        let holiday = CalendarEvent {
+            transparency: Transparency::Transparent,
+            kind: EventKind::Annotation,
+            ..event("Labor Day")
+        };
+        let events = vec![
+            declined,
+            someone_else_declined,
+            free,
+            cancelled,
+            home,
+            holiday,
+            event("Standup"),
+        ];
+
+        let filter = EventFilter::new()
+            .with_rule(FilterRule::Declined)
+            .with_rule(FilterRule::Free)
+            .with_rule(FilterRule::Cancelled);
+        let shown: Vec<String> = filter
+            .apply(events.clone())
+            .into_iter()
+            .map(|e| e.summary)
+            .collect();
+        assert_eq!(
+            vec!["Someone else declined", "Home", "Labor Day", "Standup"],
+            shown
+        );
+
+        assert_eq!(7, EventFilter::new().apply(events).len());
+    }
+
+    #[test]
+    fn includes_and_excludes_by_title_calendar_and_organizer() {
+        let newsletter = CalendarEvent {
+            organizer: Some(Person {
+                email: "noreply@example.com".to_string(),
+                display_name: None,
+            }),
+            ..event("Webinar")
+        };
+        let personal = CalendarEvent {
+            source: None,
+            ..event("Dentist")
+        };
+        let events = vec![newsletter, personal, event("Standup"), event("Lunch")];
+
+        let filter = EventFilter::new()
+            .with_rule(FilterRule::Exclude(EventPattern::Organizer(regex(
+                "^noreply@",
+            ))))
+            .with_rule(FilterRule::Exclude(EventPattern::Title(regex(
+                "(?i)^lunch$",
+            ))));
+        let shown: Vec<String> = filter
+            .apply(events.clone())
+            .into_iter()
+            .map(|e| e.summary)
+            .collect();
+        assert_eq!(vec!["Dentist", "Standup"], shown);
+
+        let filter =
+            EventFilter::new().with_rule(FilterRule::IncludeOnly(vec![EventPattern::Calendar(
+                regex("^team@"),
+            )]));
+        let shown: Vec<String> = filter
+            .apply(events.clone())
+            .into_iter()
+            .map(|e| e.summary)
+            .collect();
+        assert_eq!(vec!["Webinar", "Standup", "Lunch"], shown);
+
+        let filter = EventFilter::new().with_rule(FilterRule::IncludeOnly(vec![
+            EventPattern::Calendar(regex("^Team$")),
+            EventPattern::Title(regex("Dentist")),
+        ]));
+        assert_eq!(4, filter.apply(events).len());
+    }
+
+    #[test]
+    fn filters_tasks_by_title_and_list() {
+        let task = |summary: &str, list: &str| Task {
+            summary: summary.to_string(),
+            source: Some(EventSource {
+                calendar_name: list.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let tasks = vec![
+            task("Send the invoice", "Work"),
+            task("Water the plants", "Home"),
+            task("Review PR", "Work"),
+        ];
+
+        let filter = EventFilter::new()
+            .with_rule(FilterRule::Free)
+            .with_rule(FilterRule::IncludeOnly(vec![EventPattern::Calendar(
+                regex("^Work$"),
+            )]))
+            .with_rule(FilterRule::Exclude(EventPattern::Title(regex("invoice"))));
+        let shown: Vec<String> = filter
+            .apply_to_tasks(tasks)
+            .into_iter()
+            .map(|task| task.summary)
+            .collect();
+        assert_eq!(vec!["Review PR"], shown);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::calendar::oauth::OAuthSession;
+use crate::calendar::throttle::RequestBudget;
+use crate::calendar::{CalendarError, ErrorKind};
+use crate::model::*;
+use chrono::Local;
+use google_calendar::types::{CalendarListEntry, Event};
+use once_cell::sync::Lazy;
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const GOOGLE_CALENDAR_API: &str = "https://www.googleapis.com/calendar/v3/";
+/// The bucket for requests that aren't about one calendar in particular
+const CALENDAR_LIST_BUCKET: &str = "calendarList";
+const FREE_BUSY_BUCKET: &str = "freeBusy";
+const COLORS_BUCKET: &str = "colors";
+
+/// Google's quotas are per user and per project, not per client, so every client shares one budget.
+/// Logging in and refreshing tokens don't count against the Calendar API's quota, so they go around it:
+/// polling already waits as long as the token endpoint asks, and a session refreshes at most once an hour.
+static GOOGLE_BUDGET: Lazy<Arc<RequestBudget>> =
+    Lazy::new(|| Arc::new(RequestBudget::new(5, Duration::from_secs(1))));
+
+/// A thin client for the Calendar REST API.
+///
+/// The generated `google_calendar` client only returns the `items` of list responses,
+/// dropping `nextPageToken` and the calendar's `timeZone`, so we make requests ourselves
+/// and only borrow its types.
+pub struct GoogleApi {
+    http: reqwest::Client,
+    base_url: Url,
+    session: OAuthSession,
+    budget: Arc<RequestBudget>,
+}
+
+/// One page of an `events.list` response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsPage {
+    #[serde(default)]
+    pub items: Vec<EventItem>,
+    pub next_page_token: Option<String>,
+    /// The time zone of the calendar the events are from
+    pub time_zone: Option<String>,
+    /// Only on the last page, for when the next request should only get what's changed since
+    pub next_sync_token: Option<String>,
+}
+
+/// An event from `events.list`, with the fields that are newer than the generated `Event`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventItem {
+    #[serde(flatten)]
+    pub event: Event,
+    /// `default`, `outOfOffice`, `focusTime` or `workingLocation`
+    #[serde(default)]
+    pub event_type: String,
+    /// Only on `workingLocation` events
+    pub working_location_properties: Option<WorkingLocationProperties>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkingLocationProperties {
+    /// `homeOffice`, `officeLocation` or `customLocation`
+    #[serde(rename = "type", default)]
+    pub location_type: String,
+    pub office_location: Option<LocationLabel>,
+    pub custom_location: Option<LocationLabel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LocationLabel {
+    #[serde(default)]
+    pub label: String,
+}
+
+/// Which events to list
+#[derive(Debug, Clone, Copy)]
+pub enum EventsQuery<'a> {
+    /// Everything that overlaps a time range, in order of start time
+    Range(&'a DefiniteTimeRange<Local>),
+    /// Everything that overlaps a time range, ending with a sync token
+    StartSync(&'a DefiniteTimeRange<Local>),
+    /// What changed since the sync that gave this token, including deleted events
+    Changes(&'a str),
+}
+
+/// One page of a `calendarList.list` response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalendarListPage {
+    #[serde(default)]
+    pub items: Vec<CalendarListEntry>,
+    pub next_page_token: Option<String>,
+}
+
+/// A `freeBusy.query` response
+#[derive(Debug, Deserialize)]
+pub struct FreeBusyResponse {
+    #[serde(default)]
+    pub calendars: HashMap<String, FreeBusyCalendar>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FreeBusyCalendar {
+    #[serde(default)]
+    pub busy: Vec<DefiniteTimeRange<Local>>,
+    /// Set instead of `busy` for calendars that couldn't be read, e.g. because they weren't found
+    #[serde(default)]
+    pub errors: Vec<FreeBusyError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FreeBusyError {
+    pub reason: String,
+}
+
+/// A `colors.get` response: what each `colorId` stands for
+#[derive(Debug, Default, Deserialize)]
+pub struct ColorPalette {
+    #[serde(default)]
+    pub calendar: HashMap<String, ColorDefinition>,
+    #[serde(default)]
+    pub event: HashMap<String, ColorDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ColorDefinition {
+    /// Like `#a4bdfc`
+    pub background: String,
+    /// The color of text drawn on the background
+    pub foreground: String,
+}
+
+impl ColorPalette {
+    pub fn event_color(&self, color_id: &str) -> Option<Color> {
+        Color::from_hex(&self.event.get(color_id)?.background)
+    }
+
+    pub fn calendar_color(&self, color_id: &str) -> Option<Color> {
+        Color::from_hex(&self.calendar.get(color_id)?.background)
+    }
+}
+
+impl GoogleApi {
+    pub fn new(session: OAuthSession) -> GoogleApi {
+        GoogleApi {
+            http: reqwest::Client::new(),
+            base_url: Url::parse(GOOGLE_CALENDAR_API).unwrap(),
+            session,
+            budget: GOOGLE_BUDGET.clone(),
+        }
+    }
+
+    /// Uses a budget other than the one shared by every Google client
+    pub fn with_budget(self, budget: Arc<RequestBudget>) -> GoogleApi {
+        GoogleApi { budget, ..self }
+    }
+
+    pub fn with_base_url(self, base_url: Url) -> GoogleApi {
+        GoogleApi { base_url, ..self }
+    }
+
+    fn url(&self, path: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("API base URL must be a hierarchical URL")
+            .pop_if_empty()
+            .extend(path);
+        url
+    }
+
+    pub async fn list_events_page(
+        &self,
+        calendar_id: &str,
+        query: EventsQuery<'_>,
+        page_token: Option<&str>,
+        max_results: usize,
+    ) -> Result<EventsPage, CalendarError> {
+        let mut url = self.url(&["calendars", calendar_id, "events"]);
+        url.query_pairs_mut()
+            .append_pair("maxResults", &max_results.to_string())
+            // expand repeating meetings into single events
+            .append_pair("singleEvents", "true");
+        match query {
+            // Google won't sort results it's going to give a sync token for
+            EventsQuery::Range(when) => {
+                url.query_pairs_mut()
+                    .append_pair("orderBy", "startTime")
+                    .append_pair("timeMin", &when.start.to_rfc3339())
+                    .append_pair("timeMax", &when.end.to_rfc3339());
+            }
+            EventsQuery::StartSync(when) => {
+                url.query_pairs_mut()
+                    .append_pair("timeMin", &when.start.to_rfc3339())
+                    .append_pair("timeMax", &when.end.to_rfc3339());
+            }
+            EventsQuery::Changes(sync_token) => {
+                url.query_pairs_mut().append_pair("syncToken", sync_token);
+            }
+        }
+        if let Some(page_token) = page_token {
+            url.query_pairs_mut().append_pair("pageToken", page_token);
+        }
+        self.get(calendar_id, url).await
+    }
+
+    pub async fn list_calendars_page(
+        &self,
+        page_token: Option<&str>,
+    ) -> Result<CalendarListPage, CalendarError> {
+        let mut url = self.url(&["users", "me", "calendarList"]);
+        if let Some(page_token) = page_token {
+            url.query_pairs_mut().append_pair("pageToken", page_token);
+        }
+        self.get(CALENDAR_LIST_BUCKET, url).await
+    }
+
+    /// The colors that calendars' and events' `colorId`s stand for
+    pub async fn get_colors(&self) -> Result<ColorPalette, CalendarError> {
+        self.get(COLORS_BUCKET, self.url(&["colors"])).await
+    }
+
+    /// When each calendar is busy, without saying what with
+    pub async fn query_free_busy(
+        &self,
+        calendar_ids: &[&str],
+        when: &DefiniteTimeRange<Local>,
+    ) -> Result<FreeBusyResponse, CalendarError> {
+        let body = serde_json::json!({
+            "timeMin": when.start.to_rfc3339(),
+            "timeMax": when.end.to_rfc3339(),
+            "items": calendar_ids.iter().map(|id| serde_json::json!({ "id": id })).collect::<Vec<_>>(),
+        });
+        let url = self.url(&["freeBusy"]);
+        self.budget
+            .run(FREE_BUSY_BUCKET, || {
+                self.send_once(Method::POST, &url, Some(&body))
+            })
+            .await
+    }
+
+    /// Waits for budget in `bucket` before fetching, and retries if Google is busy or wants fewer requests
+    async fn get<T: DeserializeOwned>(&self, bucket: &str, url: Url) -> Result<T, CalendarError> {
+        self.budget
+            .run(bucket, || self.send_once(Method::GET, &url, None))
+            .await
+    }
+
+    async fn send_once<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: &Url,
+        body: Option<&serde_json::Value>,
+    ) -> Result<T, CalendarError> {
+        let access_token = self.session.access_token().await?;
+        let mut response = self.send(method.clone(), url, body, &access_token).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            // The token was revoked or expired early, so a fresh one might still work
+            let access_token = self
+                .session
+                .access_token_after_rejection(&access_token)
+                .await?;
+            response = self.send(method, url, body, &access_token).await?;
+        }
+
+        let context = format!("could not fetch {} from google calendar", url.path());
+        match response.status() {
+            StatusCode::OK => {}
+            status @ StatusCode::UNAUTHORIZED => {
+                return Err(CalendarError::logged_out(context).with_status(status))
+            }
+            status @ StatusCode::GONE => {
+                return Err(CalendarError::new(ErrorKind::SyncExpired, context).with_status(status))
+            }
+            _ => {
+                let error = CalendarError::from_response(context, &response);
+                let body = response.text().await.unwrap_or_default();
+                return Err(match is_rate_limit(&body) {
+                    true => error.with_kind(ErrorKind::RateLimited),
+                    false => error,
+                });
+            }
+        }
+
+        response.json().await.map_err(|e| {
+            CalendarError::fetch(format!(
+                "could not parse {} from google calendar",
+                url.path()
+            ))
+            .with_cause(e)
+        })
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: &Url,
+        body: Option<&serde_json::Value>,
+        access_token: &str,
+    ) -> Result<reqwest::Response, CalendarError> {
+        let request = self
+            .http
+            .request(method, url.clone())
+            .bearer_auth(access_token);
+        let request = match body {
+            Some(body) => request.json(body),
+            None => request,
+        };
+        request.send().await.map_err(|e| {
+            CalendarError::fetch(format!(
+                "could not fetch {} from google calendar",
+                url.path()
+            ))
+            .with_cause(e)
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    errors: Vec<ErrorDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    #[serde(default)]
+    reason: String,
+}
+
+/// Google says it's rate limiting with a 403 as often as with a 429, and only the body tells them apart from real 403s
+fn is_rate_limit(body: &str) -> bool {
+    serde_json::from_str::<ErrorResponse>(body).is_ok_and(|response| {
+        response
+            .error
+            .errors
+            .iter()
+            .any(|e| e.reason == "rateLimitExceeded" || e.reason == "userRateLimitExceeded")
+    })
+}
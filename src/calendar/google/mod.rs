@@ -0,0 +1,1261 @@
+use super::calendar::*;
+use super::conference::find_conference_link;
+use super::errors::*;
+use crate::model::*;
+use async_std::sync::Mutex;
+use chrono::prelude::*;
+use chrono::Duration;
+use chrono_tz::Tz;
+use futures_util::future::join_all;
+use google_calendar::types::{CalendarListEntry, Event, EventAttendee, EventDateTime, Organizer};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+mod api;
+pub use api::*;
+
+mod provider;
+pub use provider::*;
+
+/// How many events to ask for in each page of results; Google allows up to 2500
+const PAGE_SIZE: usize = 250;
+
+pub struct GoogleCalendar {
+    api: GoogleApi,
+    max_events: usize,
+    selectors: Vec<CalendarSelector>,
+    /// What color IDs stand for, which never changes, so it's only fetched once
+    palette: Mutex<Option<Arc<ColorPalette>>>,
+    /// The calendars the selectors picked, and when their list was fetched
+    selected: Mutex<Option<(Instant, Vec<CalendarListEntry>)>>,
+}
+
+impl GoogleCalendar {
+    pub fn new(api: GoogleApi) -> GoogleCalendar {
+        GoogleCalendar {
+            api,
+            max_events: DEFAULT_MAX_EVENTS,
+            selectors: Vec::new(),
+            palette: Mutex::new(None),
+            selected: Mutex::new(None),
+        }
+    }
+
+    /// The most events to fetch from one calendar for one request; see [`reached_max_events`]
+    pub fn with_max_events(self, max_events: usize) -> GoogleCalendar {
+        GoogleCalendar { max_events, ..self }
+    }
+
+    /// Which of the user's calendars to show. With no selectors, only the primary calendar is shown.
+    pub fn with_calendars(self, selectors: Vec<CalendarSelector>) -> GoogleCalendar {
+        GoogleCalendar { selectors, ..self }
+    }
+
+    pub async fn list_calendars(&self) -> Result<Vec<CalendarListEntry>, CalendarError> {
+        let mut calendars = Vec::new();
+        let mut page_token = None;
+        loop {
+            let page = self.api.list_calendars_page(page_token.as_deref()).await?;
+            calendars.extend(page.items);
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                return Ok(calendars);
+            }
+        }
+    }
+
+    /// The calendars to show, from a list that's only fetched again once it's an hour old
+    /// or one of its calendars has gone away
+    async fn selected_calendars(&self) -> Result<Vec<CalendarListEntry>, CalendarError> {
+        let mut selected = self.selected.lock().await;
+        if let Some((fetched_at, calendars)) = selected.as_ref() {
+            if fetched_at.elapsed() < CALENDAR_LIST_MAX_AGE {
+                return Ok(calendars.clone());
+            }
+        }
+        let calendars: Vec<CalendarListEntry> = self
+            .list_calendars()
+            .await?
+            .into_iter()
+            .filter(|calendar| {
+                if self.selectors.is_empty() {
+                    return calendar.primary;
+                }
+                self.selectors
+                    .iter()
+                    .any(|selector| selector.matches(&calendar.id, calendar_name(calendar)))
+            })
+            .collect();
+        *selected = Some((Instant::now(), calendars.clone()));
+        Ok(calendars)
+    }
+
+    /// Fetches the list of calendars again next time if any of them was deleted or unshared
+    async fn forget_gone_calendars<T>(&self, results: &[Result<T, CalendarError>]) {
+        if any_calendar_gone(results) {
+            *self.selected.lock().await = None;
+        }
+    }
+
+    /// Without the palette events are still shown, just in their calendar's color
+    async fn palette(&self) -> Arc<ColorPalette> {
+        let mut palette = self.palette.lock().await;
+        if let Some(palette) = palette.as_ref() {
+            return palette.clone();
+        }
+        match self.api.get_colors().await {
+            Ok(fetched) => palette.insert(Arc::new(fetched)).clone(),
+            Err(e) => {
+                eprintln!("could not fetch google calendar's colors: {}", e);
+                Arc::new(ColorPalette::default())
+            }
+        }
+    }
+
+    async fn get_calendar_events(
+        &self,
+        calendar: &CalendarListEntry,
+        date: &DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let fetched = self
+            .fetch_events(&calendar.id, EventsQuery::Range(date))
+            .await?;
+        let time_zone = fetched
+            .time_zone
+            .and_then(|tz| parse_time_zone(&tz, &calendar.id));
+        let palette = self.palette().await;
+        let source = event_source(calendar, &palette);
+        Ok(fetched
+            .events
+            .into_iter()
+            .map(|item| CalendarEvent {
+                source: Some(source.clone()),
+                ..parse_event(item, time_zone, &palette)
+            })
+            .collect())
+    }
+
+    /// Everything in `date` if there's no sync token, or else what's changed since that sync
+    async fn sync_calendar(
+        &self,
+        calendar: &CalendarListEntry,
+        date: &DefiniteTimeRange<Local>,
+        sync_token: Option<&String>,
+    ) -> Result<CalendarChanges, CalendarError> {
+        let palette = self.palette().await;
+        if let Some(sync_token) = sync_token {
+            match self
+                .fetch_events(&calendar.id, EventsQuery::Changes(sync_token))
+                .await
+            {
+                Ok(fetched) => return Ok(changes(calendar, fetched, false, &palette)),
+                Err(e) if e.kind() == ErrorKind::SyncExpired => {
+                    eprintln!(
+                        "sync token for calendar {} expired; fetching everything again",
+                        calendar.id
+                    )
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let fetched = self
+            .fetch_events(&calendar.id, EventsQuery::StartSync(date))
+            .await?;
+        Ok(changes(calendar, fetched, true, &palette))
+    }
+
+    /// Follows pages of results until the last one, or until there are too many events
+    async fn fetch_events(
+        &self,
+        calendar_id: &str,
+        query: EventsQuery<'_>,
+    ) -> Result<FetchedEvents, CalendarError> {
+        let mut fetched = FetchedEvents::default();
+        let mut page_token = None;
+        loop {
+            let page = self
+                .api
+                .list_events_page(calendar_id, query, page_token.as_deref(), PAGE_SIZE)
+                .await?;
+            fetched.time_zone = fetched.time_zone.or(page.time_zone);
+            fetched.sync_token = page.next_sync_token;
+            fetched.events.extend(page.items);
+
+            page_token = page.next_page_token;
+            if page_token.is_none()
+                || reached_max_events(&mut fetched.events, self.max_events, calendar_id)
+            {
+                return Ok(fetched);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct FetchedEvents {
+    events: Vec<EventItem>,
+    time_zone: Option<String>,
+    sync_token: Option<String>,
+}
+
+impl Calendar for GoogleCalendar {
+    /// Only fails if every selected calendar did; otherwise the ones that failed are left out
+    async fn get_events_on(
+        &self,
+        date: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let calendars = self.selected_calendars().await?;
+        let results = join_all(
+            calendars
+                .iter()
+                .map(|calendar| self.get_calendar_events(calendar, &date)),
+        )
+        .await;
+        self.forget_gone_calendars(&results).await;
+        Ok(
+            unless_every_calendar_failed(calendars.iter().map(calendar_name), results)?
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect(),
+        )
+    }
+
+    async fn get_changes(
+        &self,
+        date: &DefiniteTimeRange<Local>,
+        sync_tokens: &HashMap<String, String>,
+    ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+        let calendars = match self.selected_calendars().await {
+            Ok(calendars) => calendars,
+            Err(e) => return Some(Err(e)),
+        };
+        let results = join_all(
+            calendars
+                .iter()
+                .map(|calendar| self.sync_calendar(calendar, date, sync_tokens.get(&calendar.id))),
+        )
+        .await;
+        self.forget_gone_calendars(&results).await;
+        let changes =
+            match unless_every_calendar_failed(calendars.iter().map(calendar_name), results) {
+                Ok(changes) => changes,
+                Err(e) => return Some(Err(e)),
+            };
+        Some(Ok(calendars
+            .iter()
+            .zip(changes)
+            .map(|(calendar, changes)| changes.unwrap_or_else(|| unchanged(calendar, sync_tokens)))
+            .collect()))
+    }
+
+    /// Google says when each calendar is busy without sending the events, which takes one request for all of them
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        let calendars = self.selected_calendars().await?;
+        let ids: Vec<&str> = calendars
+            .iter()
+            .map(|calendar| calendar.id.as_str())
+            .collect();
+        let response = self.api.query_free_busy(&ids, &when).await?;
+
+        let answered = response.calendars.len();
+        let mut busy = Vec::new();
+        let mut failures = Vec::new();
+        if response
+            .calendars
+            .values()
+            .flat_map(|calendar| &calendar.errors)
+            .any(|error| error.reason == "notFound")
+        {
+            *self.selected.lock().await = None;
+        }
+        for (id, calendar) in response.calendars {
+            match calendar.errors.first() {
+                Some(error) => failures.push(
+                    CalendarError::fetch(format!(
+                        "could not get free/busy times for calendar {}",
+                        id
+                    ))
+                    .with_cause(error.reason.clone()),
+                ),
+                None => busy.extend(calendar.busy),
+            }
+        }
+        if !failures.is_empty() && failures.len() == answered {
+            return Err(failures.remove(0));
+        }
+        for e in &failures {
+            eprintln!("{}; using the other calendars", e);
+        }
+        Ok(BusyTimes::new(busy))
+    }
+}
+
+/// Keeps what was last fetched from a calendar that couldn't be synced, and syncs from the same point next time
+fn unchanged(
+    calendar: &CalendarListEntry,
+    sync_tokens: &HashMap<String, String>,
+) -> CalendarChanges {
+    CalendarChanges {
+        calendar_id: calendar.id.clone(),
+        sync_token: sync_tokens.get(&calendar.id).cloned(),
+        ..Default::default()
+    }
+}
+
+fn event_source(calendar: &CalendarListEntry, palette: &ColorPalette) -> EventSource {
+    EventSource {
+        calendar_id: calendar.id.clone(),
+        calendar_name: calendar_name(calendar).to_string(),
+        background_color: Color::from_hex(&calendar.background_color)
+            .or_else(|| palette.calendar_color(&calendar.color_id)),
+        ..Default::default()
+    }
+}
+
+/// Deleted and cancelled events only come back when asking for changes, so they can be removed
+fn changes(
+    calendar: &CalendarListEntry,
+    fetched: FetchedEvents,
+    full: bool,
+    palette: &ColorPalette,
+) -> CalendarChanges {
+    let time_zone = fetched
+        .time_zone
+        .and_then(|tz| parse_time_zone(&tz, &calendar.id));
+    let source = event_source(calendar, palette);
+    let (cancelled, events): (Vec<EventItem>, Vec<EventItem>) = fetched
+        .events
+        .into_iter()
+        .partition(|item| item.event.status == "cancelled");
+    CalendarChanges {
+        calendar_id: calendar.id.clone(),
+        full,
+        changed: events
+            .into_iter()
+            .map(|item| CalendarEvent {
+                source: Some(source.clone()),
+                ..parse_event(item, time_zone, palette)
+            })
+            .collect(),
+        removed: cancelled.into_iter().map(|item| item.event.id).collect(),
+        sync_token: fetched.sync_token,
+    }
+}
+
+/// The name the user gave the calendar, falling back to the owner's name for it
+fn calendar_name(calendar: &CalendarListEntry) -> &str {
+    if calendar.summary_override.is_empty() {
+        &calendar.summary
+    } else {
+        &calendar.summary_override
+    }
+}
+
+/// All-day events only have dates, which are midnight-to-midnight in the calendar's own time zone
+fn parse_time_zone(time_zone: &str, calendar_id: &str) -> Option<Tz> {
+    match time_zone.parse() {
+        Ok(tz) => Some(tz),
+        Err(e) => {
+            eprintln!(
+                "unknown time zone {:?} for calendar {}: {}",
+                time_zone, calendar_id, e
+            );
+            None
+        }
+    }
+}
+
+fn parse_event(item: EventItem, time_zone: Option<Tz>, palette: &ColorPalette) -> CalendarEvent {
+    let kind = parse_kind(&item.event_type, item.working_location_properties);
+    let g_event = item.event;
+    let all_day = parse_all_day(g_event.start.as_ref(), g_event.end.as_ref());
+    CalendarEvent {
+        id: non_empty(g_event.id.clone()),
+        conference_link: conference_link(&g_event),
+        times: match &all_day {
+            Some(dates) => IndefiniteTimeRange {
+                start: start_of_date(dates.start, time_zone),
+                end: start_of_date(dates.end, time_zone),
+            },
+            None => IndefiniteTimeRange {
+                start: g_event
+                    .start
+                    .and_then(|t| t.date_time)
+                    .map(|t| t.with_timezone(&Local)),
+                end: g_event
+                    .end
+                    .and_then(|t| t.date_time)
+                    .map(|t| t.with_timezone(&Local)),
+            },
+        },
+        all_day,
+        summary: g_event.summary,
+        description: non_empty(g_event.description),
+        location: non_empty(g_event.location),
+        organizer: g_event.organizer.map(parse_organizer),
+        attendees: g_event.attendees.into_iter().map(parse_attendee).collect(),
+        visibility: parse_visibility(&g_event.visibility),
+        transparency: parse_transparency(&g_event.transparency),
+        status: parse_status(&g_event.status),
+        kind,
+        color: palette.event_color(&g_event.color_id),
+        color_id: non_empty(g_event.color_id),
+        html_link: non_empty(g_event.html_link),
+        ical_uid: non_empty(g_event.i_cal_uid),
+        source: None,
+    }
+}
+
+/// All-day events have a `date` instead of a `date_time`, with an exclusive end date
+fn parse_all_day(start: Option<&EventDateTime>, end: Option<&EventDateTime>) -> Option<DateRange> {
+    let start = start?.date?;
+    let end = end
+        .and_then(|t| t.date)
+        .unwrap_or(start + Duration::days(1));
+    Some(DateRange { start, end })
+}
+
+fn start_of_date(date: NaiveDate, time_zone: Option<Tz>) -> Option<DateTime<Local>> {
+    let midnight = date.and_hms_opt(0, 0, 0)?;
+    match time_zone {
+        Some(tz) => tz
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|t| t.with_timezone(&Local)),
+        None => Local.from_local_datetime(&midnight).earliest(),
+    }
+}
+
+/// Google leaves fields it has no value for as empty strings
+fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+fn parse_organizer(organizer: Organizer) -> Person {
+    Person {
+        email: organizer.email,
+        display_name: non_empty(organizer.display_name),
+    }
+}
+
+fn parse_attendee(attendee: EventAttendee) -> Attendee {
+    Attendee {
+        response_status: match attendee.response_status.as_str() {
+            "declined" => ResponseStatus::Declined,
+            "tentative" => ResponseStatus::Tentative,
+            "accepted" => ResponseStatus::Accepted,
+            _ => ResponseStatus::NeedsAction,
+        },
+        is_self: attendee.self_,
+        optional: attendee.optional,
+        person: Person {
+            email: attendee.email,
+            display_name: non_empty(attendee.display_name),
+        },
+    }
+}
+
+fn parse_visibility(visibility: &str) -> Visibility {
+    match visibility {
+        "public" => Visibility::Public,
+        "private" => Visibility::Private,
+        "confidential" => Visibility::Confidential,
+        _ => Visibility::Default,
+    }
+}
+
+fn parse_transparency(transparency: &str) -> Transparency {
+    match transparency {
+        "transparent" => Transparency::Transparent,
+        _ => Transparency::Opaque,
+    }
+}
+
+fn parse_status(status: &str) -> EventStatus {
+    match status {
+        "cancelled" => EventStatus::Cancelled,
+        "tentative" => EventStatus::Tentative,
+        _ => EventStatus::Confirmed,
+    }
+}
+
+fn parse_kind(event_type: &str, working_location: Option<WorkingLocationProperties>) -> EventKind {
+    match event_type {
+        "outOfOffice" => EventKind::OutOfOffice,
+        "focusTime" => EventKind::FocusTime,
+        "workingLocation" => match working_location {
+            Some(properties) => EventKind::WorkingLocation(parse_working_location(properties)),
+            None => EventKind::Default,
+        },
+        _ => EventKind::Default,
+    }
+}
+
+fn parse_working_location(properties: WorkingLocationProperties) -> WorkingLocation {
+    let label =
+        |location: Option<LocationLabel>| location.and_then(|location| non_empty(location.label));
+    match properties.location_type.as_str() {
+        "homeOffice" => WorkingLocation::Home,
+        "officeLocation" => WorkingLocation::Office(label(properties.office_location)),
+        _ => WorkingLocation::Custom(
+            label(properties.custom_location).unwrap_or_else(|| ELSEWHERE.to_string()),
+        ),
+    }
+}
+
+/// Prefers the Meet link, then the first video entry point of any other conference solution,
+/// then a link in the location or description
+fn conference_link(g_event: &Event) -> Option<ConferenceLink> {
+    let entry_points = g_event
+        .conference_data
+        .as_ref()
+        .map(|data| data.entry_points.as_slice())
+        .unwrap_or_default();
+    let video = entry_points
+        .iter()
+        .find(|entry| entry.entry_point_type == "video");
+    let url = non_empty(g_event.hangout_link.clone())
+        .or_else(|| video.and_then(|entry| non_empty(entry.uri.clone())));
+    let Some(url) = url else {
+        return [&g_event.location, &g_event.description]
+            .into_iter()
+            .find_map(|text| find_conference_link(text));
+    };
+
+    let link = ConferenceLink::new(&url);
+    let phone = entry_points
+        .iter()
+        .find(|entry| entry.entry_point_type == "phone");
+    Some(match phone {
+        Some(phone) => {
+            // The label is the number written nicely; the URI is a `tel:` link
+            let number = non_empty(phone.label.clone())
+                .unwrap_or_else(|| phone.uri.trim_start_matches("tel:").to_string());
+            link.with_dial_in(&number, non_empty(phone.pin.clone()).as_deref())
+        }
+        None => link,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::oauth::*;
+    use crate::calendar::throttle::RequestBudget;
+    use crate::calendar::tokens::StoredToken;
+    use crate::testing::*;
+    use reqwest::{StatusCode, Url};
+
+    const EVENT: &str = r#"{
+        "kind": "calendar#event",
+        "id": "7cbh8rpc10lrc0ckih9tafss99",
+        "status": "confirmed",
+        "htmlLink": "https://www.google.com/calendar/event?eid=N2NiaDhycGMxMGxyYzBja2loOXRhZnNzOTk",
+        "summary": "Quarterly planning",
+        "description": "Bring your roadmap",
+        "location": "Conference room B",
+        "organizer": {"email": "dana@example.com", "displayName": "Dana Swope"},
+        "start": {"dateTime": "2023-09-05T13:00:00Z", "timeZone": "America/New_York"},
+        "end": {"dateTime": "2023-09-05T14:30:00Z", "timeZone": "America/New_York"},
+        "iCalUID": "7cbh8rpc10lrc0ckih9tafss99@google.com",
+        "visibility": "private",
+        "transparency": "transparent",
+        "attendees": [
+            {"email": "dana@example.com", "displayName": "Dana Swope", "organizer": true, "responseStatus": "accepted"},
+            {"email": "me@example.com", "self": true, "responseStatus": "tentative"},
+            {"email": "alex@example.com", "optional": true, "responseStatus": "declined"}
+        ],
+        "eventType": "default"
+    }"#;
+
+    fn parse(json: &str) -> CalendarEvent {
+        parse_event(
+            serde_json::from_str(json).unwrap(),
+            None,
+            &ColorPalette::default(),
+        )
+    }
+
+    #[test]
+    fn maps_event_details_people_and_privacy() {
+        let event = parse(EVENT);
+
+        assert_eq!("Quarterly planning", event.summary);
+        assert_eq!(Some("7cbh8rpc10lrc0ckih9tafss99"), event.id.as_deref());
+        assert_eq!(Some("Bring your roadmap"), event.description.as_deref());
+        assert_eq!(Some("Conference room B"), event.location.as_deref());
+        assert_eq!(
+            Some("7cbh8rpc10lrc0ckih9tafss99@google.com"),
+            event.ical_uid.as_deref()
+        );
+        assert_eq!(
+            Some(
+                Utc.with_ymd_and_hms(2023, 9, 5, 13, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Local)
+            ),
+            event.times.start
+        );
+        assert_eq!(
+            Some(
+                Utc.with_ymd_and_hms(2023, 9, 5, 14, 30, 0)
+                    .unwrap()
+                    .with_timezone(&Local)
+            ),
+            event.times.end
+        );
+        assert_eq!(None, event.all_day);
+
+        assert_eq!(
+            Some(Person {
+                email: "dana@example.com".to_string(),
+                display_name: Some("Dana Swope".to_string()),
+            }),
+            event.organizer
+        );
+        let responses: Vec<(&str, ResponseStatus, bool, bool)> = event
+            .attendees
+            .iter()
+            .map(|a| {
+                (
+                    a.person.email.as_str(),
+                    a.response_status,
+                    a.is_self,
+                    a.optional,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("dana@example.com", ResponseStatus::Accepted, false, false),
+                ("me@example.com", ResponseStatus::Tentative, true, false),
+                ("alex@example.com", ResponseStatus::Declined, false, true),
+            ],
+            responses
+        );
+        assert_eq!(Some(ResponseStatus::Tentative), event.self_response());
+        assert_eq!(Visibility::Private, event.visibility);
+        assert_eq!(Transparency::Transparent, event.transparency);
+        assert_eq!(EventStatus::Confirmed, event.status);
+    }
+
+    #[test]
+    fn leaves_missing_details_at_their_defaults() {
+        let event = parse(
+            r#"{
+                "id": "bare",
+                "summary": "Lunch",
+                "start": {"dateTime": "2023-09-05T12:00:00Z"},
+                "end": {"dateTime": "2023-09-05T13:00:00Z"}
+            }"#,
+        );
+
+        assert_eq!(None, event.description);
+        assert_eq!(None, event.location);
+        assert_eq!(None, event.organizer);
+        assert!(event.attendees.is_empty());
+        assert_eq!(None, event.self_response());
+        assert_eq!(Visibility::Default, event.visibility);
+        assert_eq!(Transparency::Opaque, event.transparency);
+        assert_eq!(EventKind::Default, event.kind);
+    }
+
+    /// An event of `event_type`, with `extra` fields added
+    fn parse_kind_of(event_type: &str, extra: &str) -> EventKind {
+        let json = format!(
+            r#"{{
+                "id": "kind",
+                "summary": "Somewhere",
+                "start": {{"date": "2023-09-05"}},
+                "end": {{"date": "2023-09-06"}},
+                "eventType": "{}"{}
+            }}"#,
+            event_type, extra
+        );
+        parse(&json).kind
+    }
+
+    #[test]
+    fn recognizes_out_of_office_and_focus_time() {
+        assert_eq!(EventKind::OutOfOffice, parse_kind_of("outOfOffice", ""));
+        assert_eq!(EventKind::FocusTime, parse_kind_of("focusTime", ""));
+        assert_eq!(EventKind::Default, parse_kind_of("fromGmail", ""));
+    }
+
+    #[test]
+    fn maps_each_type_of_working_location() {
+        let location = |properties: &str| {
+            parse_kind_of(
+                "workingLocation",
+                &format!(r#", "workingLocationProperties": {}"#, properties),
+            )
+        };
+
+        assert_eq!(
+            EventKind::WorkingLocation(WorkingLocation::Home),
+            location(r#"{"type": "homeOffice", "homeOffice": {}}"#)
+        );
+        assert_eq!(
+            EventKind::WorkingLocation(WorkingLocation::Office(Some("Building 4".to_string()))),
+            location(
+                r#"{"type": "officeLocation", "officeLocation": {"buildingId": "b4", "label": "Building 4"}}"#
+            )
+        );
+        assert_eq!(
+            EventKind::WorkingLocation(WorkingLocation::Office(None)),
+            location(r#"{"type": "officeLocation", "officeLocation": {"buildingId": "b4"}}"#)
+        );
+        assert_eq!(
+            EventKind::WorkingLocation(WorkingLocation::Custom("Lisbon".to_string())),
+            location(r#"{"type": "customLocation", "customLocation": {"label": "Lisbon"}}"#)
+        );
+        assert_eq!(
+            EventKind::WorkingLocation(WorkingLocation::Custom(ELSEWHERE.to_string())),
+            location(r#"{"type": "customLocation", "customLocation": {}}"#)
+        );
+        assert_eq!(EventKind::Default, parse_kind_of("workingLocation", ""));
+    }
+
+    #[async_std::test]
+    async fn anchors_all_day_events_at_midnight_in_the_calendars_time_zone() {
+        // Far enough from any zone the tests run in that midnight there is on another day in UTC
+        let server = google_server(|path, _| match path {
+            "calendars/me@example.com/events" => Some((
+                200,
+                r#"{
+                    "timeZone": "Pacific/Auckland",
+                    "items": [{
+                        "id": "labor-day",
+                        "summary": "Labor Day",
+                        "start": {"date": "2023-09-04"},
+                        "end": {"date": "2023-09-05"}
+                    }]
+                }"#
+                .to_string(),
+            )),
+            _ => None,
+        });
+
+        let events = calendar(&server)
+            .get_events_on(september_5th())
+            .await
+            .unwrap();
+
+        let event = &events[0];
+        let labor_day = NaiveDate::from_ymd_opt(2023, 9, 4).unwrap();
+        assert_eq!(
+            Some(DateRange {
+                start: labor_day,
+                end: labor_day + Duration::days(1),
+            }),
+            event.all_day
+        );
+        assert!(event.is_all_day());
+        let auckland: Tz = "Pacific/Auckland".parse().unwrap();
+        let midnight = |day: u32| {
+            auckland
+                .with_ymd_and_hms(2023, 9, day, 0, 0, 0)
+                .unwrap()
+                .with_timezone(&Local)
+        };
+        assert_eq!(Some(midnight(4)), event.times.start);
+        assert_eq!(Some(midnight(5)), event.times.end);
+        assert_eq!(
+            Some(
+                Utc.with_ymd_and_hms(2023, 9, 3, 12, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Local)
+            ),
+            event.times.start
+        );
+    }
+
+    const CALENDAR_LIST: &str = r##"{
+        "items": [
+            {"id": "me@example.com", "summary": "me@example.com", "primary": true, "backgroundColor": "#9fe1e7"},
+            {"id": "team@group.calendar.google.com", "summary": "Team", "backgroundColor": "#7bd148"}
+        ]
+    }"##;
+
+    fn events_page(ids: &[&str], next_page_token: Option<&str>) -> String {
+        let items: Vec<serde_json::Value> = ids
+            .iter()
+            .map(|id| {
+                serde_json::json!({
+                    "id": id,
+                    "summary": format!("Event {}", id),
+                    "start": {"dateTime": "2023-09-05T13:00:00Z"},
+                    "end": {"dateTime": "2023-09-05T14:00:00Z"},
+                })
+            })
+            .collect();
+        let mut page = serde_json::json!({"timeZone": "America/New_York", "items": items});
+        if let Some(token) = next_page_token {
+            page["nextPageToken"] = serde_json::json!(token);
+        }
+        page.to_string()
+    }
+
+    /// Answers with whatever `respond` gives for the path (without the API prefix) and query, or else a 404
+    fn google_server(
+        respond: impl Fn(&str, &str) -> Option<(u16, String)> + Send + 'static,
+    ) -> StubServer {
+        StubServer::start(move |request| {
+            if request.header("Authorization") != Some("Bearer google-token") {
+                return StubResponse::new(401, "");
+            }
+            let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+            let path = path.trim_start_matches("/calendar/v3/");
+            let (status, body) = match path {
+                "users/me/calendarList" => (200, CALENDAR_LIST.to_string()),
+                "colors" => (200, "{}".to_string()),
+                _ => match respond(path, query) {
+                    Some(response) => response,
+                    None => return StubResponse::new(404, ""),
+                },
+            };
+            StubResponse::new(status, &body).with_header("Content-Type", "application/json")
+        })
+    }
+
+    /// An API client for the stub server that retries `retries` times without waiting
+    fn api(server: &StubServer, token: StoredToken, retries: u32) -> GoogleApi {
+        let budget = RequestBudget::new(100, std::time::Duration::from_millis(1)).with_retries(
+            retries,
+            std::time::Duration::ZERO,
+            std::time::Duration::ZERO,
+        );
+        api_with_budget(server, token, budget)
+    }
+
+    fn api_with_budget(
+        server: &StubServer,
+        token: StoredToken,
+        budget: RequestBudget,
+    ) -> GoogleApi {
+        let client = DeviceFlowClient {
+            client_id: "client".to_string(),
+            client_secret: None,
+            device_authorization_url: server.url("/device/code"),
+            token_url: server.url("/token"),
+            scopes: Vec::new(),
+        };
+        GoogleApi::new(OAuthSession::new(client, token, None))
+            .with_base_url(Url::parse(&server.url("/calendar/v3/")).unwrap())
+            .with_budget(Arc::new(budget))
+    }
+
+    fn token(access_token: &str) -> StoredToken {
+        StoredToken {
+            access_token: access_token.to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: Utc::now() + Duration::hours(1),
+        }
+    }
+
+    fn calendar(server: &StubServer) -> GoogleCalendar {
+        GoogleCalendar::new(api(server, token("google-token"), 0))
+    }
+
+    fn page_tokens(server: &StubServer) -> Vec<Option<String>> {
+        server
+            .requests()
+            .iter()
+            .filter(|request| request.path.contains("/events"))
+            .map(|request| {
+                let url = Url::parse(&format!("http://stub{}", request.path)).unwrap();
+                url.query_pairs()
+                    .find(|(name, _)| name == "pageToken")
+                    .map(|(_, token)| token.into_owned())
+            })
+            .collect()
+    }
+
+    #[async_std::test]
+    async fn follows_page_tokens_to_the_last_page() {
+        let server = google_server(|path, query| match path {
+            "calendars/me@example.com/events" if query.contains("pageToken=page-2") => {
+                Some((200, events_page(&["3"], None)))
+            }
+            "calendars/me@example.com/events" => {
+                Some((200, events_page(&["1", "2"], Some("page-2"))))
+            }
+            _ => None,
+        });
+
+        let events = calendar(&server)
+            .get_events_on(september_5th())
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = events.iter().filter_map(|e| e.id.as_deref()).collect();
+        assert_eq!(vec!["1", "2", "3"], ids);
+        assert_eq!(vec![None, Some("page-2".to_string())], page_tokens(&server));
+        assert_eq!(
+            "me@example.com",
+            events[0].source.as_ref().unwrap().calendar_name
+        );
+    }
+
+    #[async_std::test]
+    async fn stops_following_pages_at_the_most_events() {
+        let server = google_server(|path, query| match path {
+            "calendars/me@example.com/events" if query.contains("pageToken=page-3") => {
+                Some((200, events_page(&["5"], None)))
+            }
+            "calendars/me@example.com/events" if query.contains("pageToken=page-2") => {
+                Some((200, events_page(&["3", "4"], Some("page-3"))))
+            }
+            "calendars/me@example.com/events" => {
+                Some((200, events_page(&["1", "2"], Some("page-2"))))
+            }
+            _ => None,
+        });
+
+        let events = calendar(&server)
+            .with_max_events(3)
+            .get_events_on(september_5th())
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = events.iter().filter_map(|e| e.id.as_deref()).collect();
+        assert_eq!(vec!["1", "2", "3"], ids);
+        assert_eq!(vec![None, Some("page-2".to_string())], page_tokens(&server));
+    }
+
+    fn selecting_both(calendar: GoogleCalendar) -> GoogleCalendar {
+        calendar.with_calendars(vec![
+            CalendarSelector::Id("me@example.com".to_string()),
+            CalendarSelector::Name(regex::Regex::new("^Team$").unwrap()),
+        ])
+    }
+
+    #[async_std::test]
+    async fn leaves_out_calendars_that_cannot_be_fetched() {
+        let server = google_server(|path, _| match path {
+            "calendars/me@example.com/events" => Some((200, events_page(&["mine"], None))),
+            "calendars/team@group.calendar.google.com/events" => {
+                Some((404, r#"{"error": {"code": 404}}"#.to_string()))
+            }
+            _ => None,
+        });
+        let calendar = selecting_both(calendar(&server));
+
+        let events = calendar.get_events_on(september_5th()).await.unwrap();
+        let ids: Vec<&str> = events.iter().filter_map(|e| e.id.as_deref()).collect();
+        assert_eq!(vec!["mine"], ids);
+
+        let sync_tokens = HashMap::from([(
+            "team@group.calendar.google.com".to_string(),
+            "team-token".to_string(),
+        )]);
+        let changes = calendar
+            .get_changes(&september_5th(), &sync_tokens)
+            .await
+            .unwrap()
+            .unwrap();
+        let team = changes
+            .iter()
+            .find(|c| c.calendar_id == "team@group.calendar.google.com")
+            .unwrap();
+        assert!(!team.full && team.changed.is_empty());
+        assert_eq!(Some("team-token"), team.sync_token.as_deref());
+    }
+
+    fn calendar_list_fetches(server: &StubServer) -> usize {
+        server
+            .requests()
+            .iter()
+            .filter(|request| request.path.contains("/calendarList"))
+            .count()
+    }
+
+    #[async_std::test]
+    async fn fetches_the_calendar_list_again_only_when_a_calendar_is_gone() {
+        let server = google_server(|path, _| match path {
+            "calendars/me@example.com/events" => Some((200, events_page(&["mine"], None))),
+            "calendars/team@group.calendar.google.com/events" => {
+                Some((200, events_page(&["team"], None)))
+            }
+            _ => None,
+        });
+        let google = selecting_both(calendar(&server));
+
+        google.get_events_on(september_5th()).await.unwrap();
+        google.get_busy_times(september_5th()).await.ok();
+        google.get_events_on(september_5th()).await.unwrap();
+        assert_eq!(1, calendar_list_fetches(&server));
+
+        let server = google_server(|path, _| match path {
+            "calendars/me@example.com/events" => Some((200, events_page(&["mine"], None))),
+            "calendars/team@group.calendar.google.com/events" => {
+                Some((404, r#"{"error": {"code": 404}}"#.to_string()))
+            }
+            _ => None,
+        });
+        let google = selecting_both(calendar(&server));
+
+        google.get_events_on(september_5th()).await.unwrap();
+        google.get_events_on(september_5th()).await.unwrap();
+        assert_eq!(2, calendar_list_fetches(&server));
+    }
+
+    #[async_std::test]
+    async fn fails_when_every_calendar_fails() {
+        let server = google_server(|_, _| Some((404, r#"{"error": {"code": 404}}"#.to_string())));
+        let calendar = selecting_both(calendar(&server));
+
+        assert!(calendar.get_events_on(september_5th()).await.is_err());
+        assert!(calendar
+            .get_changes(&september_5th(), &HashMap::new())
+            .await
+            .unwrap()
+            .is_err());
+    }
+
+    /// A `freeBusy` response where me@example.com is busy at 9 and from 10, and team is busy from 9:30
+    fn free_busy(team: &str) -> String {
+        format!(
+            r#"{{"calendars": {{
+                "me@example.com": {{"busy": [
+                    {{"start": "2023-09-05T09:00:00Z", "end": "2023-09-05T10:00:00Z"}},
+                    {{"start": "2023-09-05T10:00:00Z", "end": "2023-09-05T10:30:00Z"}}
+                ]}},
+                "team@group.calendar.google.com": {}
+            }}}}"#,
+            team
+        )
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Utc.with_ymd_and_hms(2023, 9, 5, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    #[async_std::test]
+    async fn merges_busy_times_from_every_calendar_in_one_request() {
+        let team =
+            r#"{"busy": [{"start": "2023-09-05T09:30:00Z", "end": "2023-09-05T11:00:00Z"}]}"#;
+        let server = google_server(move |path, _| match path {
+            "freeBusy" => Some((200, free_busy(team))),
+            _ => None,
+        });
+
+        let busy = selecting_both(calendar(&server))
+            .get_busy_times(september_5th())
+            .await
+            .unwrap();
+
+        let times: Vec<_> = busy
+            .times()
+            .iter()
+            .map(|time| (time.start, time.end))
+            .collect();
+        assert_eq!(vec![(at(9, 0), at(11, 0))], times);
+        let requests = server.requests();
+        let query = requests
+            .iter()
+            .find(|request| request.path.ends_with("/freeBusy"))
+            .unwrap();
+        assert_eq!("POST", query.method);
+        let body: serde_json::Value = serde_json::from_str(&query.body).unwrap();
+        assert_eq!(2, body["items"].as_array().unwrap().len());
+        assert_eq!(
+            september_5th().start,
+            body["timeMin"]
+                .as_str()
+                .unwrap()
+                .parse::<DateTime<Local>>()
+                .unwrap()
+        );
+    }
+
+    #[async_std::test]
+    async fn leaves_out_calendars_that_free_busy_could_not_read() {
+        let team = r#"{"errors": [{"domain": "global", "reason": "notFound"}]}"#;
+        let server = google_server(move |path, _| match path {
+            "freeBusy" => Some((200, free_busy(team))),
+            _ => None,
+        });
+
+        let busy = selecting_both(calendar(&server))
+            .get_busy_times(september_5th())
+            .await
+            .unwrap();
+
+        let times: Vec<_> = busy
+            .times()
+            .iter()
+            .map(|time| (time.start, time.end))
+            .collect();
+        assert_eq!(vec![(at(9, 0), at(10, 30))], times);
+    }
+
+    #[async_std::test]
+    async fn fails_when_free_busy_could_read_no_calendar() {
+        let response = r#"{"calendars": {
+            "me@example.com": {"errors": [{"reason": "internalError"}]},
+            "team@group.calendar.google.com": {"errors": [{"reason": "notFound"}]}
+        }}"#;
+        let server = google_server(move |path, _| match path {
+            "freeBusy" => Some((200, response.to_string())),
+            _ => None,
+        });
+
+        let e = selecting_both(calendar(&server))
+            .get_busy_times(september_5th())
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::FetchError, e.kind());
+    }
+
+    /// Rejects `old-token` and accepts `new-token`, which is what the token endpoint gives out
+    fn rotating_token_server(accepted: &'static str) -> StubServer {
+        StubServer::start(move |request| {
+            if request.path == "/token" {
+                return StubResponse::new(
+                    200,
+                    r#"{"access_token": "new-token", "expires_in": 3599, "token_type": "Bearer"}"#,
+                )
+                .with_header("Content-Type", "application/json");
+            }
+            match request.header("Authorization") == Some(&format!("Bearer {}", accepted)) {
+                true => StubResponse::new(200, CALENDAR_LIST)
+                    .with_header("Content-Type", "application/json"),
+                false => StubResponse::new(401, ""),
+            }
+        })
+    }
+
+    fn authorizations(server: &StubServer) -> Vec<Option<String>> {
+        server
+            .requests()
+            .iter()
+            .map(|request| match request.path.as_str() {
+                "/token" => None,
+                _ => request.header("Authorization").map(str::to_string),
+            })
+            .collect()
+    }
+
+    #[async_std::test]
+    async fn retries_once_with_a_new_token_when_the_old_one_is_rejected() {
+        let server = rotating_token_server("new-token");
+
+        let page = api(&server, token("old-token"), 0)
+            .list_calendars_page(None)
+            .await
+            .unwrap();
+
+        assert_eq!(2, page.items.len());
+        let expected = vec![
+            Some("Bearer old-token".to_string()),
+            None,
+            Some("Bearer new-token".to_string()),
+        ];
+        assert_eq!(expected, authorizations(&server));
+        assert!(server.requests()[1]
+            .body
+            .contains("grant_type=refresh_token"));
+    }
+
+    #[async_std::test]
+    async fn gives_up_when_the_new_token_is_rejected_too() {
+        let server = rotating_token_server("some-other-token");
+
+        let e = api(&server, token("old-token"), 0)
+            .list_calendars_page(None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(ErrorKind::LoggedOut, e.kind());
+        let expected = vec![
+            Some("Bearer old-token".to_string()),
+            None,
+            Some("Bearer new-token".to_string()),
+        ];
+        assert_eq!(expected, authorizations(&server));
+    }
+
+    /// Answers the calendar list with each of `statuses` in turn, then with the list
+    fn flaky_calendar_list(statuses: &[(u16, &'static str)]) -> StubServer {
+        let responses = std::sync::Mutex::new(statuses.to_vec());
+        StubServer::start(move |_| {
+            let mut responses = responses.lock().unwrap();
+            let (status, body) = match responses.is_empty() {
+                true => (200, CALENDAR_LIST),
+                false => responses.remove(0),
+            };
+            StubResponse::new(status, body).with_header("Content-Type", "application/json")
+        })
+    }
+
+    const RATE_LIMITED: &str = r#"{"error": {"code": 403, "errors": [{"domain": "usageLimits", "reason": "rateLimitExceeded"}]}}"#;
+    const FORBIDDEN: &str =
+        r#"{"error": {"code": 403, "errors": [{"domain": "global", "reason": "forbidden"}]}}"#;
+
+    #[async_std::test]
+    async fn retries_when_told_it_is_over_the_rate_limit_with_a_403() {
+        let server = flaky_calendar_list(&[(403, RATE_LIMITED)]);
+
+        let page = api(&server, token("google-token"), 1)
+            .list_calendars_page(None)
+            .await
+            .unwrap();
+
+        assert_eq!(2, page.items.len());
+        assert_eq!(2, server.requests().len());
+    }
+
+    #[async_std::test]
+    async fn does_not_retry_other_403s() {
+        let server = flaky_calendar_list(&[(403, FORBIDDEN)]);
+
+        let e = api(&server, token("google-token"), 1)
+            .list_calendars_page(None)
+            .await
+            .unwrap_err();
+
+        assert_eq!(Some(StatusCode::FORBIDDEN), e.status());
+        assert_ne!(ErrorKind::RateLimited, e.kind());
+        assert_eq!(1, server.requests().len());
+    }
+
+    #[async_std::test]
+    async fn budgets_each_calendar_separately() {
+        let server = google_server(|path, _| match path {
+            "calendars/me@example.com/events" => Some((200, events_page(&["mine"], None))),
+            "calendars/team@group.calendar.google.com/events" => {
+                Some((200, events_page(&["team"], None)))
+            }
+            _ => None,
+        });
+        // One request in each bucket at once, and then one a minute, so sharing a bucket would stall
+        let budget = RequestBudget::new(1, std::time::Duration::from_secs(60));
+        let google = selecting_both(GoogleCalendar::new(api_with_budget(
+            &server,
+            token("google-token"),
+            budget,
+        )));
+
+        let events = async_std::future::timeout(
+            std::time::Duration::from_secs(5),
+            google.get_events_on(september_5th()),
+        )
+        .await
+        .expect("requests for different calendars waited on each other")
+        .unwrap();
+
+        assert_eq!(2, events.len());
+    }
+}
@@ -0,0 +1,90 @@
+use super::*;
+use crate::calendar::oauth::*;
+use crate::calendar::tokens::*;
+
+const GOOGLE_DEVICE_AUTHORIZATION_URL: &str = "https://oauth2.googleapis.com/device/code";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const CALENDAR_READONLY_SCOPE: &str = "https://www.googleapis.com/auth/calendar.readonly";
+pub const GOOGLE_TOKEN_FILE: &str = "google-token.json";
+/// What Google tokens are saved as in the keyring
+pub const GOOGLE_KEYRING_ACCOUNT: &str = "google";
+
+/// Logs in to Google Calendar with a code entered on another device.
+/// The client ID and secret must belong to a "TVs and Limited Input devices" OAuth client.
+pub struct GoogleCalendarProvider {
+    oauth: DeviceFlowClient,
+    store: Option<TokenStore>,
+}
+
+impl GoogleCalendarProvider {
+    pub fn new(client_id: String, client_secret: String) -> GoogleCalendarProvider {
+        GoogleCalendarProvider {
+            oauth: DeviceFlowClient {
+                client_id,
+                client_secret: Some(client_secret),
+                device_authorization_url: GOOGLE_DEVICE_AUTHORIZATION_URL.to_string(),
+                token_url: GOOGLE_TOKEN_URL.to_string(),
+                scopes: vec![CALENDAR_READONLY_SCOPE.to_string()],
+            },
+            store: None,
+        }
+    }
+
+    /// Remembers tokens in the store, so the user only has to log in once
+    pub fn with_token_store(self, store: TokenStore) -> GoogleCalendarProvider {
+        GoogleCalendarProvider {
+            store: Some(store),
+            ..self
+        }
+    }
+
+    fn calendar_for(&self, token: StoredToken) -> GoogleCalendar {
+        let session = OAuthSession::new(self.oauth.clone(), token, self.store.clone());
+        GoogleCalendar::new(GoogleApi::new(session))
+    }
+}
+
+impl DeviceLoginProvider for GoogleCalendarProvider {
+    type Calendar = GoogleCalendar;
+
+    fn name(&self) -> &str {
+        "Google Calendar"
+    }
+
+    async fn resume_session(&self) -> Option<GoogleCalendar> {
+        let token = self.store.as_ref()?.load().await?;
+        Some(self.calendar_for(token))
+    }
+
+    async fn start_login(&self) -> Result<DeviceAuthorization, CalendarError> {
+        self.oauth.request_device_code().await
+    }
+
+    async fn finish_login(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> Result<GoogleCalendar, CalendarError> {
+        let token = self
+            .oauth
+            .finish_login(authorization, self.store.as_ref())
+            .await?;
+        Ok(self.calendar_for(token))
+    }
+}
+
+impl CalendarProvider for GoogleCalendarProvider {
+    type Calendar = GoogleCalendar;
+
+    async fn login(&mut self) -> Result<GoogleCalendar, CalendarError> {
+        if let Some(calendar) = self.resume_session().await {
+            return Ok(calendar);
+        }
+
+        let authorization = self.start_login().await?;
+        println!(
+            "To show your Google Calendar, visit {} and enter the code {}",
+            authorization.verification_url, authorization.user_code
+        );
+        self.finish_login(&authorization).await
+    }
+}
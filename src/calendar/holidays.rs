@@ -0,0 +1,285 @@
+//! Public holidays for a few countries, worked out from rules so they don't need fetching.
+//! Only nationwide holidays are listed, and not the days off in lieu of ones that fall on a weekend.
+
+use super::calendar::*;
+use super::errors::*;
+use crate::model::*;
+use chrono::prelude::*;
+use chrono::Duration;
+
+/// When a holiday falls in a given year
+#[derive(Debug, Clone, Copy)]
+enum HolidayDate {
+    /// The same month and day every year
+    Fixed(u32, u32),
+    /// The nth of a weekday in a month, e.g. the 4th Thursday of November. Counts from the end of the month if negative.
+    Weekday(u32, Weekday, i32),
+    /// A number of days after Easter Sunday
+    Easter(i64),
+}
+
+const UNITED_STATES: &[(&str, HolidayDate)] = &[
+    ("New Year's Day", HolidayDate::Fixed(1, 1)),
+    (
+        "Martin Luther King Jr. Day",
+        HolidayDate::Weekday(1, Weekday::Mon, 3),
+    ),
+    ("Presidents' Day", HolidayDate::Weekday(2, Weekday::Mon, 3)),
+    ("Memorial Day", HolidayDate::Weekday(5, Weekday::Mon, -1)),
+    ("Juneteenth", HolidayDate::Fixed(6, 19)),
+    ("Independence Day", HolidayDate::Fixed(7, 4)),
+    ("Labor Day", HolidayDate::Weekday(9, Weekday::Mon, 1)),
+    ("Columbus Day", HolidayDate::Weekday(10, Weekday::Mon, 2)),
+    ("Veterans Day", HolidayDate::Fixed(11, 11)),
+    (
+        "Thanksgiving Day",
+        HolidayDate::Weekday(11, Weekday::Thu, 4),
+    ),
+    ("Christmas Day", HolidayDate::Fixed(12, 25)),
+];
+
+/// England and Wales
+const UNITED_KINGDOM: &[(&str, HolidayDate)] = &[
+    ("New Year's Day", HolidayDate::Fixed(1, 1)),
+    ("Good Friday", HolidayDate::Easter(-2)),
+    ("Easter Monday", HolidayDate::Easter(1)),
+    (
+        "Early May bank holiday",
+        HolidayDate::Weekday(5, Weekday::Mon, 1),
+    ),
+    (
+        "Spring bank holiday",
+        HolidayDate::Weekday(5, Weekday::Mon, -1),
+    ),
+    (
+        "Summer bank holiday",
+        HolidayDate::Weekday(8, Weekday::Mon, -1),
+    ),
+    ("Christmas Day", HolidayDate::Fixed(12, 25)),
+    ("Boxing Day", HolidayDate::Fixed(12, 26)),
+];
+
+const GERMANY: &[(&str, HolidayDate)] = &[
+    ("New Year's Day", HolidayDate::Fixed(1, 1)),
+    ("Good Friday", HolidayDate::Easter(-2)),
+    ("Easter Monday", HolidayDate::Easter(1)),
+    ("Labour Day", HolidayDate::Fixed(5, 1)),
+    ("Ascension Day", HolidayDate::Easter(39)),
+    ("Whit Monday", HolidayDate::Easter(50)),
+    ("German Unity Day", HolidayDate::Fixed(10, 3)),
+    ("Christmas Day", HolidayDate::Fixed(12, 25)),
+    ("St. Stephen's Day", HolidayDate::Fixed(12, 26)),
+];
+
+const FRANCE: &[(&str, HolidayDate)] = &[
+    ("New Year's Day", HolidayDate::Fixed(1, 1)),
+    ("Easter Monday", HolidayDate::Easter(1)),
+    ("Labour Day", HolidayDate::Fixed(5, 1)),
+    ("Victory in Europe Day", HolidayDate::Fixed(5, 8)),
+    ("Ascension Day", HolidayDate::Easter(39)),
+    ("Whit Monday", HolidayDate::Easter(50)),
+    ("Bastille Day", HolidayDate::Fixed(7, 14)),
+    ("Assumption Day", HolidayDate::Fixed(8, 15)),
+    ("All Saints' Day", HolidayDate::Fixed(11, 1)),
+    ("Armistice Day", HolidayDate::Fixed(11, 11)),
+    ("Christmas Day", HolidayDate::Fixed(12, 25)),
+];
+
+impl HolidayDate {
+    fn in_year(self, year: i32) -> Option<NaiveDate> {
+        match self {
+            HolidayDate::Fixed(month, day) => NaiveDate::from_ymd_opt(year, month, day),
+            HolidayDate::Weekday(month, weekday, n) if n < 0 => {
+                let last = NaiveDate::from_ymd_opt(year, month + 1, 1)
+                    .or_else(|| NaiveDate::from_ymd_opt(year + 1, 1, 1))?
+                    .pred_opt()?;
+                let back = (7 + last.weekday().num_days_from_monday()
+                    - weekday.num_days_from_monday())
+                    % 7;
+                Some(last - Duration::days(back as i64) - Duration::weeks((-n - 1) as i64))
+            }
+            HolidayDate::Weekday(month, weekday, n) => {
+                NaiveDate::from_weekday_of_month_opt(year, month, weekday, n as u8)
+            }
+            HolidayDate::Easter(offset) => Some(easter_sunday(year)? + Duration::days(offset)),
+        }
+    }
+}
+
+/// The Gregorian date of Easter Sunday, by the anonymous Gregorian algorithm
+fn easter_sunday(year: i32) -> Option<NaiveDate> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+}
+
+const CALENDAR_ID_PREFIX: &str = "holidays:";
+
+/// A country's public holidays, as all-day annotation events
+#[derive(Debug, Clone)]
+pub struct HolidayCalendar {
+    code: String,
+    country: &'static str,
+    holidays: &'static [(&'static str, HolidayDate)],
+}
+
+impl HolidayCalendar {
+    /// By ISO 3166 country code: `US`, `GB`, `DE` or `FR`
+    pub fn for_country(code: &str) -> Option<HolidayCalendar> {
+        let (country, holidays) = match code.to_ascii_uppercase().as_str() {
+            "US" => ("United States", UNITED_STATES),
+            "GB" | "UK" => ("the United Kingdom", UNITED_KINGDOM),
+            "DE" => ("Germany", GERMANY),
+            "FR" => ("France", FRANCE),
+            _ => return None,
+        };
+        Some(HolidayCalendar {
+            code: code.to_ascii_uppercase(),
+            country,
+            holidays,
+        })
+    }
+
+    /// For error messages, and the events' calendar
+    pub fn name(&self) -> String {
+        format!("Holidays in {}", self.country)
+    }
+
+    /// Whether the event is one of the holidays bundled here, rather than from a calendar someone configured
+    pub fn made(event: &CalendarEvent) -> bool {
+        event.kind == EventKind::Annotation
+            && event.source.as_ref().is_some_and(|source| source.bundled)
+    }
+
+    fn holidays_on(&self, dates: &DateRange) -> Vec<CalendarEvent> {
+        let source = EventSource {
+            calendar_id: format!("{}{}", CALENDAR_ID_PREFIX, self.code),
+            calendar_name: self.name(),
+            background_color: None,
+            bundled: true,
+        };
+        (dates.start.year()..=dates.end.year())
+            .flat_map(|year| {
+                self.holidays
+                    .iter()
+                    .filter_map(move |(name, date)| Some((*name, date.in_year(year)?)))
+            })
+            .filter(|(_, date)| dates.contains(*date))
+            .map(|(name, date)| {
+                let all_day = DateRange::single_day(date);
+                let midnight = |date: NaiveDate| {
+                    date.and_time(NaiveTime::MIN)
+                        .and_local_timezone(Local)
+                        .earliest()
+                };
+                CalendarEvent {
+                    id: Some(format!("{}-{}", source.calendar_id, date)),
+                    summary: name.to_string(),
+                    times: IndefiniteTimeRange {
+                        start: midnight(all_day.start),
+                        end: midnight(all_day.end),
+                    },
+                    all_day: Some(all_day),
+                    transparency: Transparency::Transparent,
+                    kind: EventKind::Annotation,
+                    source: Some(source.clone()),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Calendar for HolidayCalendar {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        // Every day the range touches, including the one it ends on
+        let dates = DateRange {
+            start: when.start.date_naive(),
+            end: when.end.date_naive() + Duration::days(1),
+        };
+        Ok(self.holidays_on(&dates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn works_out_holidays_from_their_rules() {
+        assert_eq!(Some(date(2024, 3, 31)), easter_sunday(2024));
+        assert_eq!(Some(date(2025, 4, 20)), easter_sunday(2025));
+
+        let year = |code: &str, year: i32| {
+            HolidayCalendar::for_country(code)
+                .unwrap()
+                .holidays_on(&DateRange {
+                    start: date(year, 1, 1),
+                    end: date(year + 1, 1, 1),
+                })
+        };
+        let on = |events: &[CalendarEvent], name: &str| {
+            events
+                .iter()
+                .find(|e| e.summary == name)
+                .unwrap()
+                .all_day
+                .clone()
+                .unwrap()
+                .start
+        };
+
+        let us = year("us", 2023);
+        assert_eq!(11, us.len());
+        assert!(us.iter().all(HolidayCalendar::made));
+        assert_eq!(date(2023, 11, 23), on(&us, "Thanksgiving Day"));
+        assert_eq!(date(2023, 5, 29), on(&us, "Memorial Day"));
+        assert_eq!(date(2023, 9, 4), on(&us, "Labor Day"));
+
+        let gb = year("GB", 2024);
+        assert_eq!(date(2024, 3, 29), on(&gb, "Good Friday"));
+        assert_eq!(date(2024, 8, 26), on(&gb, "Summer bank holiday"));
+        assert_eq!(date(2024, 5, 20), on(&year("DE", 2024), "Whit Monday"));
+        assert!(HolidayCalendar::for_country("XX").is_none());
+    }
+
+    #[async_std::test]
+    async fn lists_holidays_in_range_as_annotations() {
+        let start = Local.with_ymd_and_hms(2023, 12, 25, 8, 0, 0).unwrap();
+        let calendar = HolidayCalendar::for_country("GB").unwrap();
+        let events = calendar
+            .get_events_on(DefiniteTimeRange {
+                start,
+                end: start + Duration::days(1),
+            })
+            .await
+            .unwrap();
+
+        let summaries: Vec<&str> = events.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(vec!["Christmas Day", "Boxing Day"], summaries);
+        assert!(events.iter().all(|e| e.kind == EventKind::Annotation));
+        assert_eq!(
+            "Holidays in the United Kingdom",
+            events[0].source.as_ref().unwrap().calendar_name
+        );
+    }
+}
@@ -0,0 +1,729 @@
+use super::calendar::*;
+use super::conference::find_conference_link;
+use super::errors::*;
+use crate::model::*;
+use chrono::prelude::*;
+use chrono::Duration;
+use futures_util::future::join_all;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub mod parser;
+use parser::*;
+
+pub mod recurrence;
+use recurrence::*;
+
+pub mod timezone;
+use timezone::*;
+
+pub mod values;
+use values::*;
+
+/// Somewhere to read an iCalendar file from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcsSource {
+    File(PathBuf),
+    /// A subscription URL, which is fetched again every time
+    Url(Url),
+}
+
+impl IcsSource {
+    /// Reads paths, `file://` URLs, and `http(s)://` or `webcal://` subscription URLs
+    pub fn parse(location: &str) -> Option<IcsSource> {
+        let Ok(url) = Url::parse(location) else {
+            return Some(IcsSource::File(PathBuf::from(location)));
+        };
+        match url.scheme() {
+            "file" => url.to_file_path().ok().map(IcsSource::File),
+            "http" | "https" => Some(IcsSource::Url(url)),
+            // webcal:// is http(s):// by another name, for calendar apps to pick up
+            "webcal" | "webcals" => {
+                Url::parse(&format!("https{}", &location[url.scheme().len()..]))
+                    .ok()
+                    .map(IcsSource::Url)
+            }
+            // Windows paths like C:\calendar.ics parse as URLs with a one-letter scheme
+            scheme if scheme.len() == 1 => Some(IcsSource::File(PathBuf::from(location))),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> String {
+        match self {
+            IcsSource::File(path) => path.display().to_string(),
+            IcsSource::Url(url) => url.to_string(),
+        }
+    }
+
+    async fn read(&self, http: &reqwest::Client) -> Result<String, CalendarError> {
+        match self {
+            IcsSource::File(path) => async_std::fs::read_to_string(path).await.map_err(|e| {
+                CalendarError::fetch(format!("could not read calendar {}", path.display()))
+                    .with_cause(e)
+            }),
+            IcsSource::Url(url) => {
+                let response = http.get(url.clone()).send().await.map_err(|e| {
+                    CalendarError::fetch(format!("could not fetch calendar {}", url)).with_cause(e)
+                })?;
+                if !response.status().is_success() {
+                    return Err(CalendarError::from_response(
+                        format!("could not fetch calendar {}", url),
+                        &response,
+                    ));
+                }
+                response.text().await.map_err(|e| {
+                    CalendarError::fetch(format!("could not read calendar {}", url)).with_cause(e)
+                })
+            }
+        }
+    }
+}
+
+/// Calendars exported from Outlook, Fastmail, etc. as `.ics` files, or subscribed to by URL
+pub struct IcsCalendar {
+    sources: Vec<IcsSource>,
+    http: reqwest::Client,
+}
+
+impl IcsCalendar {
+    pub fn new(sources: Vec<IcsSource>) -> IcsCalendar {
+        IcsCalendar {
+            sources,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    async fn get_source_events(
+        &self,
+        source: &IcsSource,
+        date: &DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let text = source.read(&self.http).await?;
+        parse_events(&text, &source.id(), date).map_err(|e| {
+            CalendarError::fetch(format!("could not parse calendar {}", source.id())).with_cause(e)
+        })
+    }
+
+    async fn get_source_tasks(
+        &self,
+        source: &IcsSource,
+        by: NaiveDate,
+    ) -> Result<Vec<Task>, CalendarError> {
+        let text = source.read(&self.http).await?;
+        parse_tasks(&text, &source.id(), by).map_err(|e| {
+            CalendarError::fetch(format!("could not parse tasks {}", source.id())).with_cause(e)
+        })
+    }
+}
+
+impl Calendar for IcsCalendar {
+    /// Only fails if every source did; otherwise the ones that failed are left out
+    async fn get_events_on(
+        &self,
+        date: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let results = join_all(
+            self.sources
+                .iter()
+                .map(|source| self.get_source_events(source, &date)),
+        )
+        .await;
+        Ok(
+            unless_every_calendar_failed(self.sources.iter().map(IcsSource::id), results)?
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect(),
+        )
+    }
+}
+
+/// The VTODOs in the same files, which is how Thunderbird, Apple Reminders and Tasks.org export tasks
+impl TaskList for IcsCalendar {
+    /// Only fails if every source did, like [`IcsCalendar::get_events_on`]
+    async fn get_tasks_due(&self, by: NaiveDate) -> Result<Vec<Task>, CalendarError> {
+        let results = join_all(
+            self.sources
+                .iter()
+                .map(|source| self.get_source_tasks(source, by)),
+        )
+        .await;
+        Ok(
+            unless_every_calendar_failed(self.sources.iter().map(IcsSource::id), results)?
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect(),
+        )
+    }
+}
+
+/// Every occurrence of every event in iCalendar text that overlaps `date`
+pub fn parse_events(
+    text: &str,
+    calendar_id: &str,
+    date: &DefiniteTimeRange<Local>,
+) -> Result<Vec<CalendarEvent>, ParseError> {
+    Ok(parse(text)?
+        .iter()
+        .filter(|calendar| calendar.name == "VCALENDAR")
+        .flat_map(|calendar| events_in(calendar, calendar_id, date))
+        .collect())
+}
+
+/// Every task in iCalendar text that isn't done yet and is due on or before `by`.
+/// Repeating tasks only count their first occurrence.
+pub fn parse_tasks(text: &str, calendar_id: &str, by: NaiveDate) -> Result<Vec<Task>, ParseError> {
+    Ok(parse(text)?
+        .iter()
+        .filter(|calendar| calendar.name == "VCALENDAR")
+        .flat_map(|calendar| tasks_in(calendar, calendar_id, by))
+        .collect())
+}
+
+fn source_of(calendar: &Component, calendar_id: &str) -> EventSource {
+    EventSource {
+        calendar_id: calendar_id.to_string(),
+        calendar_name: calendar
+            .text("X-WR-CALNAME")
+            .unwrap_or_else(|| calendar_id.to_string()),
+        background_color: calendar
+            .text("COLOR")
+            .or_else(|| calendar.text("X-APPLE-CALENDAR-COLOR"))
+            .and_then(|color| Color::from_hex(&color)),
+        ..Default::default()
+    }
+}
+
+fn tasks_in(calendar: &Component, calendar_id: &str, by: NaiveDate) -> Vec<Task> {
+    let zones = TimeZones::from_calendar(calendar);
+    let source = source_of(calendar, calendar_id);
+    calendar
+        .components_named("VTODO")
+        .filter(|todo| {
+            let status = todo
+                .text("STATUS")
+                .map(|status| status.to_ascii_uppercase());
+            todo.property("COMPLETED").is_none()
+                && !matches!(status.as_deref(), Some("COMPLETED" | "CANCELLED"))
+        })
+        .filter_map(|todo| {
+            let due = match todo.property("DUE").and_then(IcsTime::parse)? {
+                IcsTime::Date(date) => date,
+                due => zones.zone_of(&due).to_local(due.naive()).date_naive(),
+            };
+            Some(Task {
+                id: todo.text("UID"),
+                summary: todo.text("SUMMARY").unwrap_or_default(),
+                description: todo.text("DESCRIPTION"),
+                due: Some(due),
+                // 0 means it wasn't given one
+                priority: todo
+                    .text("PRIORITY")
+                    .and_then(|priority| priority.parse().ok())
+                    .filter(|&priority| priority > 0),
+                visibility: parse_visibility(todo),
+                source: Some(source.clone()),
+            })
+        })
+        .filter(|task| task.due.is_some_and(|due| due <= by))
+        .collect()
+}
+
+/// Every occurrence of every event in the calendar that overlaps `date`
+fn events_in(
+    calendar: &Component,
+    calendar_id: &str,
+    date: &DefiniteTimeRange<Local>,
+) -> Vec<CalendarEvent> {
+    let zones = TimeZones::from_calendar(calendar);
+    let source = source_of(calendar, calendar_id);
+
+    // Changes to single occurrences of a recurring event, which share its UID
+    let mut overrides: HashMap<String, Vec<&Component>> = HashMap::new();
+    for event in calendar
+        .components_named("VEVENT")
+        .filter(|e| e.property("RECURRENCE-ID").is_some())
+    {
+        overrides
+            .entry(event.text("UID").unwrap_or_default())
+            .or_default()
+            .push(event);
+    }
+
+    let mut events = Vec::new();
+    for event in calendar
+        .components_named("VEVENT")
+        .filter(|e| e.property("RECURRENCE-ID").is_none())
+    {
+        let overridden = overrides
+            .get(&event.text("UID").unwrap_or_default())
+            .map_or(&[][..], |o| o.as_slice());
+        events.extend(occurrences_of(event, overridden, &zones, date));
+    }
+    // Moved occurrences count wherever they were moved to
+    for event in overrides.values().flatten() {
+        events.extend(occurrences_of(event, &[], &zones, date));
+    }
+
+    for event in &mut events {
+        event.source = Some(source.clone());
+    }
+    events
+}
+
+/// The occurrences of an event that overlap `date`, leaving out the ones that were overridden
+fn occurrences_of(
+    event: &Component,
+    overrides: &[&Component],
+    zones: &TimeZones,
+    date: &DefiniteTimeRange<Local>,
+) -> Vec<CalendarEvent> {
+    let Some(start) = event.property("DTSTART").and_then(IcsTime::parse) else {
+        eprintln!(
+            "skipping event without a start: {:?}",
+            event.text("SUMMARY")
+        );
+        return Vec::new();
+    };
+    let zone = zones.zone_of(&start);
+    let length = EventLength::of(event, &start, zones);
+
+    // Expand in the event's own wall-clock time, so it stays at 9am across daylight saving changes.
+    // Occurrences that start before the window can still run into it.
+    let window_start = zone.from_utc(date.start.naive_utc()) - length.longest() - Duration::days(1);
+    let window_end = zone.from_utc(date.end.naive_utc()) + Duration::days(1);
+    let mut starts = match event.property("RRULE").and_then(|p| RRule::parse(&p.value)) {
+        Some(rule) => {
+            let until = rule.until.as_ref().map(|until| match until {
+                // UNTIL is inclusive, so a date means the end of that day
+                IcsTime::Date(day) => day.and_hms_opt(23, 59, 59).unwrap_or(until.naive()),
+                until => zones.wall_time_in(until, &zone),
+            });
+            rule.occurrences(start.naive(), until, window_start, window_end)
+        }
+        None => vec![start.naive()],
+    };
+    starts.extend(
+        event
+            .properties_named("RDATE")
+            .flat_map(IcsTime::parse_list)
+            .map(|rdate| zones.wall_time_in(&rdate, &zone)),
+    );
+    let excluded: Vec<NaiveDateTime> = event
+        .properties_named("EXDATE")
+        .flat_map(IcsTime::parse_list)
+        .chain(
+            overrides
+                .iter()
+                .filter_map(|o| o.property("RECURRENCE-ID").and_then(IcsTime::parse)),
+        )
+        .map(|time| zones.wall_time_in(&time, &zone))
+        .collect();
+    starts.sort();
+    starts.dedup();
+    starts.retain(|start| !excluded.contains(start));
+
+    starts
+        .into_iter()
+        .map(|start| occurrence(event, start, &zone, &length))
+        .filter(|occurrence| overlaps(occurrence, date))
+        .collect()
+}
+
+/// How long each occurrence of an event lasts
+enum EventLength {
+    Days(i64),
+    Exactly(Duration),
+}
+
+impl EventLength {
+    /// From DTEND, or else DURATION. Without either, all-day events last a day and others are instants.
+    fn of(event: &Component, start: &IcsTime, zones: &TimeZones) -> EventLength {
+        let end = event.property("DTEND").and_then(IcsTime::parse);
+        let duration = event
+            .property("DURATION")
+            .and_then(|p| parse_duration(&p.value));
+        match (start, end) {
+            (IcsTime::Date(start), Some(IcsTime::Date(end))) => {
+                EventLength::Days((end - *start).num_days().max(1))
+            }
+            (IcsTime::Date(_), _) => EventLength::Days(duration.map_or(1, |d| d.num_days().max(1))),
+            (start, Some(end)) => {
+                let to_utc = |time: &IcsTime| zones.zone_of(time).to_utc(time.naive());
+                EventLength::Exactly(to_utc(&end) - to_utc(start))
+            }
+            (_, None) => EventLength::Exactly(duration.unwrap_or_else(Duration::zero)),
+        }
+    }
+
+    fn longest(&self) -> Duration {
+        match self {
+            EventLength::Days(days) => Duration::days(*days),
+            EventLength::Exactly(duration) => (*duration).max(Duration::zero()),
+        }
+    }
+}
+
+fn occurrence(
+    event: &Component,
+    start: NaiveDateTime,
+    zone: &Zone,
+    length: &EventLength,
+) -> CalendarEvent {
+    let (all_day, times) = match length {
+        EventLength::Days(days) => {
+            let dates = DateRange {
+                start: start.date(),
+                end: start.date() + Duration::days(*days),
+            };
+            let times = IndefiniteTimeRange {
+                start: Some(Zone::Floating.to_local(dates.start.and_time(NaiveTime::default()))),
+                end: Some(Zone::Floating.to_local(dates.end.and_time(NaiveTime::default()))),
+            };
+            (Some(dates), times)
+        }
+        EventLength::Exactly(duration) => {
+            let start = zone.to_local(start);
+            (
+                None,
+                IndefiniteTimeRange {
+                    start: Some(start),
+                    end: Some(start + *duration),
+                },
+            )
+        }
+    };
+
+    CalendarEvent {
+        id: None,
+        summary: event.text("SUMMARY").unwrap_or_default(),
+        times,
+        all_day,
+        description: event.text("DESCRIPTION"),
+        location: event.text("LOCATION"),
+        organizer: event.property("ORGANIZER").map(parse_person),
+        attendees: event
+            .properties_named("ATTENDEE")
+            .map(parse_attendee)
+            .collect(),
+        visibility: parse_visibility(event),
+        transparency: match event.text("TRANSP").as_deref() {
+            Some("TRANSPARENT") => Transparency::Transparent,
+            _ => Transparency::Opaque,
+        },
+        status: match event
+            .text("STATUS")
+            .map(|status| status.to_ascii_uppercase())
+            .as_deref()
+        {
+            Some("CANCELLED") => EventStatus::Cancelled,
+            Some("TENTATIVE") => EventStatus::Tentative,
+            _ => EventStatus::Confirmed,
+        },
+        // Outlook marks out-of-office time in the feeds it publishes
+        kind: match event.text("X-MICROSOFT-CDO-BUSYSTATUS").as_deref() {
+            Some("OOF") => EventKind::OutOfOffice,
+            _ => EventKind::Default,
+        },
+        color_id: None,
+        // RFC 7986 allows any CSS color name here too, but only hex colors are understood
+        color: event
+            .text("COLOR")
+            .and_then(|color| Color::from_hex(&color)),
+        html_link: event.text("URL"),
+        conference_link: event
+            .text("X-GOOGLE-CONFERENCE")
+            .or_else(|| event.text("X-MICROSOFT-SKYPETEAMSMEETINGURL"))
+            .map(|url| ConferenceLink::new(&url))
+            .or_else(|| {
+                ["LOCATION", "DESCRIPTION"]
+                    .into_iter()
+                    .find_map(|name| find_conference_link(&event.text(name)?))
+            }),
+        ical_uid: event.text("UID"),
+        source: None,
+    }
+}
+
+fn parse_visibility(component: &Component) -> Visibility {
+    match component.text("CLASS").as_deref() {
+        Some("PUBLIC") => Visibility::Public,
+        Some("PRIVATE") => Visibility::Private,
+        Some("CONFIDENTIAL") => Visibility::Confidential,
+        _ => Visibility::Default,
+    }
+}
+
+fn overlaps(event: &CalendarEvent, date: &DefiniteTimeRange<Local>) -> bool {
+    match (event.times.start, event.times.end) {
+        (Some(start), Some(end)) if start == end => date.start <= start && start < date.end,
+        (Some(start), Some(end)) => start < date.end && date.start < end,
+        _ => false,
+    }
+}
+
+/// ORGANIZER and ATTENDEE values are `mailto:` URIs, with the name in a CN parameter
+fn parse_person(property: &Property) -> Person {
+    let value = property.value.as_str();
+    let email = match value.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &value[7..],
+        _ => value,
+    };
+    Person {
+        email: email.to_string(),
+        display_name: property.param("CN").map(str::to_string),
+    }
+}
+
+fn parse_attendee(property: &Property) -> Attendee {
+    Attendee {
+        person: parse_person(property),
+        response_status: match property.param("PARTSTAT") {
+            Some("ACCEPTED") => ResponseStatus::Accepted,
+            Some("DECLINED") => ResponseStatus::Declined,
+            Some("TENTATIVE") => ResponseStatus::Tentative,
+            _ => ResponseStatus::NeedsAction,
+        },
+        is_self: false,
+        optional: property.param("ROLE") == Some("OPT-PARTICIPANT"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    const WEEKLY_WITH_EXCEPTIONS: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+X-WR-CALNAME:Team\r
+BEGIN:VEVENT\r
+UID:standup\r
+SUMMARY:Standup\r
+DTSTART;TZID=Europe/Berlin:20230904T091500\r
+DTEND;TZID=Europe/Berlin:20230904T093000\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE\r
+EXDATE;TZID=Europe/Berlin:20230906T091500\r
+RDATE;TZID=Europe/Berlin:20230908T091500\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID;TZID=Europe/Berlin:20230911T091500\r
+SUMMARY:Standup (moved)\r
+DTSTART;TZID=Europe/Berlin:20230911T140000\r
+DTEND;TZID=Europe/Berlin:20230911T141500\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:offsite\r
+SUMMARY:Offsite\r
+DTSTART;VALUE=DATE:20230907\r
+DTEND;VALUE=DATE:20230909\r
+X-MICROSOFT-CDO-BUSYSTATUS:OOF\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn berlin(d: u32, h: u32, m: u32) -> DateTime<Local> {
+        chrono_tz::Europe::Berlin
+            .with_ymd_and_hms(2023, 9, d, h, m, 0)
+            .unwrap()
+            .with_timezone(&Local)
+    }
+
+    fn range(from: DateTime<Local>, to: DateTime<Local>) -> DefiniteTimeRange<Local> {
+        DefiniteTimeRange {
+            start: from,
+            end: to,
+        }
+    }
+
+    fn starts(events: &[&CalendarEvent]) -> Vec<(String, DateTime<Local>)> {
+        let mut starts: Vec<_> = events
+            .iter()
+            .map(|e| (e.summary.clone(), e.times.start.unwrap()))
+            .collect();
+        starts.sort_by_key(|(_, start)| *start);
+        starts
+    }
+
+    #[test]
+    fn expands_recurrences_with_exceptions_and_overrides() {
+        let calendar = &parse(WEEKLY_WITH_EXCEPTIONS).unwrap()[0];
+        let events = events_in(
+            calendar,
+            "team.ics",
+            &range(berlin(4, 0, 0), berlin(12, 0, 0)),
+        );
+
+        let timed: Vec<_> = events.iter().filter(|e| !e.is_all_day()).collect();
+        assert_eq!(
+            vec![
+                ("Standup".to_string(), berlin(4, 9, 15)),
+                ("Standup".to_string(), berlin(8, 9, 15)),
+                ("Standup (moved)".to_string(), berlin(11, 14, 0)),
+            ],
+            starts(&timed)
+        );
+        assert_eq!(
+            Some(berlin(4, 9, 30)),
+            timed.iter().map(|e| e.times.end.unwrap()).min()
+        );
+        assert_eq!("Team", events[0].source.as_ref().unwrap().calendar_name);
+
+        let all_day: Vec<_> = events.iter().filter_map(|e| e.all_day.clone()).collect();
+        assert_eq!(
+            vec![DateRange {
+                start: NaiveDate::from_ymd_opt(2023, 9, 7).unwrap(),
+                end: NaiveDate::from_ymd_opt(2023, 9, 9).unwrap(),
+            }],
+            all_day
+        );
+        assert_eq!(
+            EventKind::OutOfOffice,
+            events.iter().find(|e| e.is_all_day()).unwrap().kind
+        );
+        assert!(timed.iter().all(|e| e.kind == EventKind::Default));
+    }
+
+    #[test]
+    fn recurring_event_keeps_wall_clock_time_across_daylight_saving() {
+        let calendar = &parse(WEEKLY_WITH_EXCEPTIONS).unwrap()[0];
+        let after_change = chrono_tz::Europe::Berlin
+            .with_ymd_and_hms(2023, 10, 30, 0, 0, 0)
+            .unwrap();
+        let events = events_in(
+            calendar,
+            "team.ics",
+            &range(
+                after_change.with_timezone(&Local),
+                (after_change + Duration::days(1)).with_timezone(&Local),
+            ),
+        );
+
+        let start = events[0]
+            .times
+            .start
+            .unwrap()
+            .with_timezone(&chrono_tz::Europe::Berlin);
+        assert_eq!((9, 15), (start.hour(), start.minute()));
+    }
+
+    #[async_std::test]
+    async fn reads_calendars_from_files_and_urls() {
+        let server = StubServer::start(|_| {
+            StubResponse::new(200, WEEKLY_WITH_EXCEPTIONS)
+                .with_header("Content-Type", "text/calendar")
+        });
+        let dir = tempdir::TempDir::new("ics").unwrap();
+        let path = dir.path().join("team.ics");
+        std::fs::write(&path, WEEKLY_WITH_EXCEPTIONS).unwrap();
+
+        let calendar = IcsCalendar::new(vec![
+            IcsSource::parse(&server.url("/team.ics")).unwrap(),
+            IcsSource::parse(path.to_str().unwrap()).unwrap(),
+        ]);
+        let events = calendar
+            .get_events_on(range(berlin(4, 0, 0), berlin(5, 0, 0)))
+            .await
+            .unwrap();
+
+        assert_eq!(2, events.len());
+        assert_eq!(1, server.requests().len());
+        assert_eq!("GET", server.requests()[0].method);
+        assert_eq!("/team.ics", server.requests()[0].path);
+    }
+
+    #[async_std::test]
+    async fn skips_sources_that_cannot_be_read_unless_all_of_them_fail() {
+        let server = StubServer::start(|_| StubResponse::new(500, ""));
+        let dir = tempdir::TempDir::new("ics").unwrap();
+        let path = dir.path().join("team.ics");
+        std::fs::write(&path, WEEKLY_WITH_EXCEPTIONS).unwrap();
+        let broken = IcsSource::parse(&server.url("/team.ics")).unwrap();
+        let day = range(berlin(4, 0, 0), berlin(5, 0, 0));
+
+        let calendar = IcsCalendar::new(vec![
+            broken.clone(),
+            IcsSource::parse(path.to_str().unwrap()).unwrap(),
+        ]);
+        assert_eq!(1, calendar.get_events_on(day.clone()).await.unwrap().len());
+        assert!(calendar
+            .get_tasks_due(berlin(4, 0, 0).date_naive())
+            .await
+            .is_ok());
+
+        let calendar = IcsCalendar::new(vec![broken]);
+        assert!(calendar.get_events_on(day).await.is_err());
+        assert!(calendar
+            .get_tasks_due(berlin(4, 0, 0).date_naive())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn reads_open_tasks_due_by_the_day() {
+        let text = "BEGIN:VCALENDAR\r
+X-WR-CALNAME:Chores\r
+BEGIN:VTODO\r
+UID:invoice\r
+SUMMARY:Send the invoice\r
+DUE;VALUE=DATE:20230904\r
+PRIORITY:1\r
+END:VTODO\r
+BEGIN:VTODO\r
+SUMMARY:Call the bank\r
+DUE;TZID=Europe/Berlin:20230905T170000\r
+CLASS:PRIVATE\r
+END:VTODO\r
+BEGIN:VTODO\r
+SUMMARY:Book flights\r
+DUE;VALUE=DATE:20230901\r
+STATUS:COMPLETED\r
+END:VTODO\r
+BEGIN:VTODO\r
+SUMMARY:Renew passport\r
+DUE;VALUE=DATE:20231001\r
+END:VTODO\r
+BEGIN:VTODO\r
+SUMMARY:Learn the cello\r
+END:VTODO\r
+END:VCALENDAR\r
+";
+        let tasks = parse_tasks(
+            text,
+            "chores.ics",
+            NaiveDate::from_ymd_opt(2023, 9, 5).unwrap(),
+        )
+        .unwrap();
+
+        let summaries: Vec<&str> = tasks.iter().map(|task| task.summary.as_str()).collect();
+        assert_eq!(vec!["Send the invoice", "Call the bank"], summaries);
+        assert_eq!(Some(1), tasks[0].priority);
+        assert_eq!(None, tasks[1].priority);
+        assert_eq!(Visibility::Private, tasks[1].visibility);
+        assert_eq!(Some(berlin(5, 17, 0).date_naive()), tasks[1].due);
+        assert_eq!("Chores", tasks[0].source.as_ref().unwrap().calendar_name);
+    }
+
+    #[test]
+    fn parses_sources() {
+        assert_eq!(
+            Some(IcsSource::File(PathBuf::from("/home/me/cal.ics"))),
+            IcsSource::parse("file:///home/me/cal.ics")
+        );
+        assert_eq!(
+            Some(IcsSource::File(PathBuf::from("cal.ics"))),
+            IcsSource::parse("cal.ics")
+        );
+        assert_eq!(
+            Some(IcsSource::Url(
+                Url::parse("https://example.com/cal.ics").unwrap()
+            )),
+            IcsSource::parse("webcal://example.com/cal.ics")
+        );
+        assert_eq!(None, IcsSource::parse("ftp://example.com/cal.ics"));
+    }
+}
@@ -0,0 +1,243 @@
+//! Splits iCalendar (RFC 5545) text into components and properties, without interpreting their values
+
+/// A `BEGIN:`...`END:` block, like a VCALENDAR, VEVENT or VTIMEZONE
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Component {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// A content line with no `:` between its name and value
+    MalformedLine(usize),
+    /// An `END:` that doesn't close the innermost open component
+    MismatchedEnd { line: usize, name: String },
+    /// The text ended while this component was still open
+    Unterminated(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::MalformedLine(line) => write!(f, "line {} has no value", line),
+            ParseError::MismatchedEnd { line, name } => {
+                write!(f, "END:{} on line {} doesn't close anything", name, line)
+            }
+            ParseError::Unterminated(name) => write!(f, "{} was never closed", name),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Component {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    pub fn properties_named<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Property> + 'a {
+        self.properties.iter().filter(move |p| p.name == name)
+    }
+
+    /// The unescaped text of a property, if it's there and isn't empty
+    pub fn text(&self, name: &str) -> Option<String> {
+        self.property(name)
+            .map(Property::text)
+            .filter(|text| !text.is_empty())
+    }
+
+    pub fn components_named<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Component> + 'a {
+        self.components.iter().filter(move |c| c.name == name)
+    }
+}
+
+impl Property {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The value of a TEXT property, with `\n`, `\,`, `\;` and `\\` escapes undone
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.value.len());
+        let mut chars = self.value.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                text.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') | Some('N') => text.push('\n'),
+                Some(escaped) => text.push(escaped),
+                None => text.push('\\'),
+            }
+        }
+        text
+    }
+}
+
+/// Parses every top-level component in the text, which is normally a single VCALENDAR
+pub fn parse(text: &str) -> Result<Vec<Component>, ParseError> {
+    let mut finished = Vec::new();
+    let mut open: Vec<Component> = Vec::new();
+
+    for (line_number, line) in unfold(text) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let property = parse_line(&line).ok_or(ParseError::MalformedLine(line_number))?;
+        match property.name.as_str() {
+            "BEGIN" => open.push(Component {
+                name: property.value.to_ascii_uppercase(),
+                ..Component::default()
+            }),
+            "END" => {
+                let name = property.value.to_ascii_uppercase();
+                match open.pop() {
+                    Some(component) if component.name == name => match open.last_mut() {
+                        Some(parent) => parent.components.push(component),
+                        None => finished.push(component),
+                    },
+                    _ => {
+                        return Err(ParseError::MismatchedEnd {
+                            line: line_number,
+                            name,
+                        })
+                    }
+                }
+            }
+            // Anything outside a component isn't part of a calendar
+            _ => {
+                if let Some(component) = open.last_mut() {
+                    component.properties.push(property);
+                }
+            }
+        }
+    }
+
+    match open.pop() {
+        Some(component) => Err(ParseError::Unterminated(component.name)),
+        None => Ok(finished),
+    }
+}
+
+/// Joins lines that were folded by starting the next line with a space or tab,
+/// numbering each logical line by the physical line it started on
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some((_, previous))) => previous.push_str(continuation),
+            _ => lines.push((index + 1, line.to_string())),
+        }
+    }
+    lines
+}
+
+/// Splits `NAME;PARAM=value;PARAM="quoted;value":value`
+fn parse_line(line: &str) -> Option<Property> {
+    let mut in_quotes = false;
+    let mut separators = Vec::new();
+    let mut value_start = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => separators.push(i),
+            ':' if !in_quotes => {
+                value_start = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let value_start = value_start?;
+
+    let name_end = separators.first().copied().unwrap_or(value_start);
+    let name = line[..name_end].trim().to_ascii_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut params = Vec::new();
+    let mut bounds = separators.clone();
+    bounds.push(value_start);
+    for window in bounds.windows(2) {
+        let param = &line[window[0] + 1..window[1]];
+        let (param_name, param_value) = param.split_once('=')?;
+        params.push((
+            param_name.trim().to_ascii_uppercase(),
+            param_value.trim_matches('"').to_string(),
+        ));
+    }
+
+    Some(Property {
+        name,
+        params,
+        value: line[value_start + 1..].to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_nested_components_then_builds_tree() {
+        let text = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nSUMMARY:Standup\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let calendars = parse(text).unwrap();
+
+        assert_eq!(1, calendars.len());
+        assert_eq!("VCALENDAR", calendars[0].name);
+        assert_eq!(Some("2.0".to_string()), calendars[0].text("VERSION"));
+        let event = calendars[0].components_named("VEVENT").next().unwrap();
+        assert_eq!(Some("Standup".to_string()), event.text("SUMMARY"));
+    }
+
+    #[test]
+    fn folded_lines_params_and_escapes_are_undone() {
+        let text = "BEGIN:VEVENT\nDESCRIPTION:Agenda:\\n1. Budget\\, roughly\n  and more\nATTENDEE;CN=\"Doe; Jane\";PARTSTAT=ACCEPTED:mailto:jane@example.com\nEND:VEVENT\n";
+        let event = &parse(text).unwrap()[0];
+
+        assert_eq!(
+            Some("Agenda:\n1. Budget, roughly and more".to_string()),
+            event.text("DESCRIPTION")
+        );
+        let attendee = event.property("ATTENDEE").unwrap();
+        assert_eq!(Some("Doe; Jane"), attendee.param("CN"));
+        assert_eq!(Some("ACCEPTED"), attendee.param("PARTSTAT"));
+        assert_eq!("mailto:jane@example.com", attendee.value);
+    }
+
+    #[test]
+    fn given_unbalanced_components_then_errors() {
+        assert_eq!(
+            Err(ParseError::MismatchedEnd {
+                line: 2,
+                name: "VCALENDAR".to_string()
+            }),
+            parse("BEGIN:VEVENT\nEND:VCALENDAR\n")
+        );
+        assert_eq!(
+            Err(ParseError::Unterminated("VEVENT".to_string())),
+            parse("BEGIN:VEVENT\n")
+        );
+    }
+}
@@ -0,0 +1,582 @@
+use super::values::IcsTime;
+use chrono::prelude::*;
+use chrono::Duration;
+
+/// However often a rule repeats, expanding it stops after this many periods (seconds, days, months...)
+/// past the ones it could skip, so a feed can't stall a refresh with a rule that's very frequent or rarely matches
+const MAX_PERIODS: i64 = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Secondly,
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// An RRULE, e.g. `FREQ=MONTHLY;BYDAY=-1FR;COUNT=6` for the last Friday of the next six months.
+/// BYYEARDAY, BYWEEKNO, BYHOUR, BYMINUTE and BYSECOND aren't supported, and are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<IcsTime>,
+    /// Weekdays, optionally the nth (or nth-from-last, if negative) in the month or year
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    pub week_start: Weekday,
+}
+
+impl RRule {
+    pub fn parse(value: &str) -> Option<RRule> {
+        let mut rule = RRule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            by_set_pos: Vec::new(),
+            week_start: Weekday::Mon,
+        };
+        let mut frequency = None;
+
+        for part in value.trim().split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part.split_once('=')?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => frequency = Some(parse_frequency(value)?),
+                "INTERVAL" => rule.interval = value.parse().ok().filter(|&i| i > 0)?,
+                "COUNT" => rule.count = Some(value.parse().ok()?),
+                "UNTIL" => rule.until = Some(IcsTime::parse_value(value, None)?),
+                "BYDAY" => rule.by_day = parse_list(value, parse_by_day)?,
+                "BYMONTHDAY" => rule.by_month_day = parse_list(value, |v| v.parse().ok())?,
+                "BYMONTH" => rule.by_month = parse_list(value, |v| v.parse().ok())?,
+                "BYSETPOS" => rule.by_set_pos = parse_list(value, |v| v.parse().ok())?,
+                "WKST" => rule.week_start = parse_weekday(value)?,
+                _ => {}
+            }
+        }
+
+        rule.frequency = frequency?;
+        Some(rule)
+    }
+
+    /// Every occurrence from `from` up to and including `end`, of a rule that starts at `start` (which is always the first).
+    /// All times are wall-clock times in the event's time zone, and so is `until`.
+    pub fn occurrences(
+        &self,
+        start: NaiveDateTime,
+        until: Option<NaiveDateTime>,
+        from: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        if start > end || self.count == Some(0) {
+            return Vec::new();
+        }
+        let mut found = Vec::new();
+        if start >= from {
+            found.push(start);
+        }
+        let mut counted = 1;
+
+        // Periods before `from` can only be skipped if their occurrences don't need counting
+        let first = match self.count {
+            Some(_) => 0,
+            None => self.periods_before(start, from),
+        };
+        for index in first..first + MAX_PERIODS {
+            let Some(period_start) = self.period_start(start, index) else {
+                return found;
+            };
+            if period_start > end {
+                return found;
+            }
+            for candidate in self.candidates(start, period_start) {
+                if candidate <= start {
+                    continue;
+                }
+                if candidate > end || until.is_some_and(|until| candidate > until) {
+                    return found;
+                }
+                if self.count.is_some_and(|count| counted >= count) {
+                    return found;
+                }
+                counted += 1;
+                if candidate >= from {
+                    found.push(candidate);
+                }
+            }
+        }
+        eprintln!(
+            "stopped repeating the event that starts at {} after {} periods",
+            start, MAX_PERIODS
+        );
+        found
+    }
+
+    /// How many whole periods pass between `start` and `from`, leaving out the one `from` is in
+    /// and the one before, which may still have occurrences after it
+    fn periods_before(&self, start: NaiveDateTime, from: NaiveDateTime) -> i64 {
+        if from <= start {
+            return 0;
+        }
+        let units = match self.frequency {
+            Frequency::Secondly => (from - start).num_seconds(),
+            Frequency::Minutely => (from - start).num_minutes(),
+            Frequency::Hourly => (from - start).num_hours(),
+            Frequency::Daily => (from.date() - start.date()).num_days(),
+            Frequency::Weekly => (from.date() - start.date()).num_weeks(),
+            Frequency::Monthly => {
+                (from.year() - start.year()) as i64 * 12 + from.month0() as i64
+                    - start.month0() as i64
+            }
+            Frequency::Yearly => (from.year() - start.year()) as i64,
+        };
+        (units / self.interval as i64 - 1).max(0)
+    }
+
+    /// The beginning of the `index`th period (year, month, week...) the rule repeats over
+    fn period_start(&self, start: NaiveDateTime, index: i64) -> Option<NaiveDateTime> {
+        let step = index.checked_mul(self.interval as i64)?;
+        let midnight = |date: NaiveDate| date.and_time(NaiveTime::default());
+        match self.frequency {
+            Frequency::Secondly => start.checked_add_signed(Duration::seconds(step)),
+            Frequency::Minutely => start.checked_add_signed(Duration::minutes(step)),
+            Frequency::Hourly => start.checked_add_signed(Duration::hours(step)),
+            Frequency::Daily => start
+                .date()
+                .checked_add_signed(Duration::days(step))
+                .map(midnight),
+            Frequency::Weekly => {
+                let days_into_week = (7 + start.weekday().num_days_from_monday()
+                    - self.week_start.num_days_from_monday())
+                    % 7;
+                let week_start = start.date() - Duration::days(days_into_week as i64);
+                week_start
+                    .checked_add_signed(Duration::weeks(step))
+                    .map(midnight)
+            }
+            Frequency::Monthly => {
+                let months = start.month0() as i64 + step;
+                let year = start.year() as i64 + months.div_euclid(12);
+                NaiveDate::from_ymd_opt(
+                    i32::try_from(year).ok()?,
+                    months.rem_euclid(12) as u32 + 1,
+                    1,
+                )
+                .map(midnight)
+            }
+            Frequency::Yearly => {
+                let year = i32::try_from(start.year() as i64 + step).ok()?;
+                NaiveDate::from_ymd_opt(year, 1, 1).map(midnight)
+            }
+        }
+    }
+
+    /// The occurrences in one period, in order
+    fn candidates(&self, start: NaiveDateTime, period_start: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let time = start.time();
+        let mut dates = match self.frequency {
+            Frequency::Secondly | Frequency::Minutely | Frequency::Hourly => {
+                return if self.matches_filters(period_start.date()) {
+                    vec![period_start]
+                } else {
+                    Vec::new()
+                };
+            }
+            Frequency::Daily => {
+                let date = period_start.date();
+                if self.matches_filters(date) {
+                    vec![date]
+                } else {
+                    Vec::new()
+                }
+            }
+            Frequency::Weekly => days(period_start.date(), 7)
+                .filter(|date| match self.by_day.is_empty() {
+                    true => date.weekday() == start.weekday(),
+                    false => self
+                        .by_day
+                        .iter()
+                        .any(|(_, weekday)| *weekday == date.weekday()),
+                })
+                .filter(|date| self.matches_month(*date))
+                .collect(),
+            Frequency::Monthly => match self.matches_month(period_start.date()) {
+                true => self.days_of_month(start, period_start.date()),
+                false => Vec::new(),
+            },
+            Frequency::Yearly => self.days_of_year(start, period_start.year()),
+        };
+
+        dates.sort();
+        dates.dedup();
+        if !self.by_set_pos.is_empty() {
+            dates = self
+                .by_set_pos
+                .iter()
+                .filter_map(|&pos| nth(&dates, pos).copied())
+                .collect();
+            dates.sort();
+            dates.dedup();
+        }
+        dates.into_iter().map(|date| date.and_time(time)).collect()
+    }
+
+    fn matches_month(&self, date: NaiveDate) -> bool {
+        self.by_month.is_empty() || self.by_month.contains(&date.month())
+    }
+
+    /// BYxxx parts limit how often daily and faster rules happen
+    fn matches_filters(&self, date: NaiveDate) -> bool {
+        self.matches_month(date)
+            && (self.by_month_day.is_empty()
+                || self.by_month_day.iter().any(|&day| is_month_day(date, day)))
+            && (self.by_day.is_empty()
+                || self
+                    .by_day
+                    .iter()
+                    .any(|(_, weekday)| *weekday == date.weekday()))
+    }
+
+    /// Days in the month that match BYMONTHDAY and BYDAY, or the start's day of the month if neither is given
+    fn days_of_month(&self, start: NaiveDateTime, first: NaiveDate) -> Vec<NaiveDate> {
+        let month = days(first, days_in_month(first)).collect::<Vec<_>>();
+        if self.by_month_day.is_empty() && self.by_day.is_empty() {
+            return month
+                .into_iter()
+                .filter(|date| date.day() == start.day())
+                .collect();
+        }
+        month
+            .iter()
+            .copied()
+            .filter(|&date| {
+                self.by_month_day.is_empty()
+                    || self.by_month_day.iter().any(|&day| is_month_day(date, day))
+            })
+            .filter(|&date| {
+                self.by_day.is_empty()
+                    || self
+                        .by_day
+                        .iter()
+                        .any(|&by_day| is_nth_weekday(&month, date, by_day))
+            })
+            .collect()
+    }
+
+    fn days_of_year(&self, start: NaiveDateTime, year: i32) -> Vec<NaiveDate> {
+        let Some(first) = NaiveDate::from_ymd_opt(year, 1, 1) else {
+            return Vec::new();
+        };
+
+        // Without BYMONTH or BYMONTHDAY, BYDAY picks weekdays out of the whole year, e.g. the 20th Monday
+        if self.by_month.is_empty() && self.by_month_day.is_empty() && !self.by_day.is_empty() {
+            let days_in_year = NaiveDate::from_ymd_opt(year + 1, 1, 1)
+                .map_or(365, |next| (next - first).num_days());
+            let whole_year = days(first, days_in_year).collect::<Vec<_>>();
+            return whole_year
+                .iter()
+                .copied()
+                .filter(|&date| {
+                    self.by_day
+                        .iter()
+                        .any(|&by_day| is_nth_weekday(&whole_year, date, by_day))
+                })
+                .collect();
+        }
+
+        let months: Vec<u32> = match (
+            self.by_month.is_empty(),
+            self.by_month_day.is_empty() && self.by_day.is_empty(),
+        ) {
+            (false, _) => self.by_month.clone(),
+            (true, true) => vec![start.month()],
+            (true, false) => (1..=12).collect(),
+        };
+        months
+            .into_iter()
+            .filter_map(|month| NaiveDate::from_ymd_opt(year, month, 1))
+            .flat_map(|first| self.days_of_month(start, first))
+            .collect()
+    }
+}
+
+fn parse_frequency(value: &str) -> Option<Frequency> {
+    Some(match value.to_ascii_uppercase().as_str() {
+        "SECONDLY" => Frequency::Secondly,
+        "MINUTELY" => Frequency::Minutely,
+        "HOURLY" => Frequency::Hourly,
+        "DAILY" => Frequency::Daily,
+        "WEEKLY" => Frequency::Weekly,
+        "MONTHLY" => Frequency::Monthly,
+        "YEARLY" => Frequency::Yearly,
+        _ => return None,
+    })
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    Some(match value.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+/// Parses `MO`, `2TU` or `-1SU`
+fn parse_by_day(value: &str) -> Option<(Option<i32>, Weekday)> {
+    let split = value.len().checked_sub(2)?;
+    let weekday = parse_weekday(value.get(split..)?)?;
+    let ordinal = match &value[..split] {
+        "" => None,
+        n => Some(n.parse().ok().filter(|&n: &i32| n != 0)?),
+    };
+    Some((ordinal, weekday))
+}
+
+fn parse_list<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    value.split(',').map(|v| parse(v.trim())).collect()
+}
+
+fn days(first: NaiveDate, count: i64) -> impl Iterator<Item = NaiveDate> {
+    (0..count).map(move |i| first + Duration::days(i))
+}
+
+fn days_in_month(first: NaiveDate) -> i64 {
+    let next = match first.month() {
+        12 => NaiveDate::from_ymd_opt(first.year() + 1, 1, 1),
+        month => NaiveDate::from_ymd_opt(first.year(), month + 1, 1),
+    };
+    next.map_or(31, |next| (next - first).num_days())
+}
+
+/// Whether the date is the `day`th of its month, counting from the end if `day` is negative
+fn is_month_day(date: NaiveDate, day: i32) -> bool {
+    let first = date.with_day(1).unwrap_or(date);
+    match day {
+        d if d > 0 => date.day() as i32 == d,
+        d if d < 0 => days_in_month(first) as i32 + d + 1 == date.day() as i32,
+        _ => false,
+    }
+}
+
+/// Whether the date is the given weekday, and if there's an ordinal, the nth one of them in `scope`
+fn is_nth_weekday(
+    scope: &[NaiveDate],
+    date: NaiveDate,
+    (ordinal, weekday): (Option<i32>, Weekday),
+) -> bool {
+    if date.weekday() != weekday {
+        return false;
+    }
+    let Some(ordinal) = ordinal else {
+        return true;
+    };
+    let (Some(first), Some(last)) = (scope.first(), scope.last()) else {
+        return false;
+    };
+    if ordinal > 0 {
+        (date - *first).num_days() / 7 + 1 == ordinal as i64
+    } else {
+        (*last - date).num_days() / 7 + 1 == -ordinal as i64
+    }
+}
+
+/// The nth item, counting from 1, or from the end if `n` is negative
+fn nth<T>(items: &[T], n: i32) -> Option<&T> {
+    match n {
+        n if n > 0 => items.get(n as usize - 1),
+        n if n < 0 => items
+            .len()
+            .checked_sub(n.unsigned_abs() as usize)
+            .and_then(|i| items.get(i)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, 0, 0)
+            .unwrap()
+    }
+
+    fn expand(rule: &str, start: NaiveDateTime, end: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let rule = RRule::parse(rule).unwrap();
+        let until = rule.until.as_ref().map(IcsTime::naive);
+        rule.occurrences(start, until, start, end)
+    }
+
+    fn expand_from(
+        rule: &str,
+        start: NaiveDateTime,
+        from: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        let rule = RRule::parse(rule).unwrap();
+        let until = rule.until.as_ref().map(IcsTime::naive);
+        rule.occurrences(start, until, from, end)
+    }
+
+    #[test]
+    fn weekly_on_several_days_with_count() {
+        // Starts on a Tuesday
+        let occurrences = expand(
+            "FREQ=WEEKLY;BYDAY=TU,TH;COUNT=5",
+            at(2023, 9, 5, 9),
+            at(2024, 1, 1, 0),
+        );
+        assert_eq!(
+            vec![
+                at(2023, 9, 5, 9),
+                at(2023, 9, 7, 9),
+                at(2023, 9, 12, 9),
+                at(2023, 9, 14, 9),
+                at(2023, 9, 19, 9)
+            ],
+            occurrences
+        );
+    }
+
+    #[test]
+    fn every_other_day_until() {
+        let occurrences = expand(
+            "FREQ=DAILY;INTERVAL=2;UNTIL=20230907T090000",
+            at(2023, 9, 1, 9),
+            at(2024, 1, 1, 0),
+        );
+        assert_eq!(
+            vec![
+                at(2023, 9, 1, 9),
+                at(2023, 9, 3, 9),
+                at(2023, 9, 5, 9),
+                at(2023, 9, 7, 9)
+            ],
+            occurrences
+        );
+    }
+
+    #[test]
+    fn last_friday_of_each_month() {
+        let occurrences = expand(
+            "FREQ=MONTHLY;BYDAY=-1FR",
+            at(2023, 9, 29, 16),
+            at(2023, 12, 31, 0),
+        );
+        assert_eq!(
+            vec![
+                at(2023, 9, 29, 16),
+                at(2023, 10, 27, 16),
+                at(2023, 11, 24, 16),
+                at(2023, 12, 29, 16)
+            ],
+            occurrences
+        );
+    }
+
+    #[test]
+    fn monthly_on_a_missing_day_skips_short_months() {
+        let occurrences = expand("FREQ=MONTHLY", at(2024, 1, 31, 12), at(2024, 5, 31, 12));
+        assert_eq!(
+            vec![
+                at(2024, 1, 31, 12),
+                at(2024, 3, 31, 12),
+                at(2024, 5, 31, 12)
+            ],
+            occurrences
+        );
+    }
+
+    #[test]
+    fn last_weekday_of_month_with_set_pos() {
+        let occurrences = expand(
+            "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1;COUNT=3",
+            at(2023, 9, 29, 9),
+            at(2024, 12, 31, 0),
+        );
+        assert_eq!(
+            vec![at(2023, 9, 29, 9), at(2023, 10, 31, 9), at(2023, 11, 30, 9)],
+            occurrences
+        );
+    }
+
+    #[test]
+    fn yearly_daylight_saving_rule() {
+        // When US daylight saving time starts: the second Sunday in March
+        let occurrences = expand(
+            "FREQ=YEARLY;BYMONTH=3;BYDAY=2SU",
+            at(2007, 3, 11, 2),
+            at(2010, 1, 1, 0),
+        );
+        assert_eq!(
+            vec![at(2007, 3, 11, 2), at(2008, 3, 9, 2), at(2009, 3, 8, 2)],
+            occurrences
+        );
+    }
+
+    #[test]
+    fn skips_ahead_to_the_window_without_a_count() {
+        let occurrences = expand_from(
+            "FREQ=DAILY;INTERVAL=3",
+            at(2010, 1, 1, 9),
+            at(2023, 9, 5, 0),
+            at(2023, 9, 8, 0),
+        );
+        // 4995 days after the start is a multiple of three
+        assert_eq!(vec![at(2023, 9, 5, 9)], occurrences);
+
+        let occurrences = expand_from(
+            "FREQ=WEEKLY;BYDAY=MO,FR",
+            at(2010, 1, 4, 9),
+            at(2023, 9, 4, 0),
+            at(2023, 9, 9, 0),
+        );
+        assert_eq!(vec![at(2023, 9, 4, 9), at(2023, 9, 8, 9)], occurrences);
+
+        let occurrences = expand_from(
+            "FREQ=MONTHLY;BYDAY=-1FR",
+            at(2010, 1, 29, 16),
+            at(2023, 9, 1, 0),
+            at(2023, 10, 1, 0),
+        );
+        assert_eq!(vec![at(2023, 9, 29, 16)], occurrences);
+    }
+
+    #[test]
+    fn old_secondly_rule_only_expands_the_window() {
+        let from = at(2023, 9, 5, 9);
+        let occurrences = expand_from(
+            "FREQ=SECONDLY",
+            at(2010, 1, 1, 0),
+            from,
+            from + Duration::minutes(1),
+        );
+        assert_eq!(61, occurrences.len());
+        assert_eq!(Some(&from), occurrences.first());
+
+        // Counting means starting from the beginning, and this one never matches, so it gives up
+        let occurrences = expand_from(
+            "FREQ=SECONDLY;BYMONTH=2;COUNT=3",
+            at(2010, 3, 1, 0),
+            from,
+            from + Duration::days(1),
+        );
+        assert!(occurrences.is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+use super::parser::Component;
+use super::recurrence::*;
+use super::values::*;
+use chrono::prelude::*;
+use chrono::Duration;
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A time zone defined in the calendar file itself, as a list of its changes between standard and daylight time.
+/// Outlook exports these with Windows names like "Pacific Standard Time", which chrono-tz doesn't know.
+#[derive(Debug, Clone)]
+pub struct VTimeZone {
+    observances: Vec<Observance>,
+}
+
+/// A STANDARD or DAYLIGHT part of a VTIMEZONE: the offset it switches to, and when it does
+#[derive(Debug, Clone)]
+struct Observance {
+    offset_from: FixedOffset,
+    offset_to: FixedOffset,
+    /// Wall-clock time of the first switch, in `offset_from`
+    start: NaiveDateTime,
+    rule: Option<RRule>,
+    rdates: Vec<NaiveDateTime>,
+}
+
+impl VTimeZone {
+    pub fn from_component(component: &Component) -> Option<VTimeZone> {
+        let observances: Vec<Observance> = component
+            .components
+            .iter()
+            .filter(|c| c.name == "STANDARD" || c.name == "DAYLIGHT")
+            .filter_map(|c| {
+                Some(Observance {
+                    offset_from: parse_offset(&c.property("TZOFFSETFROM")?.value)?,
+                    offset_to: parse_offset(&c.property("TZOFFSETTO")?.value)?,
+                    start: IcsTime::parse(c.property("DTSTART")?)?.naive(),
+                    rule: c.property("RRULE").and_then(|p| RRule::parse(&p.value)),
+                    rdates: c
+                        .properties_named("RDATE")
+                        .flat_map(IcsTime::parse_list)
+                        .map(|t| t.naive())
+                        .collect(),
+                })
+            })
+            .collect();
+        if observances.is_empty() {
+            None
+        } else {
+            Some(VTimeZone { observances })
+        }
+    }
+
+    /// The offset from UTC at a moment, given as a UTC time
+    pub fn offset_at(&self, utc: NaiveDateTime) -> FixedOffset {
+        let mut changes: Vec<(NaiveDateTime, &Observance)> = self
+            .observances
+            .iter()
+            .flat_map(|observance| {
+                observance
+                    .onsets_until(utc + span(observance.offset_from))
+                    .into_iter()
+                    .map(move |onset| (onset - span(observance.offset_from), observance))
+            })
+            .collect();
+        changes.sort_by_key(|(onset, _)| *onset);
+
+        match changes.iter().rev().find(|(onset, _)| *onset <= utc) {
+            Some((_, observance)) => observance.offset_to,
+            // Before the first recorded change, whatever it changed from was in effect
+            None => changes
+                .first()
+                .map_or(FixedOffset::east_opt(0).unwrap(), |(_, observance)| {
+                    observance.offset_from
+                }),
+        }
+    }
+
+    /// The offset from UTC at a wall-clock time. Times that happen twice when clocks go back
+    /// get the first, and times skipped when clocks go forward are moved forward by the jump.
+    pub fn offset_for_local(&self, local: NaiveDateTime) -> FixedOffset {
+        let mut offsets: Vec<FixedOffset> = self
+            .observances
+            .iter()
+            .flat_map(|o| [o.offset_from, o.offset_to])
+            .collect();
+        // Largest offset first, which is the earlier of two moments with the same wall-clock time
+        offsets.sort_by_key(|offset| -offset.local_minus_utc());
+        offsets.dedup();
+        offsets
+            .iter()
+            .copied()
+            .find(|&offset| self.offset_at(local - span(offset)) == offset)
+            .unwrap_or_else(|| self.offset_at(local - span(offsets[0])))
+    }
+}
+
+impl Observance {
+    fn onsets_until(&self, end: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let mut onsets = match &self.rule {
+            Some(rule) => {
+                let until = rule.until.as_ref().map(|until| match until {
+                    IcsTime::Utc(t) => *t + span(self.offset_from),
+                    other => other.naive(),
+                });
+                let start = self.recent_start(rule, end);
+                rule.occurrences(start, until, start, end)
+            }
+            None if self.start <= end => vec![self.start],
+            None => Vec::new(),
+        };
+        onsets.extend(self.rdates.iter().filter(|&&t| t <= end));
+        onsets
+    }
+
+    /// Outlook starts its rules in 1601, but only the last couple of years matter.
+    /// Skipping ahead only keeps to the same pattern for rules that repeat every year, forever.
+    fn recent_start(&self, rule: &RRule, end: NaiveDateTime) -> NaiveDateTime {
+        let repeats_yearly =
+            rule.frequency == Frequency::Yearly && rule.interval == 1 && rule.count.is_none();
+        if repeats_yearly && self.start.year() < end.year() - 2 {
+            self.start.with_year(end.year() - 2).unwrap_or(self.start)
+        } else {
+            self.start
+        }
+    }
+}
+
+fn span(offset: FixedOffset) -> Duration {
+    Duration::seconds(offset.local_minus_utc() as i64)
+}
+
+/// How to turn a wall-clock time from the calendar into a moment
+#[derive(Debug, Clone)]
+pub enum Zone {
+    /// Whatever time zone this computer is in
+    Floating,
+    Utc,
+    Named(Tz),
+    Defined(Arc<VTimeZone>),
+}
+
+impl Zone {
+    pub fn to_utc(&self, local: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Zone::Floating => Local
+                .from_local_datetime(&local)
+                .earliest()
+                .or_else(|| {
+                    Local
+                        .from_local_datetime(&(local + Duration::hours(1)))
+                        .earliest()
+                })
+                .map_or(local, |t| t.naive_utc()),
+            Zone::Utc => local,
+            Zone::Named(tz) => tz
+                .from_local_datetime(&local)
+                .earliest()
+                .or_else(|| {
+                    tz.from_local_datetime(&(local + Duration::hours(1)))
+                        .earliest()
+                })
+                .map_or(local, |t| t.naive_utc()),
+            Zone::Defined(zone) => local - span(zone.offset_for_local(local)),
+        }
+    }
+
+    pub fn from_utc(&self, utc: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            Zone::Floating => Local.from_utc_datetime(&utc).naive_local(),
+            Zone::Utc => utc,
+            Zone::Named(tz) => tz.from_utc_datetime(&utc).naive_local(),
+            Zone::Defined(zone) => utc + span(zone.offset_at(utc)),
+        }
+    }
+
+    pub fn to_local(&self, local: NaiveDateTime) -> DateTime<Local> {
+        Local.from_utc_datetime(&self.to_utc(local))
+    }
+}
+
+/// The time zones a calendar's TZIDs refer to
+#[derive(Debug, Default)]
+pub struct TimeZones {
+    defined: HashMap<String, Arc<VTimeZone>>,
+}
+
+impl TimeZones {
+    pub fn from_calendar(calendar: &Component) -> TimeZones {
+        let defined = calendar
+            .components_named("VTIMEZONE")
+            .filter_map(|c| {
+                Some((
+                    c.property("TZID")?.value.clone(),
+                    Arc::new(VTimeZone::from_component(c)?),
+                ))
+            })
+            .collect();
+        TimeZones { defined }
+    }
+
+    /// The calendar's own definition of a TZID wins, then the IANA zone of the same name
+    pub fn zone(&self, tzid: &str) -> Zone {
+        if let Some(zone) = self.defined.get(tzid) {
+            return Zone::Defined(zone.clone());
+        }
+        match tzid.trim_start_matches('/').parse::<Tz>() {
+            Ok(tz) => Zone::Named(tz),
+            Err(_) => {
+                eprintln!("unknown time zone {:?}; using local time", tzid);
+                Zone::Floating
+            }
+        }
+    }
+
+    pub fn zone_of(&self, time: &IcsTime) -> Zone {
+        match time {
+            IcsTime::Date(_) | IcsTime::Floating(_) => Zone::Floating,
+            IcsTime::Utc(_) => Zone::Utc,
+            IcsTime::Zoned(_, tzid) => self.zone(tzid),
+        }
+    }
+
+    /// The wall-clock time in `zone` at the same moment as `time`.
+    /// Dates and floating times are the same wall-clock time everywhere.
+    pub fn wall_time_in(&self, time: &IcsTime, zone: &Zone) -> NaiveDateTime {
+        match time {
+            IcsTime::Date(_) | IcsTime::Floating(_) => time.naive(),
+            _ => zone.from_utc(self.zone_of(time).to_utc(time.naive())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::ics::parser::parse;
+
+    /// As exported by Outlook, with a Windows name and no IANA equivalent given
+    const OUTLOOK_EASTERN: &str = "BEGIN:VCALENDAR
+BEGIN:VTIMEZONE
+TZID:Eastern Standard Time
+BEGIN:STANDARD
+DTSTART:16010101T020000
+TZOFFSETFROM:-0400
+TZOFFSETTO:-0500
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=1SU;BYMONTH=11
+END:STANDARD
+BEGIN:DAYLIGHT
+DTSTART:16010101T020000
+TZOFFSETFROM:-0500
+TZOFFSETTO:-0400
+RRULE:FREQ=YEARLY;INTERVAL=1;BYDAY=2SU;BYMONTH=3
+END:DAYLIGHT
+END:VTIMEZONE
+END:VCALENDAR
+";
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn defined_zone_follows_its_rules() {
+        let zones = TimeZones::from_calendar(&parse(OUTLOOK_EASTERN).unwrap()[0]);
+        let eastern = zones.zone("Eastern Standard Time");
+
+        assert_eq!(
+            at(2023, 1, 15, 14, 0),
+            eastern.to_utc(at(2023, 1, 15, 9, 0))
+        );
+        assert_eq!(
+            at(2023, 7, 15, 13, 0),
+            eastern.to_utc(at(2023, 7, 15, 9, 0))
+        );
+        // Clocks went forward at 2am on March 12th
+        assert_eq!(
+            at(2023, 3, 12, 6, 30),
+            eastern.to_utc(at(2023, 3, 12, 1, 30))
+        );
+        assert_eq!(at(2023, 3, 12, 7, 0), eastern.to_utc(at(2023, 3, 12, 3, 0)));
+        assert_eq!(
+            at(2023, 11, 5, 15, 0),
+            eastern.to_utc(at(2023, 11, 5, 10, 0))
+        );
+        assert_eq!(
+            at(2023, 7, 15, 9, 0),
+            eastern.from_utc(at(2023, 7, 15, 13, 0))
+        );
+    }
+
+    #[test]
+    fn given_undefined_tzid_then_uses_iana_zone() {
+        let zones = TimeZones::default();
+        let time = IcsTime::Zoned(at(2023, 7, 15, 9, 0), "Europe/Berlin".to_string());
+
+        assert_eq!(
+            at(2023, 7, 15, 7, 0),
+            zones.zone_of(&time).to_utc(time.naive())
+        );
+        assert_eq!(
+            at(2023, 7, 15, 8, 0),
+            zones.wall_time_in(&time, &zones.zone("Europe/London"))
+        );
+    }
+}
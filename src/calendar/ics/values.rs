@@ -0,0 +1,165 @@
+use super::parser::Property;
+use chrono::prelude::*;
+use chrono::Duration;
+
+/// A DATE or DATE-TIME value, before its time zone is looked up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcsTime {
+    Date(NaiveDate),
+    /// The same wall-clock time wherever the viewer is
+    Floating(NaiveDateTime),
+    Utc(NaiveDateTime),
+    /// A wall-clock time in the time zone with this TZID
+    Zoned(NaiveDateTime, String),
+}
+
+impl IcsTime {
+    pub fn parse(property: &Property) -> Option<IcsTime> {
+        IcsTime::parse_value(&property.value, property.param("TZID"))
+    }
+
+    /// Parses `20230704`, `20230704T093000`, or `20230704T093000Z`
+    pub fn parse_value(value: &str, tzid: Option<&str>) -> Option<IcsTime> {
+        let value = value.trim();
+        if let Some((date, time)) = value.split_once('T') {
+            let date = NaiveDate::parse_from_str(date, "%Y%m%d").ok()?;
+            let (time, utc) = match time.strip_suffix('Z') {
+                Some(time) => (time, true),
+                None => (time, false),
+            };
+            let time = NaiveTime::parse_from_str(time, "%H%M%S").ok()?;
+            let date_time = date.and_time(time);
+            Some(match (utc, tzid) {
+                (true, _) => IcsTime::Utc(date_time),
+                (false, Some(tzid)) => IcsTime::Zoned(date_time, tzid.to_string()),
+                (false, None) => IcsTime::Floating(date_time),
+            })
+        } else {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .ok()
+                .map(IcsTime::Date)
+        }
+    }
+
+    /// Every time in a comma-separated RDATE or EXDATE. Periods only count by their start.
+    pub fn parse_list(property: &Property) -> Vec<IcsTime> {
+        property
+            .value
+            .split(',')
+            .filter_map(|value| {
+                let start = value.split('/').next().unwrap_or(value);
+                IcsTime::parse_value(start, property.param("TZID"))
+            })
+            .collect()
+    }
+
+    /// The wall-clock time, with dates starting at midnight
+    pub fn naive(&self) -> NaiveDateTime {
+        match self {
+            IcsTime::Date(date) => date.and_time(NaiveTime::default()),
+            IcsTime::Floating(t) | IcsTime::Utc(t) | IcsTime::Zoned(t, _) => *t,
+        }
+    }
+
+    pub fn is_date(&self) -> bool {
+        matches!(self, IcsTime::Date(_))
+    }
+}
+
+/// Parses durations like `PT1H30M`, `P1D`, `P2W` or `-PT15M`
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = value.strip_prefix('P')?;
+
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                duration += match (unit, in_time) {
+                    ('W', false) => Duration::weeks(n),
+                    ('D', false) => Duration::days(n),
+                    ('H', true) => Duration::hours(n),
+                    ('M', true) => Duration::minutes(n),
+                    ('S', true) => Duration::seconds(n),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Some(if negative { -duration } else { duration })
+}
+
+/// Parses UTC offsets like `-0500` or `+053000`
+pub fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    let sign = match value.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = &value[1..];
+    if !(digits.len() == 4 || digits.len() == 6) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[0..2].parse().ok()?;
+    let minutes: i32 = digits[2..4].parse().ok()?;
+    let seconds: i32 = digits.get(4..6).map_or(Some(0), |s| s.parse().ok())?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_kind_of_time() {
+        let date = NaiveDate::from_ymd_opt(2023, 7, 4).unwrap();
+        let date_time = date.and_hms_opt(9, 30, 0).unwrap();
+
+        assert_eq!(
+            Some(IcsTime::Date(date)),
+            IcsTime::parse_value("20230704", None)
+        );
+        assert_eq!(
+            Some(IcsTime::Floating(date_time)),
+            IcsTime::parse_value("20230704T093000", None)
+        );
+        assert_eq!(
+            Some(IcsTime::Utc(date_time)),
+            IcsTime::parse_value("20230704T093000Z", Some("Europe/Paris"))
+        );
+        assert_eq!(
+            Some(IcsTime::Zoned(date_time, "Europe/Paris".to_string())),
+            IcsTime::parse_value("20230704T093000", Some("Europe/Paris"))
+        );
+        assert_eq!(None, IcsTime::parse_value("July 4th", None));
+    }
+
+    #[test]
+    fn parses_durations_and_offsets() {
+        assert_eq!(Some(Duration::minutes(90)), parse_duration("PT1H30M"));
+        assert_eq!(Some(Duration::days(15)), parse_duration("P2W1D"));
+        assert_eq!(Some(Duration::minutes(-15)), parse_duration("-PT15M"));
+        assert_eq!(None, parse_duration("PT1H30"));
+
+        assert_eq!(FixedOffset::west_opt(5 * 3600), parse_offset("-0500"));
+        assert_eq!(
+            FixedOffset::east_opt(5 * 3600 + 30 * 60),
+            parse_offset("+053000")
+        );
+        assert_eq!(None, parse_offset("0500"));
+    }
+}
@@ -0,0 +1,102 @@
+use super::tokens::*;
+use secret_service::{Collection, EncryptionType, SecretService};
+use std::collections::HashMap;
+
+const APPLICATION: &str = "cast-schedule";
+
+/// Keeps tokens in the desktop keyring (GNOME Keyring, KWallet, KeePassXC, ...)
+/// through the freedesktop Secret Service API on the session bus.
+/// A locked keyring asks the user to unlock it, which it normally is once they've logged in.
+#[derive(Debug, Clone)]
+pub struct SecretServiceTokenStorage {
+    account: String,
+}
+
+impl SecretServiceTokenStorage {
+    /// Tokens are saved under the attributes `application=cast-schedule` and `account=<account>`
+    pub fn new(account: &str) -> SecretServiceTokenStorage {
+        SecretServiceTokenStorage {
+            account: account.to_string(),
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("{} {} tokens", APPLICATION, self.account)
+    }
+
+    fn attributes(&self) -> HashMap<&str, &str> {
+        HashMap::from([
+            ("application", APPLICATION),
+            ("account", self.account.as_str()),
+        ])
+    }
+
+    /// Tokens are encrypted on their way over the bus, so other programs on it can't read them
+    async fn connect(&self) -> Result<SecretService<'static>, secret_service::Error> {
+        SecretService::connect(EncryptionType::Dh).await
+    }
+
+    async fn find_secret(&self) -> Result<Option<Vec<u8>>, secret_service::Error> {
+        let service = self.connect().await?;
+        let collection = unlocked_default_collection(&service).await?;
+        match collection.search_items(self.attributes()).await?.first() {
+            Some(item) => Ok(Some(item.get_secret().await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl TokenStorage for SecretServiceTokenStorage {
+    async fn load(&self) -> Option<StoredToken> {
+        let secret = match self.find_secret().await {
+            Ok(secret) => secret?,
+            Err(e) => {
+                eprintln!("could not read tokens from the keyring: {:?}", e);
+                return None;
+            }
+        };
+        match serde_json::from_slice(&secret) {
+            Ok(token) => Some(token),
+            Err(e) => {
+                eprintln!("ignoring unreadable tokens in the keyring: {:?}", e);
+                None
+            }
+        }
+    }
+
+    async fn save(&self, token: &StoredToken) -> Result<(), TokenStoreError> {
+        let secret = serde_json::to_vec(token)?;
+        let service = self.connect().await?;
+        let collection = unlocked_default_collection(&service).await?;
+        // Replacing means there's only ever one item with our attributes
+        collection
+            .create_item(
+                &self.label(),
+                self.attributes(),
+                &secret,
+                true,
+                "application/json",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), TokenStoreError> {
+        let service = self.connect().await?;
+        let collection = unlocked_default_collection(&service).await?;
+        for item in collection.search_items(self.attributes()).await? {
+            item.delete().await?;
+        }
+        Ok(())
+    }
+}
+
+async fn unlocked_default_collection<'s>(
+    service: &'s SecretService<'_>,
+) -> Result<Collection<'s>, secret_service::Error> {
+    let collection = service.get_default_collection().await?;
+    if collection.is_locked().await? {
+        collection.unlock().await?;
+    }
+    Ok(collection)
+}
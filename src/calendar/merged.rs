@@ -0,0 +1,505 @@
+use super::caching::CachingCalendar;
+use super::caldav::CalDavCalendar;
+use super::calendar::*;
+use super::command::CommandCalendar;
+use super::errors::*;
+use super::google::GoogleCalendar;
+use super::holidays::HolidayCalendar;
+use super::ics::IcsCalendar;
+use super::microsoft::MicrosoftCalendar;
+use crate::model::*;
+use async_std::sync::Mutex;
+use chrono::prelude::*;
+use futures_util::future::join_all;
+use std::collections::{HashMap, HashSet};
+
+/// Any of the calendars this crate can read, so different kinds can be merged together.
+/// The ones that carry a login are much bigger than the rest, so they're boxed.
+pub enum AnyCalendar {
+    Google(Box<GoogleCalendar>),
+    Microsoft(Box<MicrosoftCalendar>),
+    Ics(IcsCalendar),
+    CalDav(CalDavCalendar),
+    Command(CommandCalendar),
+}
+
+impl Calendar for AnyCalendar {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        match self {
+            AnyCalendar::Google(calendar) => calendar.get_events_on(when).await,
+            AnyCalendar::Microsoft(calendar) => calendar.get_events_on(when).await,
+            AnyCalendar::Ics(calendar) => calendar.get_events_on(when).await,
+            AnyCalendar::CalDav(calendar) => calendar.get_events_on(when).await,
+            AnyCalendar::Command(calendar) => calendar.get_events_on(when).await,
+        }
+    }
+
+    async fn get_changes(
+        &self,
+        when: &DefiniteTimeRange<Local>,
+        sync_tokens: &HashMap<String, String>,
+    ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+        match self {
+            AnyCalendar::Google(calendar) => calendar.get_changes(when, sync_tokens).await,
+            AnyCalendar::Microsoft(calendar) => calendar.get_changes(when, sync_tokens).await,
+            AnyCalendar::Ics(calendar) => calendar.get_changes(when, sync_tokens).await,
+            AnyCalendar::CalDav(calendar) => calendar.get_changes(when, sync_tokens).await,
+            AnyCalendar::Command(calendar) => calendar.get_changes(when, sync_tokens).await,
+        }
+    }
+
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        match self {
+            AnyCalendar::Google(calendar) => calendar.get_busy_times(when).await,
+            AnyCalendar::Microsoft(calendar) => calendar.get_busy_times(when).await,
+            AnyCalendar::Ics(calendar) => calendar.get_busy_times(when).await,
+            AnyCalendar::CalDav(calendar) => calendar.get_busy_times(when).await,
+            AnyCalendar::Command(calendar) => calendar.get_busy_times(when).await,
+        }
+    }
+}
+
+/// A calendar to merge with the others. The ones fetched from elsewhere are cached in case that fails;
+/// bundled holidays can't fail to be fetched, so they aren't.
+pub enum MergeableCalendar {
+    Cached(CachingCalendar<AnyCalendar>),
+    Holidays(HolidayCalendar),
+}
+
+impl Calendar for MergeableCalendar {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        match self {
+            MergeableCalendar::Cached(calendar) => calendar.get_events_on(when).await,
+            MergeableCalendar::Holidays(calendar) => calendar.get_events_on(when).await,
+        }
+    }
+
+    async fn get_cached_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<CachedEvents, CalendarError> {
+        match self {
+            MergeableCalendar::Cached(calendar) => calendar.get_cached_events_on(when).await,
+            MergeableCalendar::Holidays(calendar) => calendar.get_cached_events_on(when).await,
+        }
+    }
+
+    async fn get_changes(
+        &self,
+        when: &DefiniteTimeRange<Local>,
+        sync_tokens: &HashMap<String, String>,
+    ) -> Option<Result<Vec<CalendarChanges>, CalendarError>> {
+        match self {
+            MergeableCalendar::Cached(calendar) => calendar.get_changes(when, sync_tokens).await,
+            MergeableCalendar::Holidays(calendar) => calendar.get_changes(when, sync_tokens).await,
+        }
+    }
+
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        match self {
+            MergeableCalendar::Cached(calendar) => calendar.get_busy_times(when).await,
+            MergeableCalendar::Holidays(calendar) => calendar.get_busy_times(when).await,
+        }
+    }
+}
+
+/// Events from several calendars at once, with meetings that are in more than one of them shown once
+pub struct MergedCalendar<C: Calendar> {
+    calendars: Vec<(String, C)>,
+    /// Names of the calendars that have to be logged in to again, found when fetching events
+    logged_out: Mutex<Vec<String>>,
+}
+
+/// What a merged calendar could fetch, and which of its calendars it couldn't fetch from
+#[derive(Debug, Default)]
+pub struct MergedEvents {
+    pub events: Vec<CalendarEvent>,
+    pub failures: Vec<(String, CalendarError)>,
+    /// When the longest-ago fetched of the calendars that are shown from a cache was fetched
+    pub stale_since: Option<DateTime<Utc>>,
+}
+
+impl<C: Calendar> MergedCalendar<C> {
+    pub fn new() -> MergedCalendar<C> {
+        MergedCalendar {
+            calendars: Vec::new(),
+            logged_out: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Adds a calendar, named for error messages and for its events' source if they don't have one.
+    /// When the same event is in more than one calendar, the one added first wins.
+    pub fn with_calendar(mut self, name: &str, calendar: C) -> MergedCalendar<C> {
+        self.calendars.push((name.to_string(), calendar));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calendars.is_empty()
+    }
+
+    /// Swaps in a calendar for the one with the same name, e.g. after logging in to it again
+    pub fn replace_calendar(&mut self, name: &str, calendar: C) {
+        if let Some((_, replaced)) = self.calendars.iter_mut().find(|(n, _)| n == name) {
+            *replaced = calendar;
+        }
+    }
+
+    pub fn remove_calendar(&mut self, name: &str) {
+        self.calendars.retain(|(n, _)| n != name);
+    }
+
+    /// Which calendars were logged out since this was last asked, so they can be logged in to again.
+    /// The rest of the calendars are still shown in the meantime.
+    pub async fn take_logged_out(&self) -> Vec<String> {
+        std::mem::take(&mut *self.logged_out.lock().await)
+    }
+
+    /// Fetches from every calendar at once, keeping whatever could be fetched
+    pub async fn get_merged_events_on(&self, when: DefiniteTimeRange<Local>) -> MergedEvents {
+        let results = join_all(
+            self.calendars
+                .iter()
+                .map(|(_, calendar)| calendar.get_cached_events_on(when.clone())),
+        )
+        .await;
+
+        let mut merged = MergedEvents::default();
+        for ((name, _), result) in self.calendars.iter().zip(results) {
+            match result {
+                Ok(cached) => {
+                    merged.events.extend(
+                        cached
+                            .events
+                            .into_iter()
+                            .map(|event| with_source(event, name)),
+                    );
+                    merged.stale_since = merged
+                        .stale_since
+                        .into_iter()
+                        .chain(cached.stale_since)
+                        .min();
+                }
+                Err(e) => merged.failures.push((name.clone(), e)),
+            }
+        }
+        let mut logged_out = self.logged_out.lock().await;
+        for (name, e) in &merged.failures {
+            if e.kind() == ErrorKind::LoggedOut && !logged_out.contains(name) {
+                logged_out.push(name.clone());
+            }
+        }
+        merged.events = dedupe(merged.events);
+        merged
+    }
+}
+
+impl<C: Calendar> Default for MergedCalendar<C> {
+    fn default() -> Self {
+        MergedCalendar::new()
+    }
+}
+
+impl<C: Calendar> Calendar for MergedCalendar<C> {
+    async fn get_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        Ok(self.get_cached_events_on(when).await?.events)
+    }
+
+    /// Only fails if every calendar did; otherwise the ones that failed are left out, and named.
+    /// The events are as stale as the stalest of the calendars that were shown from a cache.
+    async fn get_cached_events_on(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<CachedEvents, CalendarError> {
+        let mut merged = self.get_merged_events_on(when).await;
+        if !self.is_empty() && merged.failures.len() == self.calendars.len() {
+            return Err(merged.failures.remove(0).1);
+        }
+        for (name, e) in &merged.failures {
+            eprintln!(
+                "could not fetch events from {}; showing the other calendars: {}",
+                name, e
+            );
+        }
+        Ok(CachedEvents {
+            events: merged.events,
+            stale_since: merged.stale_since,
+            failed_calendars: merged.failures.into_iter().map(|(name, _)| name).collect(),
+        })
+    }
+
+    /// Busy whenever any of the calendars that could be fetched is
+    async fn get_busy_times(
+        &self,
+        when: DefiniteTimeRange<Local>,
+    ) -> Result<BusyTimes, CalendarError> {
+        let results = join_all(
+            self.calendars
+                .iter()
+                .map(|(_, calendar)| calendar.get_busy_times(when.clone())),
+        )
+        .await;
+        let mut busy = BusyTimes::default();
+        let mut failures = Vec::new();
+        for ((name, _), result) in self.calendars.iter().zip(results) {
+            match result {
+                Ok(times) => busy = busy.merged_with(times),
+                Err(e) => failures.push((name, e)),
+            }
+        }
+        if !self.is_empty() && failures.len() == self.calendars.len() {
+            return Err(failures.remove(0).1);
+        }
+        for (name, e) in &failures {
+            eprintln!(
+                "could not get busy times from {}; using the other calendars: {}",
+                name, e
+            );
+        }
+        Ok(busy)
+    }
+}
+
+fn with_source(event: CalendarEvent, name: &str) -> CalendarEvent {
+    match event.source {
+        Some(_) => event,
+        None => CalendarEvent {
+            source: Some(EventSource {
+                calendar_id: name.to_string(),
+                calendar_name: name.to_string(),
+                background_color: None,
+                ..Default::default()
+            }),
+            ..event
+        },
+    }
+}
+
+/// What makes two events from different calendars the same event
+#[derive(PartialEq, Eq, Hash)]
+enum EventKey {
+    /// Occurrences of a repeating event share a UID, so the start tells them apart
+    Uid(String, Option<DateTime<Local>>),
+    Times(String, Option<DateTime<Local>>, Option<DateTime<Local>>),
+}
+
+impl EventKey {
+    fn of(event: &CalendarEvent) -> EventKey {
+        match &event.ical_uid {
+            Some(uid) => EventKey::Uid(uid.clone(), event.times.start),
+            None => EventKey::Times(
+                event.summary.trim().to_lowercase(),
+                event.times.start,
+                event.times.end,
+            ),
+        }
+    }
+}
+
+/// Keeps the first of each event that appears more than once
+fn dedupe(events: Vec<CalendarEvent>) -> Vec<CalendarEvent> {
+    let mut seen = HashSet::new();
+    events
+        .into_iter()
+        .filter(|event| seen.insert(EventKey::of(event)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    /// A title, a UID, and an hour of the day to start at
+    type FixedEvent = (&'static str, Option<&'static str>, i64);
+
+    /// A calendar that always returns the same thing
+    struct FixedCalendar(Result<Vec<FixedEvent>, ErrorKind>);
+
+    impl Calendar for FixedCalendar {
+        async fn get_events_on(
+            &self,
+            when: DefiniteTimeRange<Local>,
+        ) -> Result<Vec<CalendarEvent>, CalendarError> {
+            let events = self
+                .0
+                .as_ref()
+                .map_err(|&kind| CalendarError::new(kind, "could not fetch fixed events"))?;
+            Ok(events
+                .iter()
+                .map(|(summary, uid, hour)| CalendarEvent {
+                    summary: summary.to_string(),
+                    ical_uid: uid.map(str::to_string),
+                    times: IndefiniteTimeRange {
+                        start: Some(when.start + Duration::hours(*hour)),
+                        end: Some(when.start + Duration::hours(*hour + 1)),
+                    },
+                    ..Default::default()
+                })
+                .collect())
+        }
+    }
+
+    fn today() -> DefiniteTimeRange<Local> {
+        let start = Local.with_ymd_and_hms(2023, 9, 5, 0, 0, 0).unwrap();
+        DefiniteTimeRange {
+            start,
+            end: start + Duration::days(1),
+        }
+    }
+
+    #[async_std::test]
+    async fn merges_calendars_without_duplicates() {
+        let calendar = MergedCalendar::new()
+            .with_calendar(
+                "work",
+                FixedCalendar(Ok(vec![
+                    ("Standup", Some("standup"), 9),
+                    ("Standup", Some("standup"), 10),
+                    ("Lunch", None, 12),
+                ])),
+            )
+            .with_calendar(
+                "personal",
+                FixedCalendar(Ok(vec![
+                    ("Standup (copy)", Some("standup"), 9),
+                    ("lunch ", None, 12),
+                    ("Gym", None, 18),
+                ])),
+            );
+
+        let events = calendar.get_events_on(today()).await.unwrap();
+
+        let summaries: Vec<&str> = events.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(vec!["Standup", "Standup", "Lunch", "Gym"], summaries);
+        let sources: Vec<&str> = events
+            .iter()
+            .map(|e| e.source.as_ref().unwrap().calendar_name.as_str())
+            .collect();
+        assert_eq!(vec!["work", "work", "work", "personal"], sources);
+    }
+
+    #[async_std::test]
+    async fn keeps_what_it_can_when_a_calendar_fails() {
+        let calendar = MergedCalendar::new()
+            .with_calendar("work", FixedCalendar(Err(ErrorKind::FetchError)))
+            .with_calendar("personal", FixedCalendar(Ok(vec![("Gym", None, 18)])));
+
+        let merged = calendar.get_merged_events_on(today()).await;
+        assert_eq!(1, merged.events.len());
+        assert_eq!(
+            vec!["work"],
+            merged
+                .failures
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["work"],
+            calendar
+                .get_cached_events_on(today())
+                .await
+                .unwrap()
+                .failed_calendars
+        );
+
+        let calendar =
+            MergedCalendar::new().with_calendar("work", FixedCalendar(Err(ErrorKind::FetchError)));
+        assert_eq!(
+            ErrorKind::FetchError,
+            calendar.get_events_on(today()).await.unwrap_err().kind()
+        );
+    }
+
+    #[async_std::test]
+    async fn remembers_logged_out_calendars_until_they_are_replaced() {
+        let mut calendar = MergedCalendar::new()
+            .with_calendar("Google Calendar", FixedCalendar(Err(ErrorKind::LoggedOut)))
+            .with_calendar("work", FixedCalendar(Err(ErrorKind::FetchError)))
+            .with_calendar("personal", FixedCalendar(Ok(vec![("Gym", None, 18)])));
+
+        assert_eq!(1, calendar.get_events_on(today()).await.unwrap().len());
+        calendar.get_events_on(today()).await.unwrap();
+        assert_eq!(vec!["Google Calendar"], calendar.take_logged_out().await);
+        assert!(calendar.take_logged_out().await.is_empty());
+
+        calendar.replace_calendar(
+            "Google Calendar",
+            FixedCalendar(Ok(vec![("Standup", None, 9)])),
+        );
+        let events = calendar.get_events_on(today()).await.unwrap();
+        assert_eq!(
+            vec!["Standup", "Gym"],
+            events
+                .iter()
+                .map(|e| e.summary.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(calendar.take_logged_out().await.is_empty());
+    }
+
+    /// A calendar that's shown from a cache fetched this many hours ago, or fresh if there isn't one
+    struct CachedCalendar(Option<i64>);
+
+    impl Calendar for CachedCalendar {
+        async fn get_events_on(
+            &self,
+            when: DefiniteTimeRange<Local>,
+        ) -> Result<Vec<CalendarEvent>, CalendarError> {
+            Ok(self.get_cached_events_on(when).await?.events)
+        }
+
+        async fn get_cached_events_on(
+            &self,
+            _: DefiniteTimeRange<Local>,
+        ) -> Result<CachedEvents, CalendarError> {
+            Ok(CachedEvents {
+                events: Vec::new(),
+                stale_since: self
+                    .0
+                    .map(|hours| today().start.with_timezone(&Utc) - Duration::hours(hours)),
+                failed_calendars: Vec::new(),
+            })
+        }
+    }
+
+    #[async_std::test]
+    async fn is_as_stale_as_the_stalest_cached_calendar() {
+        let calendar = MergedCalendar::new()
+            .with_calendar("work", CachedCalendar(Some(1)))
+            .with_calendar("personal", CachedCalendar(None))
+            .with_calendar("team", CachedCalendar(Some(3)));
+
+        let cached = calendar.get_cached_events_on(today()).await.unwrap();
+        assert_eq!(
+            Some(today().start.with_timezone(&Utc) - Duration::hours(3)),
+            cached.stale_since
+        );
+
+        let calendar = MergedCalendar::new().with_calendar("personal", CachedCalendar(None));
+        assert_eq!(
+            None,
+            calendar
+                .get_cached_events_on(today())
+                .await
+                .unwrap()
+                .stale_since
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use crate::calendar::oauth::OAuthSession;
+use crate::calendar::CalendarError;
+use crate::model::*;
+use chrono::Local;
+use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+pub const GRAPH_API: &str = "https://graph.microsoft.com/v1.0/";
+/// Asks for every time in UTC, instead of in each event's own (often Windows-named) time zone
+const PREFER_UTC: &str = "outlook.timezone=\"UTC\"";
+
+/// A client for the calendar parts of the Microsoft Graph REST API
+pub struct GraphApi {
+    http: reqwest::Client,
+    base_url: Url,
+    session: OAuthSession,
+}
+
+/// One page of a Graph collection. The next page is at `next_link`, query and all.
+#[derive(Debug, Deserialize)]
+pub struct GraphPage<T> {
+    #[serde(default = "Vec::new")]
+    pub value: Vec<T>,
+    #[serde(rename = "@odata.nextLink")]
+    pub next_link: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphCalendar {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// `#RRGGBB`, or empty if the user hasn't picked a color
+    #[serde(default)]
+    pub hex_color: String,
+    #[serde(default)]
+    pub is_default_calendar: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphUser {
+    pub mail: Option<String>,
+    pub user_principal_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GraphEvent {
+    pub id: String,
+    #[serde(rename = "iCalUId")]
+    pub ical_uid: Option<String>,
+    pub subject: Option<String>,
+    pub body_preview: Option<String>,
+    pub start: Option<GraphDateTime>,
+    pub end: Option<GraphDateTime>,
+    pub is_all_day: bool,
+    pub is_cancelled: bool,
+    /// `free`, `tentative`, `busy`, `oof`, `workingElsewhere` or `unknown`
+    pub show_as: Option<String>,
+    /// `normal`, `personal`, `private` or `confidential`
+    pub sensitivity: Option<String>,
+    pub location: Option<GraphLocation>,
+    pub organizer: Option<GraphRecipient>,
+    pub attendees: Vec<GraphAttendee>,
+    /// How the calendar's owner responded
+    pub response_status: Option<GraphResponse>,
+    pub online_meeting: Option<GraphOnlineMeeting>,
+    pub online_meeting_url: Option<String>,
+    pub web_link: Option<String>,
+}
+
+/// A wall-clock time and the name of its time zone
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphDateTime {
+    pub date_time: String,
+    pub time_zone: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphLocation {
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphRecipient {
+    pub email_address: GraphEmailAddress,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GraphEmailAddress {
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphAttendee {
+    pub email_address: GraphEmailAddress,
+    pub status: Option<GraphResponse>,
+    /// `required`, `optional` or `resource`
+    #[serde(rename = "type")]
+    pub attendee_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GraphResponse {
+    /// `none`, `organizer`, `tentativelyAccepted`, `accepted`, `declined` or `notResponded`
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphOnlineMeeting {
+    pub join_url: Option<String>,
+    /// A phone number to join by
+    pub toll_number: Option<String>,
+    /// The PIN to enter after dialing in
+    pub conference_id: Option<String>,
+}
+
+impl GraphApi {
+    pub fn new(session: OAuthSession) -> GraphApi {
+        GraphApi {
+            http: reqwest::Client::new(),
+            base_url: Url::parse(GRAPH_API).unwrap(),
+            session,
+        }
+    }
+
+    pub fn with_base_url(self, base_url: Url) -> GraphApi {
+        GraphApi { base_url, ..self }
+    }
+
+    fn url(&self, path: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("API base URL must be a hierarchical URL")
+            .pop_if_empty()
+            .extend(path);
+        url
+    }
+
+    pub async fn me(&self) -> Result<GraphUser, CalendarError> {
+        self.get(self.url(&["me"])).await
+    }
+
+    /// The first page of the user's calendars
+    pub async fn list_calendars_page(&self) -> Result<GraphPage<GraphCalendar>, CalendarError> {
+        self.get(self.url(&["me", "calendars"])).await
+    }
+
+    /// The first page of events in a calendar that overlap `when`, with repeating meetings expanded
+    pub async fn calendar_view_page(
+        &self,
+        calendar_id: &str,
+        when: &DefiniteTimeRange<Local>,
+        page_size: usize,
+    ) -> Result<GraphPage<GraphEvent>, CalendarError> {
+        let mut url = self.url(&["me", "calendars", calendar_id, "calendarView"]);
+        url.query_pairs_mut()
+            .append_pair("startDateTime", &when.start.to_rfc3339())
+            .append_pair("endDateTime", &when.end.to_rfc3339())
+            .append_pair("$orderby", "start/dateTime")
+            .append_pair("$top", &page_size.to_string());
+        self.get(url).await
+    }
+
+    /// The page after one that had a `next_link`
+    pub async fn next_page<T: DeserializeOwned>(
+        &self,
+        next_link: &str,
+    ) -> Result<GraphPage<T>, CalendarError> {
+        let url = Url::parse(next_link).map_err(|e| {
+            CalendarError::fetch(format!(
+                "invalid next page link {:?} from microsoft graph",
+                next_link
+            ))
+            .with_cause(e)
+        })?;
+        self.get(url).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: Url) -> Result<T, CalendarError> {
+        let access_token = self.session.access_token().await?;
+        let mut response = self.send_get(&url, &access_token).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            // The token was revoked or expired early, so a fresh one might still work
+            let access_token = self
+                .session
+                .access_token_after_rejection(&access_token)
+                .await?;
+            response = self.send_get(&url, &access_token).await?;
+        }
+
+        let context = format!("could not fetch {} from microsoft graph", url.path());
+        match response.status() {
+            StatusCode::OK => {}
+            status @ StatusCode::UNAUTHORIZED => {
+                return Err(CalendarError::logged_out(context).with_status(status))
+            }
+            _ => return Err(CalendarError::from_response(context, &response)),
+        }
+
+        response.json().await.map_err(|e| {
+            CalendarError::fetch(format!(
+                "could not parse {} from microsoft graph",
+                url.path()
+            ))
+            .with_cause(e)
+        })
+    }
+
+    async fn send_get(
+        &self,
+        url: &Url,
+        access_token: &str,
+    ) -> Result<reqwest::Response, CalendarError> {
+        self.http
+            .get(url.clone())
+            .bearer_auth(access_token)
+            .header("Prefer", PREFER_UTC)
+            .send()
+            .await
+            .map_err(|e| {
+                CalendarError::fetch(format!(
+                    "could not fetch {} from microsoft graph",
+                    url.path()
+                ))
+                .with_cause(e)
+            })
+    }
+}
@@ -0,0 +1,574 @@
+use super::calendar::*;
+use super::conference::find_conference_link;
+use super::errors::*;
+use crate::model::*;
+use async_std::sync::Mutex;
+use chrono::prelude::*;
+use chrono::Duration;
+use chrono_tz::Tz;
+use futures_util::future::join_all;
+use std::time::Instant;
+
+mod api;
+pub use api::*;
+
+mod provider;
+pub use provider::*;
+
+/// How many events to ask for in each page of results
+const PAGE_SIZE: usize = 100;
+
+/// Outlook / Microsoft 365 calendars, read through Microsoft Graph
+pub struct MicrosoftCalendar {
+    api: GraphApi,
+    max_events: usize,
+    selectors: Vec<CalendarSelector>,
+    /// The signed-in user's email address, to find them among each event's attendees
+    user_email: Mutex<Option<String>>,
+    /// The selected calendars, and when they were listed
+    selected: Mutex<Option<(Instant, Vec<GraphCalendar>)>>,
+}
+
+impl MicrosoftCalendar {
+    pub fn new(api: GraphApi) -> MicrosoftCalendar {
+        MicrosoftCalendar {
+            api,
+            max_events: DEFAULT_MAX_EVENTS,
+            selectors: Vec::new(),
+            user_email: Mutex::new(None),
+            selected: Mutex::new(None),
+        }
+    }
+
+    /// The most events to fetch from one calendar for one request; see [`reached_max_events`]
+    pub fn with_max_events(self, max_events: usize) -> MicrosoftCalendar {
+        MicrosoftCalendar { max_events, ..self }
+    }
+
+    /// Which of the user's calendars to show. With no selectors, only the default calendar is shown.
+    pub fn with_calendars(self, selectors: Vec<CalendarSelector>) -> MicrosoftCalendar {
+        MicrosoftCalendar { selectors, ..self }
+    }
+
+    pub async fn list_calendars(&self) -> Result<Vec<GraphCalendar>, CalendarError> {
+        let mut page = self.api.list_calendars_page().await?;
+        let mut calendars = Vec::new();
+        loop {
+            calendars.append(&mut page.value);
+            match page.next_link {
+                Some(next_link) => page = self.api.next_page(&next_link).await?,
+                None => return Ok(calendars),
+            }
+        }
+    }
+
+    /// The calendars to show, which are only listed again once they're an hour old
+    /// or one of them has gone away
+    async fn selected_calendars(&self) -> Result<Vec<GraphCalendar>, CalendarError> {
+        let mut selected = self.selected.lock().await;
+        if let Some((listed_at, calendars)) = selected.as_ref() {
+            if listed_at.elapsed() < CALENDAR_LIST_MAX_AGE {
+                return Ok(calendars.clone());
+            }
+        }
+        let calendars: Vec<GraphCalendar> = self
+            .list_calendars()
+            .await?
+            .into_iter()
+            .filter(|calendar| {
+                if self.selectors.is_empty() {
+                    return calendar.is_default_calendar;
+                }
+                self.selectors
+                    .iter()
+                    .any(|selector| selector.matches(&calendar.id, &calendar.name))
+            })
+            .collect();
+        *selected = Some((Instant::now(), calendars.clone()));
+        Ok(calendars)
+    }
+
+    async fn user_email(&self) -> Result<String, CalendarError> {
+        let mut user_email = self.user_email.lock().await;
+        if let Some(email) = user_email.as_ref() {
+            return Ok(email.clone());
+        }
+        let me = self.api.me().await?;
+        let email = me.mail.or(me.user_principal_name).unwrap_or_default();
+        *user_email = Some(email.clone());
+        Ok(email)
+    }
+
+    async fn get_calendar_events(
+        &self,
+        calendar: &GraphCalendar,
+        date: &DefiniteTimeRange<Local>,
+        user_email: &str,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let mut page = self
+            .api
+            .calendar_view_page(&calendar.id, date, PAGE_SIZE)
+            .await?;
+        let mut g_events = Vec::new();
+        loop {
+            g_events.append(&mut page.value);
+            let Some(next_link) = page.next_link else {
+                break;
+            };
+            if reached_max_events(&mut g_events, self.max_events, &calendar.name) {
+                break;
+            }
+            page = self.api.next_page(&next_link).await?;
+        }
+
+        let source = EventSource {
+            calendar_id: calendar.id.clone(),
+            calendar_name: calendar.name.clone(),
+            background_color: Color::from_hex(&calendar.hex_color),
+            ..Default::default()
+        };
+        Ok(g_events
+            .into_iter()
+            .map(|g_event| CalendarEvent {
+                source: Some(source.clone()),
+                ..parse_event(g_event, user_email)
+            })
+            .collect())
+    }
+}
+
+impl Calendar for MicrosoftCalendar {
+    /// Only fails if every selected calendar did; otherwise the ones that failed are left out
+    async fn get_events_on(
+        &self,
+        date: DefiniteTimeRange<Local>,
+    ) -> Result<Vec<CalendarEvent>, CalendarError> {
+        let user_email = self.user_email().await?;
+        let calendars = self.selected_calendars().await?;
+        let results = join_all(
+            calendars
+                .iter()
+                .map(|calendar| self.get_calendar_events(calendar, &date, &user_email)),
+        )
+        .await;
+        if any_calendar_gone(&results) {
+            *self.selected.lock().await = None;
+        }
+        Ok(
+            unless_every_calendar_failed(calendars.iter().map(|calendar| &calendar.name), results)?
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect(),
+        )
+    }
+}
+
+fn parse_event(g_event: GraphEvent, user_email: &str) -> CalendarEvent {
+    let start = g_event.start.as_ref().and_then(parse_date_time);
+    let end = g_event.end.as_ref().and_then(parse_date_time);
+    // All-day events run from midnight to midnight wherever they're looked at
+    let all_day = match (g_event.is_all_day, start) {
+        (true, Some(start)) => Some(DateRange {
+            start: start.date(),
+            end: end.map_or(start.date() + Duration::days(1), |end| end.date()),
+        }),
+        _ => None,
+    };
+    let description = g_event.body_preview.filter(|s| !s.is_empty());
+    let location = g_event
+        .location
+        .and_then(|l| l.display_name)
+        .filter(|s| !s.is_empty());
+    let conference_link = parse_conference_link(
+        g_event.online_meeting,
+        g_event.online_meeting_url,
+        location.as_deref(),
+        description.as_deref(),
+    );
+    CalendarEvent {
+        id: Some(g_event.id.clone()).filter(|id| !id.is_empty()),
+        times: match &all_day {
+            Some(dates) => IndefiniteTimeRange {
+                start: start_of_date(dates.start),
+                end: start_of_date(dates.end),
+            },
+            None => IndefiniteTimeRange {
+                start: g_event.start.as_ref().and_then(to_local),
+                end: g_event.end.as_ref().and_then(to_local),
+            },
+        },
+        all_day,
+        summary: g_event.subject.unwrap_or_default(),
+        description,
+        location,
+        organizer: g_event.organizer.map(|o| parse_person(o.email_address)),
+        attendees: parse_attendees(
+            g_event.attendees,
+            g_event.response_status.as_ref(),
+            user_email,
+        ),
+        visibility: match g_event.sensitivity.as_deref() {
+            Some("personal" | "private") => Visibility::Private,
+            Some("confidential") => Visibility::Confidential,
+            _ => Visibility::Default,
+        },
+        transparency: match g_event.show_as.as_deref() {
+            // Working elsewhere is still working, so the time is free for meetings
+            Some("free" | "workingElsewhere") => Transparency::Transparent,
+            _ => Transparency::Opaque,
+        },
+        // Organizers still see occurrences they've cancelled
+        status: match g_event.is_cancelled {
+            true => EventStatus::Cancelled,
+            false => EventStatus::Confirmed,
+        },
+        kind: match g_event.show_as.as_deref() {
+            Some("oof") => EventKind::OutOfOffice,
+            _ => EventKind::Default,
+        },
+        color_id: None,
+        color: None,
+        html_link: g_event.web_link,
+        conference_link,
+        ical_uid: g_event.ical_uid.filter(|s| !s.is_empty()),
+        source: None,
+    }
+}
+
+/// Teams meetings say how to join; other services' links are only in the location or the body
+fn parse_conference_link(
+    meeting: Option<GraphOnlineMeeting>,
+    meeting_url: Option<String>,
+    location: Option<&str>,
+    description: Option<&str>,
+) -> Option<ConferenceLink> {
+    let meeting = meeting.unwrap_or_default();
+    let Some(url) = meeting.join_url.or(meeting_url).filter(|s| !s.is_empty()) else {
+        return [location, description]
+            .into_iter()
+            .flatten()
+            .find_map(find_conference_link);
+    };
+    let link = ConferenceLink::new(&url);
+    Some(match meeting.toll_number.filter(|s| !s.is_empty()) {
+        Some(number) => link.with_dial_in(&number, meeting.conference_id.as_deref()),
+        None => link,
+    })
+}
+
+/// Graph sends times like `2023-09-05T13:00:00.0000000`, with the time zone separately
+fn parse_date_time(time: &GraphDateTime) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(&time.date_time, "%Y-%m-%dT%H:%M:%S%.f").ok()
+}
+
+fn to_local(time: &GraphDateTime) -> Option<DateTime<Local>> {
+    let naive = parse_date_time(time)?;
+    match time.time_zone.as_str() {
+        "UTC" => Some(Utc.from_utc_datetime(&naive).with_timezone(&Local)),
+        time_zone => match time_zone.parse::<Tz>() {
+            Ok(tz) => tz
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|t| t.with_timezone(&Local)),
+            Err(_) => {
+                eprintln!(
+                    "unknown time zone {:?} from microsoft graph; using local time",
+                    time_zone
+                );
+                Local.from_local_datetime(&naive).earliest()
+            }
+        },
+    }
+}
+
+fn start_of_date(date: NaiveDate) -> Option<DateTime<Local>> {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+}
+
+fn parse_person(email_address: GraphEmailAddress) -> Person {
+    Person {
+        email: email_address.address.unwrap_or_default(),
+        display_name: email_address.name.filter(|s| !s.is_empty()),
+    }
+}
+
+fn parse_response(response: &GraphResponse) -> ResponseStatus {
+    match response.response.as_str() {
+        "organizer" | "accepted" => ResponseStatus::Accepted,
+        "tentativelyAccepted" => ResponseStatus::Tentative,
+        "declined" => ResponseStatus::Declined,
+        _ => ResponseStatus::NeedsAction,
+    }
+}
+
+/// Graph gives the user's own response on the event rather than on their attendee entry,
+/// and leaves the organizer out of the attendees, so the user is added if they're missing
+fn parse_attendees(
+    attendees: Vec<GraphAttendee>,
+    own_response: Option<&GraphResponse>,
+    user_email: &str,
+) -> Vec<Attendee> {
+    let mut attendees: Vec<Attendee> = attendees
+        .into_iter()
+        .map(|attendee| Attendee {
+            response_status: attendee
+                .status
+                .as_ref()
+                .map(parse_response)
+                .unwrap_or_default(),
+            is_self: false,
+            optional: attendee.attendee_type.as_deref() == Some("optional"),
+            person: parse_person(attendee.email_address),
+        })
+        .collect();
+
+    let Some(own_response) = own_response.filter(|r| r.response != "none") else {
+        return attendees;
+    };
+    let response_status = parse_response(own_response);
+    match attendees
+        .iter_mut()
+        .find(|a| !user_email.is_empty() && a.person.email.eq_ignore_ascii_case(user_email))
+    {
+        Some(attendee) => {
+            attendee.is_self = true;
+            attendee.response_status = response_status;
+        }
+        None => attendees.push(Attendee {
+            person: Person {
+                email: user_email.to_string(),
+                display_name: None,
+            },
+            response_status,
+            is_self: true,
+            optional: false,
+        }),
+    }
+    attendees
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::oauth::*;
+    use crate::calendar::tokens::StoredToken;
+    use crate::testing::*;
+    use reqwest::Url;
+
+    const CALENDARS: &str = r##"{
+        "@odata.context": "https://graph.microsoft.com/v1.0/$metadata#users('me')/calendars",
+        "value": [
+            {"id": "AAMkAGI2TGuLAAA=", "name": "Calendar", "color": "auto", "hexColor": "", "isDefaultCalendar": true},
+            {"id": "AAMkAGI2TGuMAAA=", "name": "United States holidays", "color": "lightGreen", "hexColor": "#87d28e", "isDefaultCalendar": false}
+        ]
+    }"##;
+
+    const ME: &str = r#"{"displayName": "Adele Vance", "mail": "AdeleV@contoso.com", "userPrincipalName": "AdeleV@contoso.com"}"#;
+
+    /// `{next}` is replaced with the link to the second page
+    const CALENDAR_VIEW_PAGE_1: &str = r#"{
+        "value": [
+            {
+                "id": "AAMkAGI2TGuLAAA-1",
+                "iCalUId": "040000008200E00074C5B7101A82E00800000000D02F",
+                "subject": "Plan summer company picnic",
+                "bodyPreview": "Let's kick-start this event planning!",
+                "isAllDay": false,
+                "isCancelled": false,
+                "showAs": "busy",
+                "sensitivity": "normal",
+                "start": {"dateTime": "2023-09-05T13:00:00.0000000", "timeZone": "UTC"},
+                "end": {"dateTime": "2023-09-05T14:00:00.0000000", "timeZone": "UTC"},
+                "location": {"displayName": "Conf Room 3"},
+                "organizer": {"emailAddress": {"name": "Dana Swope", "address": "DanaS@contoso.com"}},
+                "attendees": [
+                    {"type": "required", "status": {"response": "none", "time": "0001-01-01T00:00:00Z"}, "emailAddress": {"name": "Adele Vance", "address": "AdeleV@contoso.com"}},
+                    {"type": "optional", "status": {"response": "accepted", "time": "2023-09-01T10:00:00Z"}, "emailAddress": {"name": "Alex Wilber", "address": "AlexW@contoso.com"}}
+                ],
+                "responseStatus": {"response": "declined", "time": "2023-09-02T08:00:00Z"},
+                "isOnlineMeeting": true,
+                "onlineMeeting": {"joinUrl": "https://teams.microsoft.com/l/meetup-join/19%3ameeting", "tollNumber": "+1 323-555-0166", "conferenceId": "177513992"},
+                "webLink": "https://outlook.office365.com/owa/?itemid=AAMkAGI2TGuLAAA-1"
+            },
+            {
+                "id": "AAMkAGI2TGuLAAA-2",
+                "subject": "Standup",
+                "isAllDay": false,
+                "isCancelled": true,
+                "showAs": "busy",
+                "start": {"dateTime": "2023-09-05T15:00:00.0000000", "timeZone": "UTC"},
+                "end": {"dateTime": "2023-09-05T15:15:00.0000000", "timeZone": "UTC"},
+                "attendees": [],
+                "responseStatus": {"response": "organizer", "time": "0001-01-01T00:00:00Z"}
+            }
+        ],
+        "@odata.nextLink": "{next}"
+    }"#;
+
+    const CALENDAR_VIEW_PAGE_2: &str = r#"{
+        "value": [
+            {
+                "id": "AAMkAGI2TGuLAAA-3",
+                "subject": "Working from the Seattle office",
+                "isAllDay": true,
+                "isCancelled": false,
+                "showAs": "workingElsewhere",
+                "sensitivity": "private",
+                "start": {"dateTime": "2023-09-05T00:00:00.0000000", "timeZone": "UTC"},
+                "end": {"dateTime": "2023-09-06T00:00:00.0000000", "timeZone": "UTC"},
+                "attendees": [],
+                "responseStatus": {"response": "none", "time": "0001-01-01T00:00:00Z"}
+            }
+        ]
+    }"#;
+
+    /// Replays recorded Graph responses
+    fn graph_server() -> StubServer {
+        StubServer::start(|request| {
+            if request.header("Authorization") != Some("Bearer graph-token") {
+                return StubResponse::new(401, "");
+            }
+            let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+            let body = match path {
+                "/v1.0/me" => ME.to_string(),
+                "/v1.0/me/calendars" => CALENDARS.to_string(),
+                "/v1.0/me/calendars/AAMkAGI2TGuLAAA=/calendarView"
+                    if query.contains("$skip=100") =>
+                {
+                    CALENDAR_VIEW_PAGE_2.to_string()
+                }
+                "/v1.0/me/calendars/AAMkAGI2TGuLAAA=/calendarView" => {
+                    let next = format!(
+                        "http://{}{}?{}&$skip=100",
+                        request.header("Host").unwrap(),
+                        path,
+                        query
+                    );
+                    CALENDAR_VIEW_PAGE_1.replace("{next}", &next)
+                }
+                "/v1.0/me/calendars/AAMkAGI2TGuMAAA=/calendarView" => {
+                    return StubResponse::new(500, "")
+                }
+                _ => return StubResponse::new(404, ""),
+            };
+            StubResponse::new(200, &body).with_header("Content-Type", "application/json")
+        })
+    }
+
+    fn calendar(server: &StubServer) -> MicrosoftCalendar {
+        let client = DeviceFlowClient {
+            client_id: "client".to_string(),
+            client_secret: None,
+            device_authorization_url: server.url("/devicecode"),
+            token_url: server.url("/token"),
+            scopes: Vec::new(),
+        };
+        let token = StoredToken {
+            access_token: "graph-token".to_string(),
+            refresh_token: None,
+            expires_at: Utc::now() + Duration::hours(1),
+        };
+        let api = GraphApi::new(OAuthSession::new(client, token, None))
+            .with_base_url(Url::parse(&server.url("/v1.0/")).unwrap());
+        MicrosoftCalendar::new(api)
+    }
+
+    #[async_std::test]
+    async fn reads_every_page_of_the_default_calendar() {
+        let server = graph_server();
+        let events = calendar(&server)
+            .get_events_on(september_5th())
+            .await
+            .unwrap();
+
+        let summaries: Vec<&str> = events.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(
+            vec![
+                "Plan summer company picnic",
+                "Standup",
+                "Working from the Seattle office"
+            ],
+            summaries
+        );
+        assert_eq!(EventStatus::Cancelled, events[1].status);
+
+        let picnic = &events[0];
+        assert_eq!(
+            Some(
+                Utc.with_ymd_and_hms(2023, 9, 5, 13, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Local)
+            ),
+            picnic.times.start
+        );
+        assert_eq!(Some("Conf Room 3"), picnic.location.as_deref());
+        let link = picnic.conference_link.as_ref().unwrap();
+        assert_eq!(
+            "https://teams.microsoft.com/l/meetup-join/19%3ameeting",
+            link.url
+        );
+        assert_eq!(ConferencePlatform::Teams, link.platform);
+        assert_eq!(
+            Some("+1 323-555-0166 PIN: 177513992#"),
+            link.dial_in.as_deref()
+        );
+        assert_eq!(Some(ResponseStatus::Declined), picnic.self_response());
+        assert!(picnic.attendees[1].optional);
+        assert_eq!(Transparency::Opaque, picnic.transparency);
+        assert_eq!("Calendar", picnic.source.as_ref().unwrap().calendar_name);
+
+        let office = &events[2];
+        let september_5th = NaiveDate::from_ymd_opt(2023, 9, 5).unwrap();
+        assert_eq!(Some(DateRange::single_day(september_5th)), office.all_day);
+        assert_eq!(Transparency::Transparent, office.transparency);
+        assert_eq!(Visibility::Private, office.visibility);
+
+        let view = server
+            .requests()
+            .into_iter()
+            .find(|r| r.path.contains("calendarView"))
+            .unwrap();
+        assert_eq!(Some("outlook.timezone=\"UTC\""), view.header("Prefer"));
+        assert!(view.path.contains("startDateTime=2023-09-05"));
+    }
+
+    #[async_std::test]
+    async fn selects_calendars_by_name() {
+        let server = graph_server();
+        let calendar = calendar(&server).with_calendars(vec![CalendarSelector::Name(
+            regex::Regex::new("holidays").unwrap(),
+        )]);
+
+        let selected = calendar.selected_calendars().await.unwrap();
+        assert_eq!(
+            vec!["United States holidays"],
+            selected.iter().map(|c| c.name.as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    #[async_std::test]
+    async fn shows_the_other_calendars_when_one_fails_without_listing_them_again() {
+        let server = graph_server();
+        let calendar = calendar(&server).with_calendars(vec![CalendarSelector::Name(
+            regex::Regex::new("Calendar|holidays").unwrap(),
+        )]);
+
+        for _ in 0..2 {
+            let events = calendar.get_events_on(september_5th()).await.unwrap();
+            assert_eq!(3, events.len());
+            assert!(events
+                .iter()
+                .all(|e| e.source.as_ref().unwrap().calendar_name == "Calendar"));
+        }
+
+        let listed = server
+            .requests()
+            .iter()
+            .filter(|r| r.path == "/v1.0/me/calendars")
+            .count();
+        assert_eq!(1, listed);
+    }
+}
@@ -0,0 +1,105 @@
+use super::*;
+use crate::calendar::oauth::*;
+use crate::calendar::tokens::*;
+
+const MICROSOFT_LOGIN_URL: &str = "https://login.microsoftonline.com/";
+/// Lets both work or school accounts and personal Microsoft accounts log in
+const DEFAULT_TENANT: &str = "common";
+const SCOPES: [&str; 3] = ["offline_access", "User.Read", "Calendars.Read"];
+pub const MICROSOFT_TOKEN_FILE: &str = "microsoft-token.json";
+/// What Microsoft tokens are saved as in the keyring
+pub const MICROSOFT_KEYRING_ACCOUNT: &str = "microsoft";
+
+/// Logs in to Outlook / Microsoft 365 with a code entered on another device.
+/// The client ID must belong to an Entra ID app registration with public client flows allowed.
+pub struct MicrosoftCalendarProvider {
+    oauth: DeviceFlowClient,
+    store: Option<TokenStore>,
+}
+
+impl MicrosoftCalendarProvider {
+    pub fn new(client_id: String) -> MicrosoftCalendarProvider {
+        MicrosoftCalendarProvider {
+            oauth: DeviceFlowClient {
+                client_id,
+                client_secret: None,
+                device_authorization_url: String::new(),
+                token_url: String::new(),
+                scopes: SCOPES.iter().map(|scope| scope.to_string()).collect(),
+            },
+            store: None,
+        }
+        .with_tenant(DEFAULT_TENANT)
+    }
+
+    /// Only lets accounts from this directory log in: a tenant ID, a domain, or `organizations`
+    pub fn with_tenant(self, tenant: &str) -> MicrosoftCalendarProvider {
+        let oauth = DeviceFlowClient {
+            device_authorization_url: format!(
+                "{}{}/oauth2/v2.0/devicecode",
+                MICROSOFT_LOGIN_URL, tenant
+            ),
+            token_url: format!("{}{}/oauth2/v2.0/token", MICROSOFT_LOGIN_URL, tenant),
+            ..self.oauth
+        };
+        MicrosoftCalendarProvider { oauth, ..self }
+    }
+
+    /// Remembers tokens in the store, so the user only has to log in once
+    pub fn with_token_store(self, store: TokenStore) -> MicrosoftCalendarProvider {
+        MicrosoftCalendarProvider {
+            store: Some(store),
+            ..self
+        }
+    }
+
+    fn calendar_for(&self, token: StoredToken) -> MicrosoftCalendar {
+        let session = OAuthSession::new(self.oauth.clone(), token, self.store.clone());
+        MicrosoftCalendar::new(GraphApi::new(session))
+    }
+}
+
+impl DeviceLoginProvider for MicrosoftCalendarProvider {
+    type Calendar = MicrosoftCalendar;
+
+    fn name(&self) -> &str {
+        "Outlook calendar"
+    }
+
+    async fn resume_session(&self) -> Option<MicrosoftCalendar> {
+        let token = self.store.as_ref()?.load().await?;
+        Some(self.calendar_for(token))
+    }
+
+    async fn start_login(&self) -> Result<DeviceAuthorization, CalendarError> {
+        self.oauth.request_device_code().await
+    }
+
+    async fn finish_login(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> Result<MicrosoftCalendar, CalendarError> {
+        let token = self
+            .oauth
+            .finish_login(authorization, self.store.as_ref())
+            .await?;
+        Ok(self.calendar_for(token))
+    }
+}
+
+impl CalendarProvider for MicrosoftCalendarProvider {
+    type Calendar = MicrosoftCalendar;
+
+    async fn login(&mut self) -> Result<MicrosoftCalendar, CalendarError> {
+        if let Some(calendar) = self.resume_session().await {
+            return Ok(calendar);
+        }
+
+        let authorization = self.start_login().await?;
+        println!(
+            "To show your Outlook calendar, visit {} and enter the code {}",
+            authorization.verification_url, authorization.user_code
+        );
+        self.finish_login(&authorization).await
+    }
+}
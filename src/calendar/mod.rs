@@ -4,4 +4,20 @@ pub use calendar::*;
 mod errors;
 pub use errors::*;
 
+pub mod annotations;
+pub mod caching;
+pub mod caldav;
+pub mod command;
+pub mod conference;
+pub mod filter;
 pub mod google;
+pub mod holidays;
+pub mod ics;
+pub mod keyring;
+pub mod merged;
+pub mod microsoft;
+pub mod oauth;
+pub mod tasks;
+pub mod throttle;
+pub mod todotxt;
+pub mod tokens;
@@ -0,0 +1,498 @@
+use super::calendar::Calendar;
+use super::errors::*;
+use super::tokens::*;
+use async_std::sync::Mutex;
+use async_std::task::sleep;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// RFC 8628 asks clients to wait this much longer between polls every time they're told to slow down
+const SLOW_DOWN_INCREMENT: Duration = Duration::from_secs(5);
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+/// Access tokens are refreshed this many minutes before they expire, so requests in flight don't fail
+const REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// An OAuth 2.0 client that logs in with the device authorization grant (RFC 8628),
+/// so the user can finish logging in on their phone instead of on the machine running this.
+/// Its requests aren't part of any API's request budget, since token endpoints have quotas of their own.
+#[derive(Debug, Clone)]
+pub struct DeviceFlowClient {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub device_authorization_url: String,
+    pub token_url: String,
+    pub scopes: Vec<String>,
+}
+
+/// What the user needs to finish logging in: a code to type in at a URL
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    /// Google calls this `verification_url`, the RFC calls it `verification_uri`
+    #[serde(alias = "verification_uri")]
+    pub verification_url: String,
+    /// A URL that already has the user code filled in, which makes for a better QR code
+    #[serde(alias = "verification_uri_complete")]
+    pub verification_url_complete: Option<String>,
+    /// Seconds until the codes expire
+    pub expires_in: u64,
+    /// Seconds to wait between polls of the token endpoint
+    #[serde(default = "default_poll_interval")]
+    pub interval: u64,
+}
+
+fn default_poll_interval() -> u64 {
+    DEFAULT_POLL_INTERVAL_SECS
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    /// Seconds until the access token expires
+    pub expires_in: i64,
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub scope: String,
+    pub token_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+impl std::fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_description {
+            Some(description) => write!(f, "{} ({})", self.error, description),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl DeviceFlowClient {
+    pub async fn request_device_code(&self) -> Result<DeviceAuthorization, CalendarError> {
+        let scope = self.scopes.join(" ");
+        let response = reqwest::Client::new()
+            .post(&self.device_authorization_url)
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("scope", scope.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| CalendarError::login("could not request a device code").with_cause(e))?;
+
+        if !response.status().is_success() {
+            let error = CalendarError::from_response("could not request a device code", &response)
+                .with_kind(ErrorKind::LoginError);
+            return Err(match response.json::<ErrorResponse>().await {
+                Ok(body) => error.with_cause(body.to_string()),
+                Err(_) => error,
+            });
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| CalendarError::login("could not parse device code response").with_cause(e))
+    }
+
+    /// Waits for the user to enter their code, polling the token endpoint as often as we're allowed to
+    pub async fn poll_for_token(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> Result<TokenResponse, CalendarError> {
+        let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+        let mut interval = Duration::from_secs(authorization.interval);
+        let client = reqwest::Client::new();
+
+        loop {
+            sleep(interval).await;
+            if Instant::now() >= deadline {
+                return Err(CalendarError::login(
+                    "the login code expired before it was used",
+                ));
+            }
+
+            let mut form = vec![
+                ("client_id", self.client_id.as_str()),
+                ("device_code", authorization.device_code.as_str()),
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ];
+            if let Some(secret) = &self.client_secret {
+                form.push(("client_secret", secret.as_str()));
+            }
+
+            let response = match client.post(&self.token_url).form(&form).send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    // The user may still be typing their code in, so don't give up on a network blip
+                    eprintln!("could not poll for a token, will retry: {:?}", e);
+                    continue;
+                }
+            };
+
+            if response.status().is_success() {
+                return response.json().await.map_err(|e| {
+                    CalendarError::login("could not parse token response").with_cause(e)
+                });
+            }
+
+            let status = response.status();
+            let error = match response.json::<ErrorResponse>().await {
+                Ok(error) => error,
+                // e.g. a proxy's error page, which is as likely to go away as a network blip
+                Err(e) if status.is_server_error() => {
+                    eprintln!("could not poll for a token, will retry: {} {:?}", status, e);
+                    continue;
+                }
+                Err(e) => {
+                    return Err(
+                        CalendarError::login("could not parse token error response").with_cause(e)
+                    )
+                }
+            };
+            match error.error.as_str() {
+                "authorization_pending" => {}
+                "slow_down" => interval += SLOW_DOWN_INCREMENT,
+                "access_denied" => return Err(CalendarError::login("login was denied")),
+                "expired_token" => {
+                    return Err(CalendarError::login(
+                        "the login code expired before it was used",
+                    ))
+                }
+                _ => {
+                    return Err(
+                        CalendarError::login("could not log in").with_cause(error.to_string())
+                    )
+                }
+            }
+        }
+    }
+
+    /// Waits for the user to log in with [`Self::poll_for_token`], and saves their tokens in `store`
+    /// so they don't have to log in again next time
+    pub async fn finish_login(
+        &self,
+        authorization: &DeviceAuthorization,
+        store: Option<&TokenStore>,
+    ) -> Result<StoredToken, CalendarError> {
+        let token = StoredToken::from_response(self.poll_for_token(authorization).await?, None);
+        if let Some(store) = store {
+            if let Err(e) = store.save(&token).await {
+                eprintln!(
+                    "could not save tokens; you'll have to log in again next time: {:?}",
+                    e
+                );
+            }
+        }
+        Ok(token)
+    }
+
+    /// Trades a refresh token for a new access token.
+    /// A revoked or expired refresh token means the user has to log in again.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenResponse, CalendarError> {
+        let mut form = vec![
+            ("client_id", self.client_id.as_str()),
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response = reqwest::Client::new()
+            .post(&self.token_url)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| CalendarError::fetch("could not refresh access token").with_cause(e))?;
+
+        if response.status().is_success() {
+            return response
+                .json()
+                .await
+                .map_err(|e| CalendarError::fetch("could not parse token response").with_cause(e));
+        }
+
+        let error = CalendarError::from_response("could not refresh access token", &response);
+        match response.json::<ErrorResponse>().await {
+            Ok(body) if body.error == "invalid_grant" => {
+                Err(error.with_kind(ErrorKind::LoggedOut).with_cause(format!(
+                    "refresh token was revoked or has expired: {}",
+                    body
+                )))
+            }
+            Ok(body) => Err(error.with_cause(body.to_string())),
+            Err(_) => Err(error),
+        }
+    }
+}
+
+/// A calendar service that's logged in to with a code entered on another device
+pub trait DeviceLoginProvider {
+    type Calendar: Calendar;
+
+    /// What to call the calendar when asking the user to log in to it
+    fn name(&self) -> &str;
+
+    /// Picks up where the last run left off, if it saved tokens.
+    /// Whether they still work isn't known until the calendar is used.
+    async fn resume_session(&self) -> Option<Self::Calendar>;
+
+    /// Gets the code and URL to show the user. Pass the result to [`Self::finish_login`].
+    async fn start_login(&self) -> Result<DeviceAuthorization, CalendarError>;
+
+    /// Waits for the user to enter the code from [`Self::start_login`]
+    async fn finish_login(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> Result<Self::Calendar, CalendarError>;
+}
+
+/// A logged-in user's tokens, which are refreshed as needed and saved whenever they change
+pub struct OAuthSession {
+    client: DeviceFlowClient,
+    token: Mutex<StoredToken>,
+    store: Option<TokenStore>,
+}
+
+impl OAuthSession {
+    pub fn new(
+        client: DeviceFlowClient,
+        token: StoredToken,
+        store: Option<TokenStore>,
+    ) -> OAuthSession {
+        OAuthSession {
+            client,
+            token: Mutex::new(token),
+            store,
+        }
+    }
+
+    /// An access token that won't expire for a little while
+    pub async fn access_token(&self) -> Result<String, CalendarError> {
+        let mut token = self.token.lock().await;
+        if token.expires_within(chrono::Duration::minutes(REFRESH_MARGIN_MINUTES)) {
+            self.refresh(&mut token).await?;
+        }
+        Ok(token.access_token.clone())
+    }
+
+    /// Gets a new access token after the server rejected `rejected_token`,
+    /// unless another request has already replaced it
+    pub async fn access_token_after_rejection(
+        &self,
+        rejected_token: &str,
+    ) -> Result<String, CalendarError> {
+        let mut token = self.token.lock().await;
+        if token.access_token == rejected_token {
+            self.refresh(&mut token).await?;
+        }
+        Ok(token.access_token.clone())
+    }
+
+    async fn refresh(&self, token: &mut StoredToken) -> Result<(), CalendarError> {
+        let Some(refresh_token) = token.refresh_token.clone() else {
+            return Err(CalendarError::logged_out(
+                "access token expired and there's no refresh token",
+            ));
+        };
+
+        let response = match self.client.refresh(&refresh_token).await {
+            Ok(response) => response,
+            Err(e) if e.kind() == ErrorKind::LoggedOut => {
+                // Don't try the revoked token again next time
+                if let Some(store) = &self.store {
+                    if let Err(e) = store.clear().await {
+                        eprintln!("could not forget revoked tokens: {:?}", e);
+                    }
+                }
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+
+        *token = StoredToken::from_response(response, Some(refresh_token));
+        if let Some(store) = &self.store {
+            if let Err(e) = store.save(token).await {
+                eprintln!("could not save refreshed tokens: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex as StdMutex;
+
+    const TOKEN: &str = r#"{"access_token": "new-token", "expires_in": 3599, "refresh_token": "refresh", "token_type": "Bearer"}"#;
+
+    /// Answers each poll of the token endpoint with the next of `errors`, and then with a token
+    fn token_server(errors: &[&str]) -> StubServer {
+        let mut responses: VecDeque<StubResponse> = errors
+            .iter()
+            .map(|error| StubResponse::new(400, &format!(r#"{{"error": "{}"}}"#, error)))
+            .collect();
+        responses.push_back(StubResponse::new(200, TOKEN));
+        let responses = StdMutex::new(responses);
+        StubServer::start(move |_| {
+            let response = responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| StubResponse::new(500, ""));
+            response.with_header("Content-Type", "application/json")
+        })
+    }
+
+    fn client(server: &StubServer) -> DeviceFlowClient {
+        DeviceFlowClient {
+            client_id: "client".to_string(),
+            client_secret: Some("secret".to_string()),
+            device_authorization_url: server.url("/device/code"),
+            token_url: server.url("/token"),
+            scopes: Vec::new(),
+        }
+    }
+
+    fn authorization(interval: u64) -> DeviceAuthorization {
+        DeviceAuthorization {
+            device_code: "device-code".to_string(),
+            user_code: "ABCD-EFGH".to_string(),
+            verification_url: "https://www.google.com/device".to_string(),
+            verification_url_complete: None,
+            expires_in: 1800,
+            interval,
+        }
+    }
+
+    #[async_std::test]
+    async fn keeps_polling_while_authorization_is_pending() {
+        let server = token_server(&["authorization_pending", "authorization_pending"]);
+
+        let token = client(&server)
+            .poll_for_token(&authorization(0))
+            .await
+            .unwrap();
+
+        assert_eq!("new-token", token.access_token);
+        let polls = server.requests();
+        assert_eq!(3, polls.len());
+        assert!(polls[0].body.contains("device_code=device-code"));
+        assert!(polls[0].body.contains("client_secret=secret"));
+        assert!(polls[0]
+            .body
+            .contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code"));
+    }
+
+    #[async_std::test]
+    async fn waits_five_seconds_longer_when_told_to_slow_down() {
+        let server = token_server(&["slow_down"]);
+
+        let started = Instant::now();
+        client(&server)
+            .poll_for_token(&authorization(0))
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() >= SLOW_DOWN_INCREMENT);
+        assert_eq!(2, server.requests().len());
+    }
+
+    #[async_std::test]
+    async fn keeps_polling_through_server_error_pages() {
+        let responses = StdMutex::new(VecDeque::from([
+            StubResponse::new(503, "<html><body>Service Unavailable</body></html>")
+                .with_header("Content-Type", "text/html"),
+            StubResponse::new(200, TOKEN).with_header("Content-Type", "application/json"),
+        ]));
+        let server = StubServer::start(move |_| responses.lock().unwrap().pop_front().unwrap());
+
+        let token = client(&server)
+            .poll_for_token(&authorization(0))
+            .await
+            .unwrap();
+
+        assert_eq!("new-token", token.access_token);
+        assert_eq!(2, server.requests().len());
+    }
+
+    #[async_std::test]
+    async fn stops_polling_when_the_code_expires_or_is_denied() {
+        for error in ["expired_token", "access_denied", "invalid_client"] {
+            let server = token_server(&[error, "authorization_pending"]);
+
+            let e = client(&server)
+                .poll_for_token(&authorization(0))
+                .await
+                .unwrap_err();
+
+            assert_eq!(ErrorKind::LoginError, e.kind(), "{}", error);
+            assert_eq!(1, server.requests().len(), "{}", error);
+        }
+    }
+
+    fn token_expiring_in(minutes: i64) -> StoredToken {
+        StoredToken {
+            access_token: "old-token".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: chrono::Utc::now() + chrono::Duration::minutes(minutes),
+        }
+    }
+
+    #[async_std::test]
+    async fn refreshes_tokens_that_are_about_to_expire_before_using_them() {
+        let server = token_server(&[]);
+        let session = OAuthSession::new(
+            client(&server),
+            token_expiring_in(REFRESH_MARGIN_MINUTES + 55),
+            None,
+        );
+        assert_eq!("old-token", session.access_token().await.unwrap());
+        assert!(server.requests().is_empty());
+
+        let session = OAuthSession::new(
+            client(&server),
+            token_expiring_in(REFRESH_MARGIN_MINUTES - 1),
+            None,
+        );
+        assert_eq!("new-token", session.access_token().await.unwrap());
+        assert_eq!("new-token", session.access_token().await.unwrap());
+
+        let refreshes = server.requests();
+        assert_eq!(1, refreshes.len());
+        assert_eq!("/token", refreshes[0].path);
+        assert!(refreshes[0].body.contains("grant_type=refresh_token"));
+        assert!(refreshes[0].body.contains("refresh_token=refresh"));
+    }
+
+    #[async_std::test]
+    async fn forgets_saved_tokens_when_the_refresh_token_was_revoked() {
+        let server = StubServer::start(|_| {
+            StubResponse::new(400, r#"{"error": "invalid_grant", "error_description": "Token has been expired or revoked."}"#)
+                .with_header("Content-Type", "application/json")
+        });
+        let dir = tempdir::TempDir::new("tokens").unwrap();
+        let path = dir.path().join("google-token.json");
+        let store = FileTokenStorage::new(path.clone());
+        store.save(&token_expiring_in(1)).await.unwrap();
+        let session = OAuthSession::new(
+            client(&server),
+            token_expiring_in(1),
+            Some(TokenStore::File(store)),
+        );
+
+        let e = session.access_token().await.unwrap_err();
+
+        assert_eq!(ErrorKind::LoggedOut, e.kind());
+        assert!(!path.exists());
+    }
+}
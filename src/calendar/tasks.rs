@@ -0,0 +1,129 @@
+use super::calendar::*;
+use super::errors::*;
+use super::filter::EventFilter;
+use super::ics::IcsCalendar;
+use super::todotxt::TodoTxtList;
+use crate::model::*;
+use chrono::prelude::*;
+use futures_util::future::join_all;
+
+/// Any of the to-do lists this crate can read
+pub enum AnyTaskList {
+    TodoTxt(TodoTxtList),
+    Ics(IcsCalendar),
+}
+
+impl TaskList for AnyTaskList {
+    async fn get_tasks_due(&self, by: NaiveDate) -> Result<Vec<Task>, CalendarError> {
+        match self {
+            AnyTaskList::TodoTxt(list) => list.get_tasks_due(by).await,
+            AnyTaskList::Ics(list) => list.get_tasks_due(by).await,
+        }
+    }
+}
+
+/// Tasks from several lists, with the ones a filter hides left out, most important first
+pub struct TaskLists<T: TaskList> {
+    lists: Vec<(String, T)>,
+    filter: EventFilter,
+}
+
+impl<T: TaskList> TaskLists<T> {
+    pub fn new(filter: EventFilter) -> TaskLists<T> {
+        TaskLists {
+            lists: Vec::new(),
+            filter,
+        }
+    }
+
+    /// Adds a list, named for error messages
+    pub fn with_list(mut self, name: &str, list: T) -> TaskLists<T> {
+        self.lists.push((name.to_string(), list));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+}
+
+/// Tasks only fill in the schedule, so a list that can't be read is left out rather than failing
+impl<T: TaskList> TaskList for TaskLists<T> {
+    async fn get_tasks_due(&self, by: NaiveDate) -> Result<Vec<Task>, CalendarError> {
+        let results = join_all(self.lists.iter().map(|(_, list)| list.get_tasks_due(by))).await;
+        let mut tasks = Vec::new();
+        for ((name, _), result) in self.lists.iter().zip(results) {
+            match result {
+                Ok(list_tasks) => tasks.extend(list_tasks),
+                Err(e) => eprintln!("could not read tasks from {}: {}", name, e),
+            }
+        }
+        let mut tasks = self.filter.apply_to_tasks(tasks);
+        tasks.sort_by(|a, b| {
+            let priority = |task: &Task| task.priority.unwrap_or(u8::MAX);
+            priority(a)
+                .cmp(&priority(b))
+                .then(a.due.cmp(&b.due))
+                .then_with(|| a.summary.cmp(&b.summary))
+        });
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::filter::*;
+    use regex::Regex;
+
+    struct StubList(Result<Vec<Task>, ()>);
+
+    impl TaskList for StubList {
+        async fn get_tasks_due(&self, _by: NaiveDate) -> Result<Vec<Task>, CalendarError> {
+            self.0.clone().map_err(|_| CalendarError::fetch("stub"))
+        }
+    }
+
+    fn task(summary: &str, priority: Option<u8>, due: u32) -> Task {
+        Task {
+            summary: summary.to_string(),
+            priority,
+            due: NaiveDate::from_ymd_opt(2023, 9, due),
+            ..Default::default()
+        }
+    }
+
+    #[async_std::test]
+    async fn sorts_filters_and_skips_lists_that_fail() {
+        let filter = EventFilter::new().with_rule(FilterRule::Exclude(EventPattern::Title(
+            Regex::new("^Lunch").unwrap(),
+        )));
+        let lists = TaskLists::new(filter)
+            .with_list(
+                "home",
+                StubList(Ok(vec![
+                    task("Water the plants", None, 4),
+                    task("Lunch order", Some(1), 5),
+                ])),
+            )
+            .with_list("broken", StubList(Err(())))
+            .with_list(
+                "work",
+                StubList(Ok(vec![
+                    task("Review PR", None, 3),
+                    task("Send the invoice", Some(2), 5),
+                ])),
+            );
+
+        let tasks = lists
+            .get_tasks_due(NaiveDate::from_ymd_opt(2023, 9, 5).unwrap())
+            .await
+            .unwrap();
+
+        let summaries: Vec<&str> = tasks.iter().map(|task| task.summary.as_str()).collect();
+        assert_eq!(
+            vec!["Send the invoice", "Review PR", "Water the plants"],
+            summaries
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Keeps requests to an API under its quota, and retries the ones it turns away
+
+use super::errors::*;
+use async_std::task::sleep;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_RETRIES: u32 = 4;
+const DEFAULT_FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// A token bucket for each kind of request (e.g. each calendar), and a pause for everything
+/// when the server says to wait. Share one between every client of the same API.
+pub struct RequestBudget {
+    burst: u32,
+    refill_every: Duration,
+    max_retries: u32,
+    first_retry_delay: Duration,
+    max_retry_delay: Duration,
+    state: Mutex<BudgetState>,
+}
+
+#[derive(Default)]
+struct BudgetState {
+    buckets: HashMap<String, TokenBucket>,
+    paused_until: Option<Instant>,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RequestBudget {
+    /// Allows `burst` requests at once for each bucket, and then one more every `refill_every`
+    pub fn new(burst: u32, refill_every: Duration) -> RequestBudget {
+        RequestBudget {
+            burst: burst.max(1),
+            refill_every,
+            max_retries: DEFAULT_MAX_RETRIES,
+            first_retry_delay: DEFAULT_FIRST_RETRY_DELAY,
+            max_retry_delay: DEFAULT_MAX_RETRY_DELAY,
+            state: Mutex::new(BudgetState::default()),
+        }
+    }
+
+    /// Retries a request up to `max_retries` times, waiting `first_delay` (give or take) before the first retry
+    /// and twice as long before each one after that, up to `max_delay`.
+    /// A server that asks to wait longer than `max_delay` isn't retried at all.
+    pub fn with_retries(
+        self,
+        max_retries: u32,
+        first_delay: Duration,
+        max_delay: Duration,
+    ) -> RequestBudget {
+        RequestBudget {
+            max_retries,
+            first_retry_delay: first_delay,
+            max_retry_delay: max_delay,
+            ..self
+        }
+    }
+
+    /// Makes a request once there's budget for it in `bucket`, retrying it while it fails in ways that might go away
+    pub async fn run<T, F, Fut>(&self, bucket: &str, mut request: F) -> Result<T, CalendarError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, CalendarError>>,
+    {
+        let mut retries = 0;
+        loop {
+            self.acquire(bucket).await;
+            let e = match request().await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            if !e.is_retryable() || retries >= self.max_retries {
+                return Err(e);
+            }
+
+            let delay = match e.retry_after() {
+                Some(delay) if delay > self.max_retry_delay => return Err(e),
+                // The whole API is asking us to slow down, not just this bucket
+                Some(delay) => {
+                    self.pause_for(delay);
+                    delay
+                }
+                None => self.backoff(retries),
+            };
+            eprintln!("{}; trying again in {}ms", e, delay.as_millis());
+            sleep(delay).await;
+            retries += 1;
+        }
+    }
+
+    async fn acquire(&self, bucket: &str) {
+        while let Some(wait) = self.try_acquire(bucket, Instant::now()) {
+            sleep(wait).await;
+        }
+    }
+
+    /// Takes a token from the bucket, or says how long until there'll be one
+    fn try_acquire(&self, bucket: &str, now: Instant) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(until) = state.paused_until.filter(|until| *until > now) {
+            return Some(until - now);
+        }
+
+        let burst = self.burst as f64;
+        let bucket = state
+            .buckets
+            .entry(bucket.to_string())
+            .or_insert(TokenBucket {
+                tokens: burst,
+                updated: now,
+            });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64()
+            / self.refill_every.as_secs_f64().max(f64::EPSILON);
+        bucket.tokens = (bucket.tokens + refilled).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(self.refill_every.mul_f64(1.0 - bucket.tokens))
+        }
+    }
+
+    fn pause_for(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut state = self.state.lock().unwrap();
+        state.paused_until = Some(
+            state
+                .paused_until
+                .map_or(until, |paused_until| paused_until.max(until)),
+        );
+    }
+
+    /// Somewhere between half and all of the exponential delay,
+    /// so clients that failed together don't all retry together
+    fn backoff(&self, retries: u32) -> Duration {
+        let delay = self
+            .first_retry_delay
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(self.max_retry_delay);
+        delay.mul_f64(0.5 + random_fraction() / 2.0)
+    }
+}
+
+/// Good enough randomness for jitter, without another dependency
+fn random_fraction() -> f64 {
+    // Every `RandomState` is seeded differently
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A server that answers with each of the responses in turn, and then with 200 OK
+    fn scripted_server(responses: Vec<StubResponse>) -> StubServer {
+        let count = AtomicUsize::new(0);
+        StubServer::start(move |_| {
+            let n = count.fetch_add(1, Ordering::SeqCst);
+            responses
+                .get(n)
+                .cloned()
+                .unwrap_or_else(|| StubResponse::new(200, "ok"))
+        })
+    }
+
+    async fn fetch(url: &str) -> Result<String, CalendarError> {
+        let response = reqwest::get(url)
+            .await
+            .map_err(|e| CalendarError::fetch("could not fetch").with_cause(e))?;
+        if !response.status().is_success() {
+            return Err(CalendarError::from_response("could not fetch", &response));
+        }
+        Ok(response.text().await.unwrap())
+    }
+
+    fn quick_budget() -> RequestBudget {
+        RequestBudget::new(10, Duration::from_millis(1)).with_retries(
+            3,
+            Duration::from_millis(1),
+            Duration::from_secs(1),
+        )
+    }
+
+    #[async_std::test]
+    async fn retries_server_errors_and_rate_limits() {
+        let server = scripted_server(vec![
+            StubResponse::new(503, "down"),
+            StubResponse::new(429, "slow down").with_header("Retry-After", "0"),
+            StubResponse::new(500, "oops"),
+        ]);
+        let url = server.url("/events");
+
+        assert_eq!(
+            "ok",
+            quick_budget().run("work", || fetch(&url)).await.unwrap()
+        );
+        assert_eq!(4, server.requests().len());
+    }
+
+    #[async_std::test]
+    async fn gives_up_on_errors_that_wont_go_away() {
+        let server = scripted_server(vec![StubResponse::new(404, "gone")]);
+        let url = server.url("/events");
+        let e = quick_budget()
+            .run("work", || fetch(&url))
+            .await
+            .unwrap_err();
+        assert_eq!(Some(reqwest::StatusCode::NOT_FOUND), e.status());
+        assert_eq!(1, server.requests().len());
+
+        let server = scripted_server(vec![StubResponse::new(502, "down"); 5]);
+        let url = server.url("/events");
+        assert!(quick_budget().run("work", || fetch(&url)).await.is_err());
+        assert_eq!(4, server.requests().len());
+
+        // Waiting an hour is the refresh loop's job, not the request's
+        let server = scripted_server(vec![
+            StubResponse::new(429, "slow down").with_header("Retry-After", "3600")
+        ]);
+        let url = server.url("/events");
+        let e = quick_budget()
+            .run("work", || fetch(&url))
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::RateLimited, e.kind());
+        assert_eq!(Some(Duration::from_secs(3600)), e.retry_after());
+    }
+
+    #[test]
+    fn spaces_out_requests_in_each_bucket() {
+        let budget = RequestBudget::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(None, budget.try_acquire("work", start));
+        assert_eq!(None, budget.try_acquire("work", start));
+        assert_eq!(
+            Some(Duration::from_secs(10)),
+            budget.try_acquire("work", start)
+        );
+        assert_eq!(None, budget.try_acquire("personal", start));
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            budget.try_acquire("work", start + Duration::from_secs(5))
+        );
+        assert_eq!(
+            None,
+            budget.try_acquire("work", start + Duration::from_secs(10))
+        );
+    }
+}
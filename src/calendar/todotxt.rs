@@ -0,0 +1,160 @@
+//! Tasks from a [todo.txt](https://github.com/todotxt/todo.txt) file, one per line, like
+//! `(A) 2023-09-01 Send the invoice +work due:2023-09-05`
+
+use super::calendar::*;
+use super::errors::*;
+use crate::model::*;
+use chrono::prelude::*;
+use std::path::PathBuf;
+
+/// A todo.txt file, read again every time tasks are asked for
+#[derive(Debug, Clone)]
+pub struct TodoTxtList {
+    path: PathBuf,
+    name: String,
+}
+
+impl TodoTxtList {
+    pub fn new(path: PathBuf) -> TodoTxtList {
+        let name = path
+            .file_stem()
+            .map_or_else(|| path.to_string_lossy(), |stem| stem.to_string_lossy())
+            .into_owned();
+        TodoTxtList { path, name }
+    }
+
+    /// What to call the list, for filters and redaction; the file's name by default
+    pub fn with_name(self, name: &str) -> TodoTxtList {
+        TodoTxtList {
+            name: name.to_string(),
+            ..self
+        }
+    }
+}
+
+impl TaskList for TodoTxtList {
+    async fn get_tasks_due(&self, by: NaiveDate) -> Result<Vec<Task>, CalendarError> {
+        let text = async_std::fs::read_to_string(&self.path)
+            .await
+            .map_err(|e| {
+                CalendarError::fetch(format!("could not read tasks from {}", self.path.display()))
+                    .with_cause(e)
+            })?;
+        let source = EventSource {
+            calendar_id: self.path.display().to_string(),
+            calendar_name: self.name.clone(),
+            background_color: None,
+            ..Default::default()
+        };
+        Ok(parse_tasks(&text)
+            .into_iter()
+            .filter(|task| task.due.is_some_and(|due| due <= by))
+            .map(|task| Task {
+                source: Some(source.clone()),
+                ..task
+            })
+            .collect())
+    }
+}
+
+/// Every task in the file that isn't done yet
+pub fn parse_tasks(text: &str) -> Vec<Task> {
+    text.lines().filter_map(parse_task).collect()
+}
+
+fn parse_task(line: &str) -> Option<Task> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("x ") {
+        return None;
+    }
+    let mut words = line.split_whitespace().peekable();
+
+    let priority = words.peek().and_then(|word| parse_priority(word));
+    if priority.is_some() {
+        words.next();
+    }
+    // The date it was added, which isn't shown
+    if words
+        .peek()
+        .is_some_and(|word| NaiveDate::parse_from_str(word, "%Y-%m-%d").is_ok())
+    {
+        words.next();
+    }
+
+    let mut due = None;
+    let mut summary = Vec::new();
+    for word in words {
+        match word.strip_prefix("due:") {
+            Some(date) => due = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(),
+            None => summary.push(word),
+        }
+    }
+    Some(Task {
+        summary: summary.join(" "),
+        due,
+        priority,
+        ..Default::default()
+    })
+}
+
+/// `(A)` is the most important, so it's 1
+fn parse_priority(word: &str) -> Option<u8> {
+    match word.as_bytes() {
+        [b'(', letter @ b'A'..=b'Z', b')'] => Some(letter - b'A' + 1),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TODO_TXT: &str = "\
+(A) 2023-09-01 Send the invoice +work due:2023-09-05
+x 2023-09-02 2023-09-01 Book flights due:2023-09-03
+Water the plants due:2023-09-04 @home
+
+(C) Renew passport due:2023-10-01
+Someday, learn the cello
+";
+
+    #[test]
+    fn reads_priorities_due_dates_and_skips_done_tasks() {
+        let tasks = parse_tasks(TODO_TXT);
+
+        let summaries: Vec<&str> = tasks.iter().map(|task| task.summary.as_str()).collect();
+        assert_eq!(
+            vec![
+                "Send the invoice +work",
+                "Water the plants @home",
+                "Renew passport",
+                "Someday, learn the cello"
+            ],
+            summaries
+        );
+        assert_eq!(Some(1), tasks[0].priority);
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 9, 5), tasks[0].due);
+        assert_eq!(None, tasks[1].priority);
+        assert_eq!(Some(3), tasks[2].priority);
+        assert_eq!(None, tasks[3].due);
+    }
+
+    #[async_std::test]
+    async fn lists_tasks_due_by_the_day() {
+        let dir = tempdir::TempDir::new("todo").unwrap();
+        let path = dir.path().join("todo.txt");
+        std::fs::write(&path, TODO_TXT).unwrap();
+
+        let tasks = TodoTxtList::new(path)
+            .get_tasks_due(NaiveDate::from_ymd_opt(2023, 9, 5).unwrap())
+            .await
+            .unwrap();
+
+        let summaries: Vec<&str> = tasks.iter().map(|task| task.summary.as_str()).collect();
+        assert_eq!(
+            vec!["Send the invoice +work", "Water the plants @home"],
+            summaries
+        );
+        assert_eq!("todo", tasks[0].source.as_ref().unwrap().calendar_name);
+    }
+}
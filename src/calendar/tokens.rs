@@ -0,0 +1,241 @@
+use super::oauth::TokenResponse;
+use async_std::task::spawn_blocking;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::keyring::SecretServiceTokenStorage;
+
+/// OAuth tokens, as remembered between runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl StoredToken {
+    /// Token endpoints don't always send a new refresh token, in which case the old one is still good
+    pub fn from_response(
+        response: TokenResponse,
+        previous_refresh_token: Option<String>,
+    ) -> StoredToken {
+        StoredToken {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token.or(previous_refresh_token),
+            expires_at: Utc::now() + Duration::seconds(response.expires_in),
+        }
+    }
+
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        self.expires_at - margin <= Utc::now()
+    }
+}
+
+/// Somewhere to remember tokens between runs
+pub trait TokenStorage {
+    /// The saved tokens, or nothing if there aren't any or they can't be read
+    async fn load(&self) -> Option<StoredToken>;
+    async fn save(&self, token: &StoredToken) -> Result<(), TokenStoreError>;
+    async fn clear(&self) -> Result<(), TokenStoreError>;
+}
+
+#[derive(Debug)]
+pub enum TokenStoreError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    SecretService(secret_service::Error),
+}
+
+impl From<std::io::Error> for TokenStoreError {
+    fn from(e: std::io::Error) -> Self {
+        TokenStoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TokenStoreError {
+    fn from(e: serde_json::Error) -> Self {
+        TokenStoreError::Json(e)
+    }
+}
+
+impl From<secret_service::Error> for TokenStoreError {
+    fn from(e: secret_service::Error) -> Self {
+        TokenStoreError::SecretService(e)
+    }
+}
+
+/// One of the places tokens can be kept, as picked in the config
+#[derive(Debug, Clone)]
+pub enum TokenStore {
+    File(FileTokenStorage),
+    SecretService(SecretServiceTokenStorage),
+}
+
+impl TokenStorage for TokenStore {
+    async fn load(&self) -> Option<StoredToken> {
+        match self {
+            TokenStore::File(store) => store.load().await,
+            TokenStore::SecretService(store) => store.load().await,
+        }
+    }
+
+    async fn save(&self, token: &StoredToken) -> Result<(), TokenStoreError> {
+        match self {
+            TokenStore::File(store) => store.save(token).await,
+            TokenStore::SecretService(store) => store.save(token).await,
+        }
+    }
+
+    async fn clear(&self) -> Result<(), TokenStoreError> {
+        match self {
+            TokenStore::File(store) => store.clear().await,
+            TokenStore::SecretService(store) => store.clear().await,
+        }
+    }
+}
+
+/// Keeps tokens in a JSON file that only the current user can read
+#[derive(Debug, Clone)]
+pub struct FileTokenStorage {
+    path: PathBuf,
+}
+
+impl FileTokenStorage {
+    pub fn new(path: PathBuf) -> FileTokenStorage {
+        FileTokenStorage { path }
+    }
+
+    /// A file in this crate's config directory, e.g. `~/.config/cast-schedule/google-token.json`
+    pub fn in_config_dir(file_name: &str) -> Option<FileTokenStorage> {
+        crate::config::config_dir().map(|dir| FileTokenStorage::new(dir.join(file_name)))
+    }
+}
+
+/// Files are read and written on a blocking thread, so a slow disk doesn't hold up other requests
+impl TokenStorage for FileTokenStorage {
+    async fn load(&self) -> Option<StoredToken> {
+        let path = self.path.clone();
+        spawn_blocking(move || read_token(&path)).await
+    }
+
+    async fn save(&self, token: &StoredToken) -> Result<(), TokenStoreError> {
+        let path = self.path.clone();
+        let contents = serde_json::to_string(token)?;
+        spawn_blocking(move || write_private_file(&path, &contents)).await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), TokenStoreError> {
+        let path = self.path.clone();
+        spawn_blocking(move || match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        })
+        .await
+    }
+}
+
+fn read_token(path: &Path) -> Option<StoredToken> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            eprintln!("could not read tokens from {}: {:?}", path.display(), e);
+            return None;
+        }
+    };
+    match serde_json::from_str(&contents) {
+        Ok(token) => Some(token),
+        Err(e) => {
+            eprintln!("ignoring unreadable tokens in {}: {:?}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Writes a file only the current user can read.
+/// It's written alongside and moved into place, so a crash can't leave half a file behind.
+pub(crate) fn write_private_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension("tmp");
+    let mut file = private_file_options().open(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)
+}
+
+/// Options for writing a file only the current user can read
+#[cfg(unix)]
+fn private_file_options() -> std::fs::OpenOptions {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true).mode(0o600);
+    options
+}
+
+#[cfg(not(unix))]
+fn private_file_options() -> std::fs::OpenOptions {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn test_token() -> StoredToken {
+        StoredToken {
+            access_token: "access".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: Utc::now() + Duration::hours(1),
+        }
+    }
+
+    #[async_std::test]
+    async fn given_saved_token_then_loads_same_token() {
+        let dir = TempDir::new("tokens").unwrap();
+        let store = FileTokenStorage::new(dir.path().join("nested").join("token.json"));
+
+        let token = test_token();
+        store.save(&token).await.unwrap();
+        assert_eq!(Some(token), store.load().await);
+    }
+
+    #[async_std::test]
+    async fn given_no_file_then_loads_nothing() {
+        let dir = TempDir::new("tokens").unwrap();
+        let store = FileTokenStorage::new(dir.path().join("token.json"));
+
+        assert_eq!(None, store.load().await);
+        store.clear().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[async_std::test]
+    async fn saved_tokens_are_only_readable_by_owner() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new("tokens").unwrap();
+        let path = dir.path().join("token.json");
+        let store = FileTokenStorage::new(path.clone());
+
+        store.save(&test_token()).await.unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+
+    #[test]
+    fn token_expiring_soon_is_reported() {
+        let token = StoredToken {
+            expires_at: Utc::now() + Duration::minutes(2),
+            ..test_token()
+        };
+        assert!(token.expires_within(Duration::minutes(5)));
+        assert!(!token.expires_within(Duration::minutes(1)));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::scan::FoundDevice;
+use rust_cast::channels::media::{Media, StreamType};
+use rust_cast::channels::receiver::CastDeviceApp;
+use rust_cast::CastDevice;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_DESTINATION_ID: &str = "receiver-0";
+/// Cast devices hang up on senders they haven't heard from in a while
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Serves the latest rendered frame over HTTP, so a cast device on the LAN can load it
+pub struct FrameServer {
+    addr: SocketAddr,
+    frame: Arc<Mutex<Frame>>,
+}
+
+#[derive(Default)]
+struct Frame {
+    number: u64,
+    png: Vec<u8>,
+}
+
+impl FrameServer {
+    pub fn start() -> std::io::Result<FrameServer> {
+        let ip = local_ip_address::local_ip()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::AddrNotAvailable, e))?;
+        let listener = TcpListener::bind(SocketAddr::new(ip, 0))?;
+        let addr = listener.local_addr()?;
+        let frame = Arc::new(Mutex::new(Frame::default()));
+
+        let served_frame = frame.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = serve_frame(stream, &served_frame) {
+                            eprintln!("could not serve frame: {:?}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("could not accept connection: {:?}", e),
+                }
+            }
+        });
+
+        Ok(FrameServer { addr, frame })
+    }
+
+    /// Replaces the frame being served, returning the URL to load it from
+    pub fn publish(&self, png: Vec<u8>) -> String {
+        let mut frame = self.frame.lock().unwrap();
+        frame.number += 1;
+        frame.png = png;
+        // A new URL for every frame, so the receiver doesn't show a cached one
+        format!("http://{}/frame/{}.png", self.addr, frame.number)
+    }
+}
+
+fn serve_frame(mut stream: TcpStream, frame: &Mutex<Frame>) -> std::io::Result<()> {
+    // Every path gets the latest frame, so only the headers need to be read
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let png = frame.lock().unwrap().png.clone();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+        png.len()
+    )?;
+    stream.write_all(&png)?;
+    stream.flush()
+}
+
+/// A cast device showing images, driven from a background thread
+/// because the cast connection is blocking and can't be shared between threads.
+pub struct CastScreen {
+    urls: Sender<String>,
+}
+
+impl CastScreen {
+    pub fn connect(device: &FoundDevice) -> CastScreen {
+        let (urls, received_urls) = channel::<String>();
+        let host = device.addr.ip();
+        let port = device.addr.port();
+        let name = device.name.clone();
+
+        std::thread::spawn(move || {
+            let (device, transport_id, session_id) = match connect_to_media_receiver(host, port) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    eprintln!("could not connect to {}: {:?}", name, e);
+                    return;
+                }
+            };
+
+            loop {
+                match received_urls.recv_timeout(HEARTBEAT_INTERVAL) {
+                    Ok(url) => {
+                        let media = Media {
+                            content_id: url,
+                            content_type: "image/png".to_string(),
+                            stream_type: StreamType::None,
+                            duration: None,
+                            metadata: None,
+                        };
+                        if let Err(e) =
+                            device
+                                .media
+                                .load(transport_id.as_str(), session_id.as_str(), &media)
+                        {
+                            eprintln!("could not show frame on {}: {:?}", name, e);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Err(e) = device.heartbeat.ping() {
+                            eprintln!("lost connection to {}: {:?}", name, e);
+                            return;
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+
+        CastScreen { urls }
+    }
+
+    /// Shows the image at the URL, which must be reachable from the cast device
+    pub fn show(&self, url: String) {
+        if self.urls.send(url).is_err() {
+            eprintln!("not connected to a cast device; dropping frame");
+        }
+    }
+}
+
+/// Launches the default media receiver, which can show images, returning its transport and session IDs
+fn connect_to_media_receiver(
+    host: IpAddr,
+    port: u16,
+) -> Result<(CastDevice<'static>, String, String), rust_cast::errors::Error> {
+    let device = CastDevice::connect_without_host_verification(host.to_string(), port)?;
+    device.connection.connect(DEFAULT_DESTINATION_ID)?;
+    device.heartbeat.ping()?;
+
+    let app = device
+        .receiver
+        .launch_app(&CastDeviceApp::DefaultMediaReceiver)?;
+    device.connection.connect(app.transport_id.as_str())?;
+    Ok((device, app.transport_id, app.session_id))
+}
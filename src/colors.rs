@@ -0,0 +1,129 @@
+use crate::model::*;
+use std::collections::HashMap;
+
+/// Which color to draw each event in: the one the provider shows it in, unless the config says otherwise
+#[derive(Debug, Clone)]
+pub struct EventColors {
+    default: Color,
+    by_color_id: HashMap<String, Color>,
+    by_calendar: HashMap<String, Color>,
+}
+
+impl Default for EventColors {
+    fn default() -> Self {
+        EventColors {
+            default: Color {
+                r: 70,
+                g: 127,
+                b: 200,
+            },
+            by_color_id: HashMap::new(),
+            by_calendar: HashMap::new(),
+        }
+    }
+}
+
+impl EventColors {
+    pub fn new() -> EventColors {
+        EventColors::default()
+    }
+
+    /// For events that don't have a color, and whose calendar doesn't either
+    pub fn with_default(self, default: Color) -> EventColors {
+        EventColors { default, ..self }
+    }
+
+    /// Replaces one of the provider's event colors, e.g. Google's "Tomato", which is `11`
+    pub fn with_event_color(mut self, color_id: &str, color: Color) -> EventColors {
+        self.by_color_id.insert(color_id.to_string(), color);
+        self
+    }
+
+    /// Replaces the color of a calendar, by its name or ID
+    pub fn with_calendar_color(mut self, calendar: &str, color: Color) -> EventColors {
+        self.by_calendar.insert(calendar.to_string(), color);
+        self
+    }
+
+    /// An event's own color wins over its calendar's, as it does in the provider's UI
+    pub fn color_of(&self, event: &CalendarEvent) -> Color {
+        let own = event
+            .color_id
+            .as_ref()
+            .and_then(|color_id| self.by_color_id.get(color_id).copied())
+            .or(event.color);
+        let calendar = || {
+            let source = event.source.as_ref()?;
+            self.by_calendar
+                .get(&source.calendar_name)
+                .or_else(|| self.by_calendar.get(&source.calendar_id))
+                .copied()
+                .or(source.background_color)
+        };
+        own.or_else(calendar).unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOMATO: Color = Color { r: 213, g: 0, b: 0 };
+    const SAGE: Color = Color {
+        r: 51,
+        g: 182,
+        b: 121,
+    };
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+
+    fn event(
+        color_id: Option<&str>,
+        color: Option<Color>,
+        calendar_color: Option<Color>,
+    ) -> CalendarEvent {
+        CalendarEvent {
+            color_id: color_id.map(str::to_string),
+            color,
+            source: Some(EventSource {
+                calendar_id: "team@example.com".to_string(),
+                calendar_name: "Team".to_string(),
+                background_color: calendar_color,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prefers_the_events_color_then_the_calendars() {
+        let colors = EventColors::new();
+        assert_eq!(
+            TOMATO,
+            colors.color_of(&event(Some("11"), Some(TOMATO), Some(SAGE)))
+        );
+        assert_eq!(SAGE, colors.color_of(&event(None, None, Some(SAGE))));
+        assert_eq!(
+            Color {
+                r: 70,
+                g: 127,
+                b: 200
+            },
+            colors.color_of(&CalendarEvent::default())
+        );
+    }
+
+    #[test]
+    fn overrides_event_and_calendar_colors() {
+        let colors = EventColors::new()
+            .with_event_color("11", RED)
+            .with_calendar_color("Team", TOMATO)
+            .with_default(SAGE);
+        assert_eq!(RED, colors.color_of(&event(Some("11"), Some(TOMATO), None)));
+        assert_eq!(
+            SAGE,
+            colors.color_of(&event(Some("2"), Some(SAGE), Some(RED)))
+        );
+        assert_eq!(TOMATO, colors.color_of(&event(None, None, Some(SAGE))));
+        assert_eq!(SAGE, colors.color_of(&CalendarEvent::default()));
+    }
+}
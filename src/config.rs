@@ -0,0 +1,602 @@
+use crate::calendar::caldav::CalDavAuth;
+use crate::calendar::command::CommandCalendar;
+use crate::calendar::filter::*;
+use crate::calendar::holidays::HolidayCalendar;
+use crate::calendar::ics::{IcsCalendar, IcsSource};
+use crate::calendar::tasks::*;
+use crate::calendar::todotxt::TodoTxtList;
+use crate::calendar::CalendarSelector;
+use crate::colors::EventColors;
+use crate::model::Color;
+use crate::redaction::*;
+use regex::Regex;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Settings read from `$XDG_CONFIG_HOME/cast-schedule/config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The friendly name of the cast device to show the schedule on
+    pub device: Option<String>,
+    pub google: Option<GoogleConfig>,
+    pub microsoft: Option<MicrosoftConfig>,
+    pub ics: Option<IcsConfig>,
+    pub caldav: Option<CalDavConfig>,
+    /// Programs that print events, as `[[command]]` tables
+    pub command: Vec<CommandConfig>,
+    /// To-do lists to show next to the schedule
+    pub tasks: TasksConfig,
+    /// Holidays to note on each day instead of showing as events
+    pub annotations: AnnotationConfig,
+    /// Where to remember logins between runs
+    pub token_storage: TokenStorageKind,
+    /// Which events to leave off the schedule
+    pub filter: FilterConfig,
+    /// Which events' titles to hide
+    pub redaction: RedactionConfig,
+    /// Colors to draw events in instead of the provider's
+    pub colors: ColorConfig,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenStorageKind {
+    /// A file in the config directory that only the current user can read
+    #[default]
+    File,
+    /// The desktop keyring, through the freedesktop Secret Service API
+    SecretService,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GoogleConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// IDs of calendars to show
+    #[serde(default)]
+    pub calendars: Vec<String>,
+    /// Regexes matching names of calendars to show
+    #[serde(default)]
+    pub calendar_names: Vec<String>,
+    /// How many events to fetch from one calendar at most
+    pub max_events: Option<usize>,
+}
+
+/// Outlook / Microsoft 365 calendars
+#[derive(Debug, Deserialize)]
+pub struct MicrosoftConfig {
+    /// The application (client) ID of an app registration that allows public client flows
+    pub client_id: String,
+    /// Which directory's accounts can log in; by default any work, school or personal account can
+    pub tenant: Option<String>,
+    /// IDs of calendars to show
+    #[serde(default)]
+    pub calendars: Vec<String>,
+    /// Regexes matching names of calendars to show
+    #[serde(default)]
+    pub calendar_names: Vec<String>,
+    /// How many events to fetch from one calendar at most
+    pub max_events: Option<usize>,
+}
+
+/// Which events to leave off the schedule. Declined, free and cancelled events are hidden unless these are turned off.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    pub hide_declined: bool,
+    pub hide_free: bool,
+    pub hide_cancelled: bool,
+    /// If set, only events that match at least one of these are shown
+    pub include: Vec<EventPatternConfig>,
+    /// Events that match any of these are hidden
+    pub exclude: Vec<EventPatternConfig>,
+}
+
+/// Which events' titles to hide from people walking past. Private events are always hidden.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// What to show instead: "busy" or "calendar-name"
+    pub placeholder: Placeholder,
+    /// Events that match any of these are hidden too
+    pub patterns: Vec<EventPatternConfig>,
+    /// Names of cast devices in shared spaces, which hide every event
+    pub public_displays: Vec<String>,
+}
+
+/// Colors like `#9fe1e7`, to use instead of the ones the calendar provider picked
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ColorConfig {
+    /// For events whose calendar doesn't have a color
+    pub default: Option<String>,
+    /// By the provider's ID for an event color, like Google's "1" to "11"
+    pub events: HashMap<String, String>,
+    /// By calendar name or ID
+    pub calendars: HashMap<String, String>,
+}
+
+/// A regex to match against part of an event, like `{ title = "^Lunch$" }`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventPatternConfig {
+    Title(String),
+    /// The calendar's name or ID
+    Calendar(String),
+    /// The organizer's email address or name
+    Organizer(String),
+}
+
+/// Calendars read from iCalendar files, or subscribed to by URL
+#[derive(Debug, Deserialize)]
+pub struct IcsConfig {
+    /// Paths, `file://` URLs, or `http(s)://` and `webcal://` subscription URLs
+    pub calendars: Vec<String>,
+}
+
+/// To-do lists, whose tasks that are due today or overdue are listed next to the schedule
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TasksConfig {
+    /// Paths of todo.txt files
+    pub todo_txt: Vec<PathBuf>,
+    /// iCalendar files with VTODOs in, as paths or URLs like `[ics]` calendars
+    pub ics: Vec<String>,
+}
+
+/// Holidays and the like, which label and tint the days they're on
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AnnotationConfig {
+    /// Calendars whose all-day events are annotations, like `{ calendar = "^Holidays in" }`
+    pub calendars: Vec<EventPatternConfig>,
+    /// Countries to show the public holidays of, like `US` or `GB`, without a calendar for them
+    pub countries: Vec<String>,
+}
+
+/// A CalDAV server, like Nextcloud, Radicale, or iCloud
+#[derive(Debug, Deserialize)]
+pub struct CalDavConfig {
+    /// Where to start looking for the user's calendars, usually the server's DAV root
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// A bearer token to log in with instead of a username and password
+    pub token: Option<String>,
+    /// URLs of calendars to show
+    #[serde(default)]
+    pub calendars: Vec<String>,
+    /// Regexes matching names of calendars to show
+    #[serde(default)]
+    pub calendar_names: Vec<String>,
+}
+
+/// A program that prints events as JSON; see [`crate::calendar::command`] for what it should print
+#[derive(Debug, Deserialize)]
+pub struct CommandConfig {
+    pub name: String,
+    pub program: PathBuf,
+    /// `{start}` and `{end}` are replaced with the time range to list
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub timeout_secs: Option<u64>,
+    pub max_output_bytes: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Regex(regex::Error),
+    /// A calendar location that isn't a path or a supported URL
+    CalendarSource(String),
+    /// A username without a password, or a password without a username
+    IncompleteLogin,
+    /// A color that isn't written like `#9fe1e7`
+    Color(String),
+    /// A country there's no list of holidays for
+    Country(String),
+}
+
+/// Where this crate keeps its config file and anything else it needs to remember
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cast-schedule"))
+}
+
+/// Where this crate keeps things it can fetch again if they're lost, like cached events
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("cast-schedule"))
+}
+
+impl Config {
+    /// Reads the config file from the usual place, or uses defaults if there isn't one
+    pub fn load() -> Result<Config, ConfigError> {
+        match config_dir() {
+            Some(dir) => Config::load_from(&dir.join(CONFIG_FILE_NAME)),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Config, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).map_err(ConfigError::Parse),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(ConfigError::Io(e)),
+        }
+    }
+}
+
+impl GoogleConfig {
+    pub fn selectors(&self) -> Result<Vec<CalendarSelector>, ConfigError> {
+        selectors(&self.calendars, &self.calendar_names)
+    }
+}
+
+impl MicrosoftConfig {
+    pub fn selectors(&self) -> Result<Vec<CalendarSelector>, ConfigError> {
+        selectors(&self.calendars, &self.calendar_names)
+    }
+}
+
+impl CalDavConfig {
+    pub fn base_url(&self) -> Result<Url, ConfigError> {
+        Url::parse(&self.url).map_err(|_| ConfigError::CalendarSource(self.url.clone()))
+    }
+
+    pub fn auth(&self) -> Result<CalDavAuth, ConfigError> {
+        match (&self.token, &self.username, &self.password) {
+            (Some(token), _, _) => Ok(CalDavAuth::Bearer(token.clone())),
+            (None, Some(username), Some(password)) => Ok(CalDavAuth::Basic {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            (None, None, None) => Ok(CalDavAuth::None),
+            _ => Err(ConfigError::IncompleteLogin),
+        }
+    }
+
+    pub fn selectors(&self) -> Result<Vec<CalendarSelector>, ConfigError> {
+        selectors(&self.calendars, &self.calendar_names)
+    }
+}
+
+impl CommandConfig {
+    pub fn calendar(&self) -> CommandCalendar {
+        let mut calendar = CommandCalendar::new(self.program.clone())
+            .with_name(&self.name)
+            .with_args(self.args.clone());
+        if let Some(timeout_secs) = self.timeout_secs {
+            calendar = calendar.with_timeout(Duration::from_secs(timeout_secs));
+        }
+        if let Some(max_output_bytes) = self.max_output_bytes {
+            calendar = calendar.with_max_output(max_output_bytes);
+        }
+        calendar
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            hide_declined: true,
+            hide_free: true,
+            hide_cancelled: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl FilterConfig {
+    pub fn filter(&self) -> Result<EventFilter, ConfigError> {
+        let mut filter = EventFilter::new();
+        for (hide, rule) in [
+            (self.hide_declined, FilterRule::Declined),
+            (self.hide_free, FilterRule::Free),
+            (self.hide_cancelled, FilterRule::Cancelled),
+        ] {
+            if hide {
+                filter = filter.with_rule(rule);
+            }
+        }
+        if !self.include.is_empty() {
+            let patterns = self
+                .include
+                .iter()
+                .map(EventPatternConfig::pattern)
+                .collect::<Result<_, _>>()?;
+            filter = filter.with_rule(FilterRule::IncludeOnly(patterns));
+        }
+        for pattern in &self.exclude {
+            filter = filter.with_rule(FilterRule::Exclude(pattern.pattern()?));
+        }
+        Ok(filter)
+    }
+}
+
+impl RedactionConfig {
+    /// The policy for the schedule shown on the named device
+    pub fn policy_for(&self, device_name: &str) -> Result<RedactionPolicy, ConfigError> {
+        let mut policy = RedactionPolicy::new().with_placeholder(self.placeholder);
+        for pattern in &self.patterns {
+            policy = policy.with_pattern(pattern.pattern()?);
+        }
+        if self.public_displays.iter().any(|name| name == device_name) {
+            policy = policy.hiding_everything();
+        }
+        Ok(policy)
+    }
+}
+
+impl ColorConfig {
+    pub fn colors(&self) -> Result<EventColors, ConfigError> {
+        let parse =
+            |hex: &String| Color::from_hex(hex).ok_or_else(|| ConfigError::Color(hex.clone()));
+        let mut colors = EventColors::new();
+        if let Some(default) = &self.default {
+            colors = colors.with_default(parse(default)?);
+        }
+        for (color_id, color) in &self.events {
+            colors = colors.with_event_color(color_id, parse(color)?);
+        }
+        for (calendar, color) in &self.calendars {
+            colors = colors.with_calendar_color(calendar, parse(color)?);
+        }
+        Ok(colors)
+    }
+}
+
+impl EventPatternConfig {
+    fn pattern(&self) -> Result<EventPattern, ConfigError> {
+        let regex = |pattern: &str| Regex::new(pattern).map_err(ConfigError::Regex);
+        Ok(match self {
+            EventPatternConfig::Title(pattern) => EventPattern::Title(regex(pattern)?),
+            EventPatternConfig::Calendar(pattern) => EventPattern::Calendar(regex(pattern)?),
+            EventPatternConfig::Organizer(pattern) => EventPattern::Organizer(regex(pattern)?),
+        })
+    }
+}
+
+fn selectors(ids: &[String], names: &[String]) -> Result<Vec<CalendarSelector>, ConfigError> {
+    let ids = ids.iter().map(|id| Ok(CalendarSelector::Id(id.clone())));
+    let names = names.iter().map(|name| {
+        Regex::new(name)
+            .map(CalendarSelector::Name)
+            .map_err(ConfigError::Regex)
+    });
+    ids.chain(names).collect()
+}
+
+impl IcsConfig {
+    pub fn sources(&self) -> Result<Vec<IcsSource>, ConfigError> {
+        self.calendars
+            .iter()
+            .map(|location| {
+                IcsSource::parse(location)
+                    .ok_or_else(|| ConfigError::CalendarSource(location.clone()))
+            })
+            .collect()
+    }
+}
+
+impl AnnotationConfig {
+    pub fn patterns(&self) -> Result<Vec<EventPattern>, ConfigError> {
+        self.calendars
+            .iter()
+            .map(EventPatternConfig::pattern)
+            .collect()
+    }
+
+    pub fn holidays(&self) -> Result<Vec<HolidayCalendar>, ConfigError> {
+        self.countries
+            .iter()
+            .map(|code| {
+                HolidayCalendar::for_country(code).ok_or_else(|| ConfigError::Country(code.clone()))
+            })
+            .collect()
+    }
+}
+
+impl TasksConfig {
+    /// Every configured list, with the tasks `filter` hides left out
+    pub fn lists(&self, filter: EventFilter) -> Result<TaskLists<AnyTaskList>, ConfigError> {
+        let mut lists = TaskLists::new(filter);
+        for path in &self.todo_txt {
+            lists = lists.with_list(
+                &path.display().to_string(),
+                AnyTaskList::TodoTxt(TodoTxtList::new(path.clone())),
+            );
+        }
+        if !self.ics.is_empty() {
+            let sources = IcsConfig {
+                calendars: self.ics.clone(),
+            }
+            .sources()?;
+            lists = lists.with_list(
+                "iCalendar tasks",
+                AnyTaskList::Ics(IcsCalendar::new(sources)),
+            );
+        }
+        Ok(lists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::*;
+
+    #[test]
+    fn reads_filter_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [filter]
+            hide_free = false
+            exclude = [{ title = "^Lunch$" }, { organizer = "@newsletters\\." }]
+            "#,
+        )
+        .unwrap();
+        let filter = config.filter.filter().unwrap();
+
+        let event = |summary: &str, transparency| CalendarEvent {
+            summary: summary.to_string(),
+            transparency,
+            ..Default::default()
+        };
+        assert!(filter.shows(&event("Hold for travel", Transparency::Transparent)));
+        assert!(!filter.shows(&event("Lunch", Transparency::Opaque)));
+        assert!(!filter.shows(&CalendarEvent {
+            status: EventStatus::Cancelled,
+            ..event("Standup", Transparency::Opaque)
+        }));
+    }
+
+    #[test]
+    fn reads_redaction_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [redaction]
+            placeholder = "calendar-name"
+            patterns = [{ title = "(?i)interview" }]
+            public_displays = ["Office TV"]
+            "#,
+        )
+        .unwrap();
+        let standup = CalendarEvent {
+            summary: "Standup".to_string(),
+            ..Default::default()
+        };
+
+        assert!(!config
+            .redaction
+            .policy_for("Living Room TV")
+            .unwrap()
+            .hides(&standup));
+        assert!(config
+            .redaction
+            .policy_for("Office TV")
+            .unwrap()
+            .hides(&standup));
+    }
+
+    #[test]
+    fn reads_color_overrides() {
+        let config: Config = toml::from_str(
+            r##"
+            [colors]
+            default = "#616161"
+            events = { "11" = "#ff0000" }
+            calendars = { "Team" = "#33b679" }
+            "##,
+        )
+        .unwrap();
+        let colors = config.colors.colors().unwrap();
+
+        let tomato = CalendarEvent {
+            color_id: Some("11".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(Color { r: 255, g: 0, b: 0 }, colors.color_of(&tomato));
+        assert_eq!(
+            Color {
+                r: 97,
+                g: 97,
+                b: 97
+            },
+            colors.color_of(&CalendarEvent::default())
+        );
+
+        let config: Config = toml::from_str("[colors]\ndefault = \"blue\"").unwrap();
+        assert!(
+            matches!(config.colors.colors(), Err(ConfigError::Color(color)) if color == "blue")
+        );
+    }
+
+    #[test]
+    fn reads_command_calendars() {
+        let config: Config = toml::from_str(
+            r#"
+            [[command]]
+            name = "On call"
+            program = "/usr/local/bin/rota"
+            args = ["--from", "{start}"]
+            timeout_secs = 5
+
+            [[command]]
+            name = "Lab"
+            program = "lab-bookings"
+            "#,
+        )
+        .unwrap();
+
+        let names: Vec<&str> = config
+            .command
+            .iter()
+            .map(|command| command.name.as_str())
+            .collect();
+        assert_eq!(vec!["On call", "Lab"], names);
+        assert_eq!(vec!["--from", "{start}"], config.command[0].args);
+        assert_eq!(Some(5), config.command[0].timeout_secs);
+        assert!(config.command[1].args.is_empty());
+    }
+
+    #[test]
+    fn reads_task_lists() {
+        let config: Config = toml::from_str(
+            r#"
+            [tasks]
+            todo_txt = ["/home/me/todo.txt"]
+            ics = ["webcal://example.com/chores.ics"]
+            "#,
+        )
+        .unwrap();
+        assert!(!config.tasks.lists(EventFilter::new()).unwrap().is_empty());
+        assert!(Config::default()
+            .tasks
+            .lists(EventFilter::new())
+            .unwrap()
+            .is_empty());
+
+        let config: Config =
+            toml::from_str("[tasks]\nics = [\"ftp://example.com/chores.ics\"]").unwrap();
+        assert!(matches!(
+            config.tasks.lists(EventFilter::new()),
+            Err(ConfigError::CalendarSource(_))
+        ));
+    }
+
+    #[test]
+    fn reads_annotation_sources() {
+        let config: Config = toml::from_str(
+            r#"
+            [annotations]
+            calendars = [{ calendar = "^Holidays in" }]
+            countries = ["US", "de"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(1, config.annotations.patterns().unwrap().len());
+        let names: Vec<String> = config
+            .annotations
+            .holidays()
+            .unwrap()
+            .iter()
+            .map(HolidayCalendar::name)
+            .collect();
+        assert_eq!(
+            vec!["Holidays in United States", "Holidays in Germany"],
+            names
+        );
+
+        let config: Config = toml::from_str("[annotations]\ncountries = [\"Atlantis\"]").unwrap();
+        assert!(
+            matches!(config.annotations.holidays(), Err(ConfigError::Country(country)) if country == "Atlantis")
+        );
+    }
+}
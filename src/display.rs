@@ -0,0 +1,185 @@
+use crate::draw::{DrawingBounds, Metrics};
+use crate::scan::FoundDevice;
+
+/// Everything in this crate is laid out against a 720x480 frame, then scaled up to the real screen.
+const DESIGN_HEIGHT: u32 = 480;
+
+/// The `ca` TXT record bit set by devices that can show video, i.e. that have a screen
+const CAPABILITY_VIDEO_OUT: u32 = 1 << 0;
+
+/// The size of the frames to render for a particular cast device,
+/// and how much bigger than the 720x480 design size they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayProfile {
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+}
+
+impl DisplayProfile {
+    pub const SD: DisplayProfile = DisplayProfile::new(720, 480);
+    pub const HD: DisplayProfile = DisplayProfile::new(1280, 720);
+    pub const FULL_HD: DisplayProfile = DisplayProfile::new(1920, 1080);
+    pub const ULTRA_HD: DisplayProfile = DisplayProfile::new(3840, 2160);
+    pub const NEST_HUB: DisplayProfile = DisplayProfile::new(1024, 600);
+    pub const NEST_HUB_MAX: DisplayProfile = DisplayProfile::new(1280, 800);
+
+    pub const fn new(width: u32, height: u32) -> DisplayProfile {
+        DisplayProfile {
+            width,
+            height,
+            scale: height as f32 / DESIGN_HEIGHT as f32,
+        }
+    }
+
+    /// Picks a render size from what the device advertised over mDNS.
+    /// Returns `None` for devices that can't show anything, like speakers.
+    pub fn for_device(device: &FoundDevice) -> Option<DisplayProfile> {
+        if device
+            .capabilities
+            .is_some_and(|ca| ca & CAPABILITY_VIDEO_OUT == 0)
+        {
+            return None;
+        }
+
+        Some(match &device.model {
+            Some(model) => DisplayProfile::for_model(model),
+            None => DisplayProfile::FULL_HD,
+        })
+    }
+
+    /// Picks a render size from a device's model name (its `md` TXT record).
+    /// Anything we don't recognize is assumed to be a 1080p TV.
+    pub fn for_model(model: &str) -> DisplayProfile {
+        let model = model.to_lowercase();
+        if model.contains("nest hub max") {
+            DisplayProfile::NEST_HUB_MAX
+        } else if model.contains("nest hub") || model.contains("home hub") {
+            DisplayProfile::NEST_HUB
+        } else if model.contains("ultra") || model.contains("4k") || model.contains("uhd") {
+            DisplayProfile::ULTRA_HD
+        } else if model.contains("chromecast hd") {
+            DisplayProfile::HD
+        } else {
+            DisplayProfile::FULL_HD
+        }
+    }
+
+    pub fn bounds(&self) -> DrawingBounds {
+        DrawingBounds {
+            left: 0,
+            top: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        Metrics::scaled(self.scale)
+    }
+}
+
+impl Default for DisplayProfile {
+    fn default() -> Self {
+        DisplayProfile::FULL_HD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::*;
+
+    fn device(model: Option<&str>, capabilities: Option<u32>) -> FoundDevice {
+        FoundDevice {
+            addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8009),
+            name: "Living Room".to_string(),
+            hostname: "living-room.local".to_string(),
+            model: model.map(|m| m.to_string()),
+            capabilities,
+        }
+    }
+
+    #[test]
+    fn given_nest_hub_then_native_panel_size() {
+        assert_eq!(
+            Some(DisplayProfile::NEST_HUB),
+            DisplayProfile::for_device(&device(Some("Google Nest Hub"), Some(0x1e05)))
+        );
+        assert_eq!(
+            Some(DisplayProfile::NEST_HUB_MAX),
+            DisplayProfile::for_device(&device(Some("Google Nest Hub Max"), None))
+        );
+    }
+
+    #[test]
+    fn given_4k_model_then_ultra_hd() {
+        assert_eq!(
+            DisplayProfile::ULTRA_HD,
+            DisplayProfile::for_model("Chromecast Ultra")
+        );
+        assert_eq!(
+            DisplayProfile::ULTRA_HD,
+            DisplayProfile::for_model("BRAVIA 4K GB")
+        );
+    }
+
+    #[test]
+    fn given_unknown_or_missing_model_then_full_hd() {
+        assert_eq!(
+            Some(DisplayProfile::FULL_HD),
+            DisplayProfile::for_device(&device(Some("Chromecast"), Some(0x1205)))
+        );
+        assert_eq!(
+            Some(DisplayProfile::FULL_HD),
+            DisplayProfile::for_device(&device(None, None))
+        );
+    }
+
+    #[test]
+    fn given_speaker_then_no_display() {
+        assert_eq!(
+            None,
+            DisplayProfile::for_device(&device(Some("Google Nest Mini"), Some(0x0804)))
+        );
+    }
+
+    #[test]
+    fn scale_is_relative_to_design_height() {
+        assert_eq!(1.0, DisplayProfile::SD.scale);
+        assert_eq!(2.25, DisplayProfile::FULL_HD.scale);
+        assert_eq!(4.5, DisplayProfile::ULTRA_HD.scale);
+    }
+
+    #[test]
+    fn metrics_scale_fonts_lines_and_paddings() {
+        assert_eq!(
+            Metrics {
+                font_size: 14,
+                line_width: 1,
+                padding: 4,
+            },
+            Metrics::scaled(1.0)
+        );
+        assert_eq!(
+            Metrics {
+                font_size: 32,
+                line_width: 2,
+                padding: 9,
+            },
+            Metrics::scaled(DisplayProfile::FULL_HD.scale)
+        );
+    }
+
+    #[test]
+    fn metrics_never_scale_below_one_pixel() {
+        assert_eq!(
+            Metrics {
+                font_size: 4,
+                line_width: 1,
+                padding: 1,
+            },
+            Metrics::scaled(0.25)
+        );
+    }
+}
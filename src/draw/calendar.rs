@@ -9,10 +9,68 @@ where
     (num - source_start) * (dest_end - dest_start) / (source_end - source_start) + dest_start
 }
 
+/// How to fill the blocks of each kind of event
+#[derive(Debug, Clone)]
+struct EventStyles {
+    /// Ordinary events are filled with their own color, and outlined with this
+    outline: Option<Stroke>,
+    colors: EventColors,
+    focus_time: Style,
+    out_of_office: Style,
+    /// The stripes that hatch out-of-office blocks, so they read as time away even without color
+    hatch: Style,
+    hatch_width: u32,
+    hatch_spacing: u32,
+}
+
+impl EventStyles {
+    fn block(
+        &self,
+        event: &CalendarEvent,
+        left: u32,
+        top: u32,
+        width: u32,
+        height: u32,
+    ) -> Vec<Drawing> {
+        let style = match event.kind {
+            EventKind::FocusTime => self.focus_time.clone(),
+            EventKind::OutOfOffice => self.out_of_office.clone(),
+            _ => {
+                let color = self.colors.color_of(event);
+                Style {
+                    stroke: self.outline.clone(),
+                    ..Style::filled(RGB::new(color.r, color.g, color.b))
+                }
+            }
+        };
+        let mut drawings = vec![Drawing::new()
+            .with_shape(Shape::Rectangle { width, height })
+            .with_xy(left as f32, top as f32)
+            .with_style(style)];
+        if event.kind == EventKind::OutOfOffice {
+            let bottom = top + height;
+            drawings.extend(
+                (top + self.hatch_spacing..bottom)
+                    .step_by(self.hatch_spacing.max(1) as usize)
+                    .map(|y| {
+                        Drawing::new()
+                            .with_shape(Shape::Rectangle {
+                                width,
+                                height: min(self.hatch_width, bottom - y),
+                            })
+                            .with_xy(left as f32, y as f32)
+                            .with_style(self.hatch.clone())
+                    }),
+            );
+        }
+        drawings
+    }
+}
+
 struct TimeRangeDrawer<TZ: TimeZone> {
     start: DateTime<TZ>,
     duration: Duration,
-    style: Style,
+    styles: EventStyles,
 }
 
 impl<TZ> TimeRangeDrawer<TZ>
@@ -35,6 +93,11 @@ where
     type Subject = CalendarEvent;
 
     fn draw(&self, event: &CalendarEvent, bounds: &DrawingBounds) -> Vec<Drawing> {
+        // Shown in the day header instead
+        if event.working_location().is_some() || event.kind == EventKind::Annotation {
+            return vec![];
+        }
+
         if event.times.start.is_some_and(|start| start >= self.end()) {
             return vec![];
         }
@@ -73,15 +136,8 @@ where
             bounds.top + bounds.height,
         );
 
-        println!("{:?}", &self.style);
-
-        vec![Drawing::new()
-            .with_shape(Shape::Rectangle {
-                width: bounds.width,
-                height: event_height,
-            })
-            .with_xy(bounds.left as f32, event_top as f32)
-            .with_style(self.style.clone())]
+        self.styles
+            .block(event, bounds.left, event_top, bounds.width, event_height)
     }
 }
 
@@ -91,7 +147,11 @@ pub struct CalendarDrawer<TZ> {
     day_start_time: NaiveTime,
     day_duration: Duration,
     time_zone: TZ,
-    base_style: Style,
+    colors: EventColors,
+    metrics: Metrics,
+    annotations: Vec<DayAnnotation>,
+    stale_since: Option<DateTime<Utc>>,
+    failed_calendars: Vec<String>,
 }
 
 impl<TZ: TimeZone> CalendarDrawer<TZ> {
@@ -102,7 +162,43 @@ impl<TZ: TimeZone> CalendarDrawer<TZ> {
             day_start_time: times.start.time(),
             day_duration: (times.end.time() - times.start.time()),
             time_zone: times.start.timezone(),
-            base_style: Style::filled(RGB::new(70, 127, 200)),
+            colors: EventColors::new(),
+            metrics: Metrics::default(),
+            annotations: Vec::new(),
+            stale_since: None,
+            failed_calendars: Vec::new(),
+        }
+    }
+
+    pub fn with_metrics(self, metrics: Metrics) -> CalendarDrawer<TZ> {
+        CalendarDrawer { metrics, ..self }
+    }
+
+    pub fn with_colors(self, colors: EventColors) -> CalendarDrawer<TZ> {
+        CalendarDrawer { colors, ..self }
+    }
+
+    /// Holidays and the like, to label and tint the days they're on
+    pub fn with_annotations(self, annotations: Vec<DayAnnotation>) -> CalendarDrawer<TZ> {
+        CalendarDrawer {
+            annotations,
+            ..self
+        }
+    }
+
+    /// When the events were last fetched, if they're out of date, to say so in the corner
+    pub fn with_stale_since(self, stale_since: Option<DateTime<Utc>>) -> CalendarDrawer<TZ> {
+        CalendarDrawer {
+            stale_since,
+            ..self
+        }
+    }
+
+    /// The calendars that couldn't be fetched, to say in the corner that their events are missing
+    pub fn with_failed_calendars(self, failed_calendars: Vec<String>) -> CalendarDrawer<TZ> {
+        CalendarDrawer {
+            failed_calendars,
+            ..self
         }
     }
 
@@ -110,7 +206,31 @@ impl<TZ: TimeZone> CalendarDrawer<TZ> {
         TimeRangeDrawer {
             start: self.start_of_day(day_num),
             duration: self.day_duration,
-            style: self.base_style.clone(), // TODO: different colors for past/future
+            styles: self.event_styles(), // TODO: different colors for past/future
+        }
+    }
+
+    fn event_styles(&self) -> EventStyles {
+        let outlined = |color: RGB| Style {
+            stroke: Some(self.outline()),
+            ..Style::filled(color)
+        };
+        EventStyles {
+            outline: Some(self.outline()),
+            colors: self.colors.clone(),
+            focus_time: outlined(RGB::new(142, 103, 194)),
+            out_of_office: outlined(RGB::new(150, 150, 150)),
+            hatch: Style::filled(RGB::new(110, 110, 110)),
+            hatch_width: self.metrics.line_width * 2,
+            hatch_spacing: self.metrics.padding * 2,
+        }
+    }
+
+    /// Outlines each event so that back-to-back events stay distinguishable at any resolution
+    fn outline(&self) -> Stroke {
+        Stroke {
+            width: self.metrics.line_width,
+            color: RGB::new(255, 255, 255),
         }
     }
 
@@ -145,11 +265,179 @@ impl<TZ: TimeZone> CalendarDrawer<TZ> {
     fn end_of_last_day(&self) -> DateTime<TZ> {
         self.end_of_day(self.num_days - 1)
     }
+
+    fn visible_dates(&self) -> DateRange {
+        DateRange {
+            start: self.start_date,
+            end: self.date(self.num_days),
+        }
+    }
+
+    /// Where the calendar's owner is working on a day, if a working location event covers any of its visible hours
+    fn working_location_on<'a>(
+        &self,
+        events: &'a [CalendarEvent],
+        day_num: u32,
+    ) -> Option<&'a WorkingLocation> {
+        let date = self.date(day_num);
+        let (day_start, day_end) = (self.start_of_day(day_num), self.end_of_day(day_num));
+        events
+            .iter()
+            .filter(|event| match &event.all_day {
+                Some(dates) => dates.contains(date),
+                None => {
+                    event
+                        .times
+                        .start
+                        .is_none_or(|start| start.with_timezone(&self.time_zone) < day_end)
+                        && event
+                            .times
+                            .end
+                            .is_none_or(|end| end.with_timezone(&self.time_zone) > day_start)
+                }
+            })
+            .find_map(|event| event.working_location())
+    }
+
+    fn annotations_on(&self, day_num: u32) -> Vec<&DayAnnotation> {
+        let date = self.date(day_num);
+        self.annotations
+            .iter()
+            .filter(|annotation| annotation.date == date)
+            .collect()
+    }
+
+    /// What to say about a day in its header: where the calendar's owner is working, then any holidays
+    fn day_header_label(&self, location: Option<&WorkingLocation>, day_num: u32) -> Option<String> {
+        let labels: Vec<&str> = location
+            .map(WorkingLocation::label)
+            .into_iter()
+            .chain(
+                self.annotations_on(day_num)
+                    .into_iter()
+                    .map(|annotation| annotation.label.as_str()),
+            )
+            .collect();
+        (!labels.is_empty()).then(|| labels.join(" · "))
+    }
+
+    /// Labels each day with where the calendar's owner is working and what's special about it
+    fn draw_day_header(&self, labels: &[Option<String>], bounds: &DrawingBounds) -> Vec<Drawing> {
+        let day_width = bounds.width / self.num_days;
+        labels
+            .iter()
+            .enumerate()
+            .filter_map(|(day_num, text)| {
+                Some(label(
+                    text.as_deref()?,
+                    self.metrics.font_size,
+                    RGB::new(60, 60, 60),
+                    bounds.left + day_num as u32 * day_width + self.metrics.padding,
+                    bounds.top + self.metrics.padding,
+                ))
+            })
+            .collect()
+    }
+
+    /// Tints the whole column of each annotated day, in a pale version of the annotation's color
+    fn draw_day_tints(&self, bounds: &DrawingBounds) -> Vec<Drawing> {
+        let day_width = bounds.width / self.num_days;
+        (0..self.num_days)
+            .filter_map(|day_num| {
+                let annotation = *self.annotations_on(day_num).first()?;
+                let color = annotation.color.unwrap_or(Color {
+                    r: 230,
+                    g: 124,
+                    b: 115,
+                });
+                let pale = |c: u8| c + (255 - c) / 4 * 3;
+                Some(
+                    Drawing::new()
+                        .with_shape(Shape::Rectangle {
+                            width: day_width,
+                            height: bounds.height,
+                        })
+                        .with_xy(
+                            (bounds.left + day_num * day_width) as f32,
+                            bounds.top as f32,
+                        )
+                        .with_style(Style::filled(RGB::new(
+                            pale(color.r),
+                            pale(color.g),
+                            pale(color.b),
+                        ))),
+                )
+            })
+            .collect()
+    }
+
+    /// Says when the events were fetched, and which calendars are missing, in the bottom left corner,
+    /// so an old or partial schedule doesn't pass for a current one
+    fn draw_stale_label(&self, bounds: &DrawingBounds) -> Option<Drawing> {
+        let updated = self.stale_since.map(|fetched_at| {
+            format!(
+                "updated {}",
+                fetched_at
+                    .with_timezone(&self.time_zone)
+                    .naive_local()
+                    .format("%H:%M")
+            )
+        });
+        let missing = (!self.failed_calendars.is_empty())
+            .then(|| format!("missing {}", self.failed_calendars.join(", ")));
+        let notes: Vec<String> = updated.into_iter().chain(missing).collect();
+        if notes.is_empty() {
+            return None;
+        }
+        Some(label(
+            &notes.join("; "),
+            self.metrics.font_size,
+            RGB::new(200, 40, 40),
+            bounds.left + self.metrics.padding,
+            (bounds.top + bounds.height)
+                .saturating_sub(self.metrics.font_size + self.metrics.padding),
+        ))
+    }
+
+    /// All-day events get a row each in a banner strip above the day columns
+    fn banner_row_height(&self) -> u32 {
+        self.metrics.font_size + 2 * self.metrics.padding
+    }
+
+    fn draw_all_day_banners(
+        &self,
+        all_day_events: &[&CalendarEvent],
+        bounds: &DrawingBounds,
+    ) -> Vec<Drawing> {
+        let visible = self.visible_dates();
+        let day_width = bounds.width / self.num_days;
+        let row_height = self.banner_row_height();
+        let styles = self.event_styles();
+        all_day_events
+            .iter()
+            .filter_map(|event| Some((event.all_day.as_ref()?, *event)))
+            .enumerate()
+            .flat_map(|(row, (dates, event))| {
+                let first_day =
+                    (max(dates.start, visible.start) - self.start_date).num_days() as u32;
+                let end_day = (min(dates.end, visible.end) - self.start_date).num_days() as u32;
+                styles.block(
+                    event,
+                    bounds.left + first_day * day_width,
+                    bounds.top + row as u32 * row_height,
+                    (end_day - first_day) * day_width,
+                    row_height,
+                )
+            })
+            .collect()
+    }
 }
 
 use super::*;
+use crate::colors::EventColors;
 use crate::model::*;
 use draw::*;
+use std::cmp::{max, min};
 
 impl<TZ> Drawer for CalendarDrawer<TZ>
 where
@@ -158,23 +446,62 @@ where
     type Subject = [CalendarEvent];
 
     fn draw(&self, events: &Self::Subject, bounds: &DrawingBounds) -> Vec<Drawing> {
-        (0..self.num_days)
+        let visible = self.visible_dates();
+        let (all_day_events, timed_events): (Vec<_>, Vec<_>) =
+            events.iter().partition(|event| event.is_all_day());
+        let all_day_events = all_day_events
             .into_iter()
-            .flat_map(|day_num| {
-                let drawer = self.single_day_drawer(day_num);
-                let sub_bounds = DrawingBounds {
-                    left: bounds.left + day_num * bounds.width / self.num_days,
-                    top: bounds.top,
-                    width: bounds.width / self.num_days,
-                    height: bounds.height,
-                };
-                println!("Drawing Day {}: {:?}", day_num, drawer.start);
-                events.iter().flat_map(move |event| {
-                    println!("\t Drawing Event {:?}", event);
-                    drawer.draw(event, &sub_bounds).into_iter()
-                })
+            .filter(|event| {
+                event.working_location().is_none() && event.kind != EventKind::Annotation
             })
-            .collect()
+            .filter(|event| {
+                event
+                    .all_day
+                    .as_ref()
+                    .is_some_and(|dates| dates.overlaps(&visible))
+            })
+            .collect::<Vec<_>>();
+
+        let header_labels: Vec<_> = (0..self.num_days)
+            .map(|day_num| {
+                self.day_header_label(self.working_location_on(events, day_num), day_num)
+            })
+            .collect();
+        let header_height = match header_labels.iter().any(Option::is_some) {
+            true => self.banner_row_height(),
+            false => 0,
+        };
+        let banners = DrawingBounds {
+            top: bounds.top + header_height,
+            height: bounds.height.saturating_sub(header_height),
+            ..bounds.clone()
+        };
+        let banner_height = all_day_events.len() as u32 * self.banner_row_height();
+        let columns = DrawingBounds {
+            top: banners.top + banner_height,
+            height: banners.height.saturating_sub(banner_height),
+            ..bounds.clone()
+        };
+
+        let mut drawings = self.draw_day_tints(bounds);
+        drawings.extend(self.draw_day_header(&header_labels, bounds));
+        drawings.extend(self.draw_all_day_banners(&all_day_events, &banners));
+        drawings.extend((0..self.num_days).into_iter().flat_map(|day_num| {
+            let drawer = self.single_day_drawer(day_num);
+            let sub_bounds = DrawingBounds {
+                left: columns.left + day_num * columns.width / self.num_days,
+                top: columns.top,
+                width: columns.width / self.num_days,
+                height: columns.height,
+            };
+            println!("Drawing Day {}: {:?}", day_num, drawer.start);
+            timed_events.iter().flat_map(move |event| {
+                println!("\t Drawing Event {:?}", event);
+                drawer.draw(event, &sub_bounds).into_iter()
+            })
+        }));
+        drawings.extend(self.draw_stale_label(bounds));
+        drawings
     }
 }
 
@@ -200,7 +527,11 @@ mod tests {
             day_start_time: TEST_START_TIME,
             day_duration: TEST_END_TIME - TEST_START_TIME,
             time_zone: Local,
-            base_style: Style::default(),
+            colors: EventColors::new(),
+            metrics: Metrics::default(),
+            annotations: Vec::new(),
+            stale_since: None,
+            failed_calendars: Vec::new(),
         }
     }
 
@@ -216,11 +547,12 @@ mod tests {
     #[test]
     fn given_infinite_event_then_both_days_are_filled() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: None,
                 end: None,
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -247,11 +579,12 @@ mod tests {
     #[test]
     fn given_single_midday_event_then_single_rectangle() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 1, 10, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 1, 12, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -270,11 +603,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_starts_early_then_single_clamped_rectangle() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 1, 0, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 1, 12, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -293,11 +627,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_runs_late_then_single_clamped_rectangle() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 1, 14, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 1, 22, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -316,11 +651,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_is_day_before_then_draw_nothing() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 8, 31, 14, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 8, 31, 22, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -331,11 +667,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_is_day_after_then_draw_nothing() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 3, 14, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 3, 22, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -346,11 +683,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_ends_at_start_of_first_day_then_draw_nothing() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 1, 6, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 1, 8, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -361,11 +699,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_starts_at_end_of_first_day_then_draw_nothing() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 1, 18, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 1, 22, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -376,11 +715,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_ends_at_start_of_second_day_then_draw_nothing() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 2, 6, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 2, 8, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -391,11 +731,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_starts_at_end_of_second_day_then_draw_nothing() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 2, 18, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 2, 22, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -406,11 +747,12 @@ mod tests {
     #[test]
     fn given_single_event_when_event_spans_both_days_then_draw_two_rectangles() {
         let events = vec![CalendarEvent {
-            name: "foo".to_string(),
+            summary: "foo".to_string(),
             times: IndefiniteTimeRange {
                 start: Some(Local.with_ymd_and_hms(2022, 9, 1, 12, 0, 0).unwrap()),
                 end: Some(Local.with_ymd_and_hms(2022, 9, 2, 12, 0, 0).unwrap()),
             },
+            ..Default::default()
         }];
         let drawer = test_drawer();
 
@@ -438,18 +780,20 @@ mod tests {
     fn given_two_events_when_events_are_disjoint_then_draw_two_fullwidth_rectangles() {
         let events = vec![
             CalendarEvent {
-                name: "foo".to_string(),
+                summary: "foo".to_string(),
                 times: IndefiniteTimeRange {
                     start: Some(Local.with_ymd_and_hms(2022, 9, 1, 12, 0, 0).unwrap()),
                     end: Some(Local.with_ymd_and_hms(2022, 9, 1, 14, 0, 0).unwrap()),
                 },
+                ..Default::default()
             },
             CalendarEvent {
-                name: "foo".to_string(),
+                summary: "foo".to_string(),
                 times: IndefiniteTimeRange {
                     start: Some(Local.with_ymd_and_hms(2022, 9, 2, 12, 0, 0).unwrap()),
                     end: Some(Local.with_ymd_and_hms(2022, 9, 2, 14, 0, 0).unwrap()),
                 },
+                ..Default::default()
             },
         ];
         let drawer = test_drawer();
@@ -474,5 +818,201 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn given_all_day_event_then_banner_above_columns() {
+        let events = vec![
+            CalendarEvent {
+                summary: "holiday".to_string(),
+                times: IndefiniteTimeRange {
+                    start: Some(Local.with_ymd_and_hms(2022, 9, 1, 0, 0, 0).unwrap()),
+                    end: Some(Local.with_ymd_and_hms(2022, 9, 2, 0, 0, 0).unwrap()),
+                },
+                all_day: Some(DateRange::single_day(TEST_DAY)),
+                ..Default::default()
+            },
+            CalendarEvent {
+                summary: "foo".to_string(),
+                times: IndefiniteTimeRange {
+                    start: Some(Local.with_ymd_and_hms(2022, 9, 1, 10, 0, 0).unwrap()),
+                    end: Some(Local.with_ymd_and_hms(2022, 9, 1, 12, 0, 0).unwrap()),
+                },
+                ..Default::default()
+            },
+        ];
+        let drawer = test_drawer();
+
+        let drawings = drawer.draw(&events, &TEST_BOUNDS);
+        assert_eq!(2, drawings.len());
+        assert_eq!(draw::Point { x: 0.0, y: 0.0 }, drawings[0].position);
+        assert!(matches!(
+            drawings[0].shape,
+            Some(Shape::Rectangle {
+                width: 100,
+                height: 22
+            })
+        ));
+        assert_eq!(draw::Point { x: 0.0, y: 37.0 }, drawings[1].position);
+        assert!(matches!(
+            drawings[1].shape,
+            Some(Shape::Rectangle {
+                width: 100,
+                height: 15
+            })
+        ));
+    }
+
+    #[test]
+    fn given_multi_day_all_day_event_then_banner_clamped_to_visible_days() {
+        let events = vec![CalendarEvent {
+            summary: "conference".to_string(),
+            times: IndefiniteTimeRange {
+                start: Some(Local.with_ymd_and_hms(2022, 8, 30, 0, 0, 0).unwrap()),
+                end: Some(Local.with_ymd_and_hms(2022, 9, 5, 0, 0, 0).unwrap()),
+            },
+            all_day: Some(DateRange {
+                start: NaiveDate::from_ymd_opt(2022, 8, 30).unwrap(),
+                end: NaiveDate::from_ymd_opt(2022, 9, 5).unwrap(),
+            }),
+            ..Default::default()
+        }];
+        let drawer = test_drawer();
+
+        let drawings = drawer.draw(&events, &TEST_BOUNDS);
+        assert_eq!(1, drawings.len());
+        assert_eq!(draw::Point { x: 0.0, y: 0.0 }, drawings[0].position);
+        assert!(matches!(
+            drawings[0].shape,
+            Some(Shape::Rectangle {
+                width: 200,
+                height: 22
+            })
+        ));
+    }
+
+    #[test]
+    fn given_out_of_office_event_then_block_is_hatched() {
+        let events = vec![CalendarEvent {
+            summary: "vacation".to_string(),
+            times: IndefiniteTimeRange {
+                start: Some(Local.with_ymd_and_hms(2022, 9, 1, 10, 0, 0).unwrap()),
+                end: Some(Local.with_ymd_and_hms(2022, 9, 1, 12, 0, 0).unwrap()),
+            },
+            kind: EventKind::OutOfOffice,
+            ..Default::default()
+        }];
+        let drawer = test_drawer();
+
+        let drawings = drawer.draw(&events, &TEST_BOUNDS);
+        assert_eq!(3, drawings.len());
+        assert_eq!(draw::Point { x: 0.0, y: 20.0 }, drawings[0].position);
+        assert_eq!(draw::Point { x: 0.0, y: 28.0 }, drawings[1].position);
+        assert_eq!(draw::Point { x: 0.0, y: 36.0 }, drawings[2].position);
+        assert!(matches!(
+            drawings[1].shape,
+            Some(Shape::Rectangle {
+                width: 100,
+                height: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn given_working_location_then_label_in_day_header() {
+        let events = vec![
+            CalendarEvent {
+                summary: "Home".to_string(),
+                times: IndefiniteTimeRange {
+                    start: Some(Local.with_ymd_and_hms(2022, 9, 1, 0, 0, 0).unwrap()),
+                    end: Some(Local.with_ymd_and_hms(2022, 9, 2, 0, 0, 0).unwrap()),
+                },
+                all_day: Some(DateRange::single_day(TEST_DAY)),
+                kind: EventKind::WorkingLocation(WorkingLocation::Home),
+                ..Default::default()
+            },
+            CalendarEvent {
+                summary: "focus".to_string(),
+                times: IndefiniteTimeRange {
+                    start: Some(Local.with_ymd_and_hms(2022, 9, 1, 10, 0, 0).unwrap()),
+                    end: Some(Local.with_ymd_and_hms(2022, 9, 1, 12, 0, 0).unwrap()),
+                },
+                kind: EventKind::FocusTime,
+                ..Default::default()
+            },
+        ];
+        let drawer = test_drawer();
+
+        let drawings = drawer.draw(&events, &TEST_BOUNDS);
+        assert_eq!(2, drawings.len());
+        assert_eq!(draw::Point { x: 4.0, y: 4.0 }, drawings[0].position);
+        assert!(matches!(&drawings[0].shape, Some(Shape::Text { text, .. }) if text == "Home"));
+        assert_eq!(draw::Point { x: 0.0, y: 37.0 }, drawings[1].position);
+        assert!(matches!(
+            drawings[1].shape,
+            Some(Shape::Rectangle {
+                width: 100,
+                height: 15
+            })
+        ));
+    }
+
+    #[test]
+    fn given_annotation_then_day_is_labelled_and_tinted() {
+        let drawer = test_drawer().with_annotations(vec![DayAnnotation {
+            date: TEST_DAY + Days::new(1),
+            label: "Labor Day".to_string(),
+            color: None,
+        }]);
+        let holiday = CalendarEvent {
+            summary: "Labor Day".to_string(),
+            all_day: Some(DateRange::single_day(TEST_DAY + Days::new(1))),
+            kind: EventKind::Annotation,
+            ..Default::default()
+        };
+
+        let drawings = drawer.draw(&[holiday], &TEST_BOUNDS);
+        assert_eq!(2, drawings.len());
+        assert_eq!(draw::Point { x: 100.0, y: 0.0 }, drawings[0].position);
+        assert!(matches!(
+            drawings[0].shape,
+            Some(Shape::Rectangle {
+                width: 100,
+                height: 100
+            })
+        ));
+        assert_eq!(draw::Point { x: 104.0, y: 4.0 }, drawings[1].position);
+        assert!(
+            matches!(&drawings[1].shape, Some(Shape::Text { text, .. }) if text == "Labor Day")
+        );
+    }
+
+    #[test]
+    fn given_stale_events_then_update_time_in_bottom_left_corner() {
+        let fetched_at = Local.with_ymd_and_hms(2022, 9, 1, 9, 5, 0).unwrap();
+        let drawer = test_drawer().with_stale_since(Some(fetched_at.with_timezone(&Utc)));
+
+        let drawings = drawer.draw(&[], &TEST_BOUNDS);
+        assert_eq!(1, drawings.len());
+        assert_eq!(draw::Point { x: 4.0, y: 82.0 }, drawings[0].position);
+        assert!(
+            matches!(&drawings[0].shape, Some(Shape::Text { text, .. }) if text == "updated 09:05")
+        );
+    }
+
+    #[test]
+    fn given_failed_calendars_then_they_are_named_in_bottom_left_corner() {
+        let fetched_at = Local.with_ymd_and_hms(2022, 9, 1, 9, 5, 0).unwrap();
+        let drawer = test_drawer()
+            .with_stale_since(Some(fetched_at.with_timezone(&Utc)))
+            .with_failed_calendars(vec!["Google".to_string(), "CalDAV".to_string()]);
+
+        let drawings = drawer.draw(&[], &TEST_BOUNDS);
+        assert_eq!(1, drawings.len());
+        assert_eq!(draw::Point { x: 4.0, y: 82.0 }, drawings[0].position);
+        assert!(matches!(
+            &drawings[0].shape,
+            Some(Shape::Text { text, .. }) if text == "updated 09:05; missing Google, CalDAV"
+        ));
+    }
+
     // TODO: add tests for overlapping events
 }
@@ -0,0 +1,44 @@
+use super::*;
+use draw::*;
+
+/// A banner across the middle of the screen saying what went wrong, for when there's nothing else to show
+pub struct ErrorDrawer {
+    pub metrics: Metrics,
+}
+
+impl Drawer for ErrorDrawer {
+    type Subject = str;
+
+    fn draw(&self, message: &str, bounds: &DrawingBounds) -> Vec<Drawing> {
+        let padding = self.metrics.padding * 4;
+        let font_size = self.metrics.font_size * 2;
+        let height = 2 * font_size + 3 * padding;
+        let top = bounds.top + bounds.height.saturating_sub(height) / 2;
+
+        let banner = Drawing::new()
+            .with_shape(Shape::Rectangle {
+                width: bounds.width,
+                height,
+            })
+            .with_xy(bounds.left as f32, top as f32)
+            .with_style(Style::filled(RGB::new(200, 40, 40)));
+        let white = RGB::new(255, 255, 255);
+        vec![
+            banner,
+            label(
+                "Could not update the schedule",
+                font_size,
+                white,
+                bounds.left + padding,
+                top + padding,
+            ),
+            label(
+                message,
+                self.metrics.font_size,
+                white,
+                bounds.left + padding,
+                top + font_size + 2 * padding,
+            ),
+        ]
+    }
+}
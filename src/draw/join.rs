@@ -0,0 +1,165 @@
+use super::qr::*;
+use super::*;
+use crate::model::*;
+use draw::*;
+
+/// How to join a meeting from a phone: which service it's on, and a QR code of the link
+pub struct JoinDrawer {
+    pub metrics: Metrics,
+}
+
+impl JoinDrawer {
+    /// A square in the service's color with its initial, standing in for its logo
+    fn icon(&self, platform: ConferencePlatform, left: u32, top: u32) -> Vec<Drawing> {
+        let size = self.icon_size();
+        let (color, initial) = match platform {
+            ConferencePlatform::Meet => (RGB::new(0, 137, 123), "M"),
+            ConferencePlatform::Zoom => (RGB::new(11, 92, 255), "Z"),
+            ConferencePlatform::Teams => (RGB::new(80, 89, 201), "T"),
+            ConferencePlatform::Other => (RGB::new(120, 120, 120), "?"),
+        };
+        let font_size = self.metrics.font_size;
+        vec![
+            Drawing::new()
+                .with_shape(Shape::Rectangle {
+                    width: size,
+                    height: size,
+                })
+                .with_xy(left as f32, top as f32)
+                .with_style(Style::filled(color)),
+            label(
+                initial,
+                font_size,
+                RGB::new(255, 255, 255),
+                left + (size - font_size / 2) / 2,
+                top + (size - font_size) / 2,
+            ),
+        ]
+    }
+
+    fn icon_size(&self) -> u32 {
+        2 * self.metrics.font_size
+    }
+}
+
+impl Drawer for JoinDrawer {
+    type Subject = CalendarEvent;
+
+    fn draw(&self, meeting: &CalendarEvent, bounds: &DrawingBounds) -> Vec<Drawing> {
+        let Some(link) = &meeting.conference_link else {
+            return vec![];
+        };
+        let padding = self.metrics.padding;
+        let font_size = self.metrics.font_size;
+        let black = RGB::new(0, 0, 0);
+
+        let left = bounds.left + padding;
+        let top = bounds.top + padding;
+        let mut drawings = self.icon(link.platform, left, top);
+        let text_left = left + self.icon_size() + padding;
+        drawings.push(label(
+            link.platform.name(),
+            font_size,
+            black,
+            text_left,
+            top,
+        ));
+        drawings.push(label(
+            &meeting.summary,
+            font_size,
+            black,
+            text_left,
+            top + font_size + padding,
+        ));
+
+        // The dial-in number goes under the QR code, so leave a line for it
+        let qr_top = top + self.icon_size() + padding;
+        let dial_in_height = match link.dial_in {
+            Some(_) => font_size + padding,
+            None => 0,
+        };
+        let qr_bounds = DrawingBounds {
+            left,
+            top: qr_top,
+            width: bounds.width.saturating_sub(2 * padding),
+            height: bounds
+                .bottom()
+                .saturating_sub(qr_top + padding + dial_in_height),
+        };
+        let qr = QrCodeDrawer.draw(&link.url, &qr_bounds);
+        let qr_bottom = qr
+            .first()
+            .and_then(|background| match background.shape {
+                Some(Shape::Rectangle { height, .. }) => Some(qr_top + height),
+                _ => None,
+            })
+            .unwrap_or(qr_top);
+        drawings.extend(qr);
+
+        if let Some(dial_in) = &link.dial_in {
+            drawings.push(label(dial_in, font_size, black, left, qr_bottom + padding));
+        }
+        drawings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: DrawingBounds = DrawingBounds {
+        left: 500,
+        top: 0,
+        width: 200,
+        height: 300,
+    };
+
+    fn texts(drawings: &[Drawing]) -> Vec<&str> {
+        drawings
+            .iter()
+            .filter_map(|drawing| match &drawing.shape {
+                Some(Shape::Text { text, .. }) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shows_the_service_the_title_and_how_to_dial_in() {
+        let meeting = CalendarEvent {
+            summary: "Planning".to_string(),
+            conference_link: Some(
+                ConferenceLink::new("https://meet.google.com/abc-defg-hij")
+                    .with_dial_in("+1 555-0100", Some("123456")),
+            ),
+            ..Default::default()
+        };
+        let drawings = JoinDrawer {
+            metrics: Metrics::default(),
+        }
+        .draw(&meeting, &BOUNDS);
+
+        assert_eq!(
+            vec!["M", "Google Meet", "Planning", "+1 555-0100 PIN: 123456#"],
+            texts(&drawings)
+        );
+        assert!(drawings
+            .iter()
+            .all(|drawing| drawing.position.x >= BOUNDS.left as f32));
+        let dial_in = drawings.last().unwrap();
+        assert!(dial_in.position.y + Metrics::default().font_size as f32 <= BOUNDS.bottom() as f32);
+    }
+
+    #[test]
+    fn draws_nothing_without_a_link() {
+        let meeting = CalendarEvent {
+            summary: "Lunch".to_string(),
+            ..Default::default()
+        };
+        assert!(JoinDrawer {
+            metrics: Metrics::default()
+        }
+        .draw(&meeting, &BOUNDS)
+        .is_empty());
+    }
+}
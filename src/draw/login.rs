@@ -0,0 +1,153 @@
+use super::qr::*;
+use super::*;
+use crate::calendar::oauth::DeviceAuthorization;
+use draw::*;
+use std::cmp::min;
+
+/// Shows how to finish logging in: a QR code to scan, or a code to type in at a URL
+pub struct LoginDrawer {
+    pub metrics: Metrics,
+}
+
+impl Drawer for LoginDrawer {
+    type Subject = DeviceAuthorization;
+
+    fn draw(&self, authorization: &DeviceAuthorization, bounds: &DrawingBounds) -> Vec<Drawing> {
+        let padding = self.metrics.padding * 4;
+        let font_size = self.metrics.font_size * 2;
+
+        // The QR code takes up the left half of the screen, and the instructions the right half
+        let qr_size = min(bounds.width / 2, bounds.height).saturating_sub(2 * padding);
+        let qr_bounds = DrawingBounds {
+            left: bounds.left + padding,
+            top: bounds.top + (bounds.height - qr_size) / 2,
+            width: qr_size,
+            height: qr_size,
+        };
+        let qr_url = authorization
+            .verification_url_complete
+            .as_deref()
+            .unwrap_or(&authorization.verification_url);
+        let mut drawings = QrCodeDrawer.draw(qr_url, &qr_bounds);
+
+        let text_left = bounds.left + bounds.width / 2 + padding;
+        let line_height = font_size + self.metrics.padding;
+        let text_top = bounds.top + bounds.height / 2 - 2 * line_height;
+        let lines = [
+            ("Scan to log in, or visit".to_string(), font_size),
+            (authorization.verification_url.clone(), font_size),
+            ("and enter the code".to_string(), font_size),
+            (authorization.user_code.clone(), font_size * 2),
+        ];
+        let mut top = text_top;
+        for (text, size) in lines {
+            drawings.push(label(&text, size, RGB::new(0, 0, 0), text_left, top));
+            top += size + self.metrics.padding;
+        }
+        drawings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: DrawingBounds = DrawingBounds {
+        left: 0,
+        top: 0,
+        width: 720,
+        height: 480,
+    };
+
+    fn authorization() -> DeviceAuthorization {
+        DeviceAuthorization {
+            device_code: "device-code".to_string(),
+            user_code: "ABCD-EFGH".to_string(),
+            verification_url: "https://www.google.com/device".to_string(),
+            verification_url_complete: None,
+            expires_in: 1800,
+            interval: 5,
+        }
+    }
+
+    #[test]
+    fn given_authorization_then_code_is_on_the_left_and_instructions_on_the_right() {
+        let metrics = Metrics::default();
+        let drawings = LoginDrawer {
+            metrics: metrics.clone(),
+        }
+        .draw(&authorization(), &BOUNDS);
+
+        let (code, text): (Vec<&Drawing>, Vec<&Drawing>) = drawings
+            .iter()
+            .partition(|drawing| matches!(drawing.shape, Some(Shape::Rectangle { .. })));
+        assert!(!code.is_empty());
+        for drawing in code {
+            let Some(Shape::Rectangle { width, height }) = drawing.shape else {
+                unreachable!()
+            };
+            assert!(drawing.position.x >= (4 * metrics.padding) as f32);
+            assert!(drawing.position.x + width as f32 <= (BOUNDS.width / 2) as f32);
+            assert!(drawing.position.y + height as f32 <= BOUNDS.height as f32);
+        }
+
+        let lines: Vec<(&str, u32, f32)> = text
+            .iter()
+            .map(|drawing| match &drawing.shape {
+                Some(Shape::Text { text, font_size }) => {
+                    (text.as_str(), *font_size, drawing.position.x)
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+        let text_left = (BOUNDS.width / 2 + 4 * metrics.padding) as f32;
+        assert_eq!(
+            vec![
+                ("Scan to log in, or visit", 2 * metrics.font_size, text_left),
+                (
+                    "https://www.google.com/device",
+                    2 * metrics.font_size,
+                    text_left
+                ),
+                ("and enter the code", 2 * metrics.font_size, text_left),
+                ("ABCD-EFGH", 4 * metrics.font_size, text_left),
+            ],
+            lines
+        );
+        let tops: Vec<f32> = text.iter().map(|drawing| drawing.position.y).collect();
+        assert!(tops.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn given_complete_url_then_code_links_to_it() {
+        let complete = DeviceAuthorization {
+            verification_url_complete: Some(
+                "https://microsoft.com/devicelogin?otc=ABCD-EFGH".to_string(),
+            ),
+            ..authorization()
+        };
+        let drawer = LoginDrawer {
+            metrics: Metrics::default(),
+        };
+        let qr_size = min(BOUNDS.width / 2, BOUNDS.height) - 2 * 4 * drawer.metrics.padding;
+        let qr_bounds = DrawingBounds {
+            left: 4 * drawer.metrics.padding,
+            top: (BOUNDS.height - qr_size) / 2,
+            width: qr_size,
+            height: qr_size,
+        };
+
+        let code = |drawings: Vec<Drawing>| -> Vec<(f32, f32)> {
+            drawings
+                .iter()
+                .filter(|drawing| matches!(drawing.shape, Some(Shape::Rectangle { .. })))
+                .map(|drawing| (drawing.position.x, drawing.position.y))
+                .collect()
+        };
+        let expected = code(QrCodeDrawer.draw(
+            "https://microsoft.com/devicelogin?otc=ABCD-EFGH",
+            &qr_bounds,
+        ));
+        assert_eq!(expected, code(drawer.draw(&complete, &BOUNDS)));
+    }
+}
@@ -1,6 +1,12 @@
 use draw::drawing::Drawing;
+use draw::{Shape, Style, RGB};
 
 pub mod calendar;
+pub mod error;
+pub mod join;
+pub mod login;
+pub mod qr;
+pub mod tasks;
 
 pub trait Drawer {
     type Subject: ?Sized;
@@ -8,6 +14,36 @@ pub trait Drawer {
     fn draw(&self, subject: &Self::Subject, bounds: &DrawingBounds) -> Vec<Drawing>;
 }
 
+/// Font sizes, line widths and paddings, in pixels, for a particular display.
+/// The unscaled values are tuned for a 720x480 frame.
+#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+pub struct Metrics {
+    pub font_size: u32,
+    pub line_width: u32,
+    pub padding: u32,
+}
+
+impl Metrics {
+    const BASE_FONT_SIZE: u32 = 14;
+    const BASE_LINE_WIDTH: u32 = 1;
+    const BASE_PADDING: u32 = 4;
+
+    pub fn scaled(scale: f32) -> Metrics {
+        let scale_px = |px: u32| ((px as f32 * scale).round() as u32).max(1);
+        Metrics {
+            font_size: scale_px(Self::BASE_FONT_SIZE),
+            line_width: scale_px(Self::BASE_LINE_WIDTH),
+            padding: scale_px(Self::BASE_PADDING),
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::scaled(1.0)
+    }
+}
+
 #[derive(Debug, Hash, Clone)]
 pub struct DrawingBounds {
     pub left: u32,
@@ -38,3 +74,14 @@ impl DrawingBounds {
         self.cropped_subshape(relative_offset)
     }
 }
+
+/// Draws a line of text with its top-left corner at the given point
+pub fn label(text: &str, font_size: u32, color: RGB, left: u32, top: u32) -> Drawing {
+    Drawing::new()
+        .with_shape(Shape::Text {
+            text: text.to_string(),
+            font_size,
+        })
+        .with_xy(left as f32, top as f32)
+        .with_style(Style::filled(color))
+}
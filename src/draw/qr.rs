@@ -0,0 +1,129 @@
+use super::*;
+use draw::*;
+use qrcode::{Color as Module, QrCode};
+use std::cmp::min;
+
+/// QR readers need a blank border this many modules wide around the code
+const QUIET_ZONE_MODULES: u32 = 4;
+
+/// Draws a QR code of some text, as big as will fit in the bounds, in the top-left corner
+pub struct QrCodeDrawer;
+
+impl Drawer for QrCodeDrawer {
+    type Subject = str;
+
+    fn draw(&self, data: &str, bounds: &DrawingBounds) -> Vec<Drawing> {
+        let code = match QrCode::new(data.as_bytes()) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("could not make a QR code of {:?}: {:?}", data, e);
+                return vec![];
+            }
+        };
+
+        let modules = code.width() as u32;
+        let size_in_modules = modules + 2 * QUIET_ZONE_MODULES;
+        let module_size = min(bounds.width, bounds.height) / size_in_modules;
+        if module_size == 0 {
+            return vec![];
+        }
+
+        let mut drawings = vec![Drawing::new()
+            .with_shape(Shape::Rectangle {
+                width: size_in_modules * module_size,
+                height: size_in_modules * module_size,
+            })
+            .with_xy(bounds.left as f32, bounds.top as f32)
+            .with_style(Style::filled(RGB::new(255, 255, 255)))];
+
+        let origin_x = bounds.left + QUIET_ZONE_MODULES * module_size;
+        let origin_y = bounds.top + QUIET_ZONE_MODULES * module_size;
+        let colors = code.to_colors();
+        for (y, row) in colors.chunks(modules as usize).enumerate() {
+            // One rectangle per run of dark modules keeps the display list small
+            let mut x = 0;
+            while x < row.len() {
+                if row[x] != Module::Dark {
+                    x += 1;
+                    continue;
+                }
+                let run_start = x;
+                while x < row.len() && row[x] == Module::Dark {
+                    x += 1;
+                }
+                drawings.push(
+                    Drawing::new()
+                        .with_shape(Shape::Rectangle {
+                            width: (x - run_start) as u32 * module_size,
+                            height: module_size,
+                        })
+                        .with_xy(
+                            (origin_x + run_start as u32 * module_size) as f32,
+                            (origin_y + y as u32 * module_size) as f32,
+                        )
+                        .with_style(Style::filled(RGB::new(0, 0, 0))),
+                );
+            }
+        }
+        drawings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://www.google.com/device";
+
+    fn rectangle(drawing: &Drawing) -> (f32, f32, u32, u32) {
+        match drawing.shape {
+            Some(Shape::Rectangle { width, height }) => {
+                (drawing.position.x, drawing.position.y, width, height)
+            }
+            _ => panic!("expected only rectangles in a QR code"),
+        }
+    }
+
+    #[test]
+    fn given_bounds_then_code_fills_the_shorter_side_from_the_top_left() {
+        let bounds = DrawingBounds {
+            left: 40,
+            top: 20,
+            width: 400,
+            height: 300,
+        };
+        let modules = QrCode::new(URL.as_bytes()).unwrap().width() as u32;
+        let module_size = 300 / (modules + 2 * QUIET_ZONE_MODULES);
+
+        let drawings = QrCodeDrawer.draw(URL, &bounds);
+
+        let side = (modules + 2 * QUIET_ZONE_MODULES) * module_size;
+        assert_eq!((40.0, 20.0, side, side), rectangle(&drawings[0]));
+        assert!(drawings.len() > 1);
+        let quiet_zone = QUIET_ZONE_MODULES * module_size;
+        for drawing in &drawings[1..] {
+            let (x, y, width, height) = rectangle(drawing);
+            assert_eq!(module_size, height);
+            assert_eq!(0, width % module_size);
+            assert!(
+                x >= (40 + quiet_zone) as f32
+                    && x + width as f32 <= (40 + side - quiet_zone) as f32
+            );
+            assert!(
+                y >= (20 + quiet_zone) as f32
+                    && y + height as f32 <= (20 + side - quiet_zone) as f32
+            );
+        }
+    }
+
+    #[test]
+    fn given_bounds_too_small_for_one_pixel_modules_then_nothing_is_drawn() {
+        let bounds = DrawingBounds {
+            left: 0,
+            top: 0,
+            width: 400,
+            height: 20,
+        };
+        assert!(QrCodeDrawer.draw(URL, &bounds).is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use super::*;
+use crate::model::*;
+use chrono::prelude::*;
+use draw::*;
+
+/// Today's and overdue tasks as a list, most important first, with as many as fit
+pub struct TaskDrawer {
+    pub metrics: Metrics,
+    pub today: NaiveDate,
+}
+
+impl TaskDrawer {
+    fn row_height(&self) -> u32 {
+        self.metrics.font_size + self.metrics.padding
+    }
+
+    /// A bar down the left of the row, red for the most important and fading to grey
+    fn priority_marker(&self, priority: u8, left: u32, top: u32) -> Drawing {
+        let color = match priority {
+            1..=3 => RGB::new(213, 0, 0),
+            4..=6 => RGB::new(244, 148, 30),
+            _ => RGB::new(160, 160, 160),
+        };
+        Drawing::new()
+            .with_shape(Shape::Rectangle {
+                width: self.metrics.padding,
+                height: self.metrics.font_size,
+            })
+            .with_xy(left as f32, top as f32)
+            .with_style(Style::filled(color))
+    }
+
+    fn text(&self, task: &Task) -> String {
+        match task.due {
+            Some(due) if task.is_overdue(self.today) => {
+                format!("{} (due {})", task.summary, due.format("%b %-d"))
+            }
+            _ => task.summary.clone(),
+        }
+    }
+}
+
+impl Drawer for TaskDrawer {
+    type Subject = [Task];
+
+    fn draw(&self, tasks: &[Task], bounds: &DrawingBounds) -> Vec<Drawing> {
+        if tasks.is_empty() {
+            return vec![];
+        }
+        let padding = self.metrics.padding;
+        let font_size = self.metrics.font_size;
+        let left = bounds.left + padding;
+        let text_left = left + 2 * padding;
+        let mut top = bounds.top + padding;
+
+        let mut drawings = vec![label("Tasks", font_size, RGB::new(0, 0, 0), left, top)];
+        top += self.row_height();
+
+        // Keep the last row for saying how many didn't fit
+        let rows = (bounds.bottom().saturating_sub(top + padding) / self.row_height()) as usize;
+        let shown = match tasks.len() <= rows {
+            true => tasks.len(),
+            false => rows.saturating_sub(1),
+        };
+        for task in &tasks[..shown] {
+            if let Some(priority) = task.priority {
+                drawings.push(self.priority_marker(priority, left, top));
+            }
+            let color = match task.is_overdue(self.today) {
+                true => RGB::new(200, 40, 40),
+                false => RGB::new(60, 60, 60),
+            };
+            drawings.push(label(&self.text(task), font_size, color, text_left, top));
+            top += self.row_height();
+        }
+        if shown < tasks.len() {
+            let more = format!("+{} more", tasks.len() - shown);
+            drawings.push(label(
+                &more,
+                font_size,
+                RGB::new(120, 120, 120),
+                text_left,
+                top,
+            ));
+        }
+        drawings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(drawings: &[Drawing]) -> Vec<&str> {
+        drawings
+            .iter()
+            .filter_map(|drawing| match &drawing.shape {
+                Some(Shape::Text { text, .. }) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn drawer() -> TaskDrawer {
+        TaskDrawer {
+            metrics: Metrics::default(),
+            today: NaiveDate::from_ymd_opt(2023, 9, 5).unwrap(),
+        }
+    }
+
+    fn task(summary: &str, priority: Option<u8>, due: u32) -> Task {
+        Task {
+            summary: summary.to_string(),
+            priority,
+            due: NaiveDate::from_ymd_opt(2023, 9, due),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lists_tasks_with_overdue_ones_dated_and_marks_priorities() {
+        let tasks = vec![
+            task("Send the invoice", Some(1), 5),
+            task("Water the plants", None, 4),
+        ];
+        let bounds = DrawingBounds {
+            left: 540,
+            top: 0,
+            width: 180,
+            height: 480,
+        };
+        let drawings = drawer().draw(&tasks, &bounds);
+
+        assert_eq!(
+            vec!["Tasks", "Send the invoice", "Water the plants (due Sep 4)"],
+            texts(&drawings)
+        );
+        let markers = drawings
+            .iter()
+            .filter(|drawing| matches!(drawing.shape, Some(Shape::Rectangle { .. })));
+        assert_eq!(1, markers.count());
+        assert!(drawings
+            .iter()
+            .all(|drawing| drawing.position.x >= bounds.left as f32));
+    }
+
+    #[test]
+    fn says_how_many_tasks_did_not_fit() {
+        let tasks: Vec<Task> = (1..=10)
+            .map(|n| task(&format!("Task {}", n), None, 5))
+            .collect();
+        let metrics = Metrics::default();
+        // The heading and three rows
+        let height = 4 * (metrics.font_size + metrics.padding) + 2 * metrics.padding;
+        let bounds = DrawingBounds {
+            left: 0,
+            top: 0,
+            width: 180,
+            height,
+        };
+
+        assert_eq!(
+            vec!["Tasks", "Task 1", "Task 2", "+8 more"],
+            texts(&drawer().draw(&tasks, &bounds))
+        );
+        assert!(drawer().draw(&[], &bounds).is_empty());
+    }
+}
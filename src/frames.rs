@@ -0,0 +1,124 @@
+use crate::calendar::oauth::DeviceAuthorization;
+use crate::colors::EventColors;
+use crate::display::DisplayProfile;
+use crate::draw::{
+    calendar::*, error::*, join::*, login::*, tasks::*, Drawer, DrawingBounds, Metrics,
+};
+use crate::model::*;
+use chrono::prelude::*;
+use draw::{render::bitmap::PngRenderer, render::save, *};
+use tempdir::TempDir;
+
+/// The schedule for a range of days, as it should appear on the given display.
+/// A panel on the right shows a way to join the current or next video call above the tasks that are due, if there are either.
+pub fn schedule_frame(
+    model: &Model,
+    range: DefiniteTimeRange<Local>,
+    profile: &DisplayProfile,
+    colors: &EventColors,
+) -> Canvas {
+    let metrics = profile.metrics();
+    let drawer = CalendarDrawer::new(range)
+        .with_metrics(metrics.clone())
+        .with_colors(colors.clone())
+        .with_annotations(model.annotations.clone())
+        .with_stale_since(model.stale_since)
+        .with_failed_calendars(model.failed_calendars.clone());
+    let mut canvas = blank_canvas(profile, &metrics);
+    let mut bounds = profile.bounds();
+    let now = Local::now();
+    let meeting = model.meeting_to_join(now);
+    if meeting.is_some() || !model.tasks.is_empty() {
+        let panel_width = bounds.width / 4;
+        bounds.width -= panel_width;
+        let mut panel = DrawingBounds {
+            left: bounds.right(),
+            width: panel_width,
+            ..bounds.clone()
+        };
+        if let Some(meeting) = meeting {
+            let join_height = match model.tasks.is_empty() {
+                true => panel.height,
+                false => panel.height / 2,
+            };
+            let join_bounds = DrawingBounds {
+                height: join_height,
+                ..panel.clone()
+            };
+            let join_drawer = JoinDrawer {
+                metrics: metrics.clone(),
+            };
+            canvas
+                .display_list
+                .drawings
+                .append(&mut join_drawer.draw(meeting, &join_bounds));
+            panel.top += join_height;
+            panel.height -= join_height;
+        }
+        let task_drawer = TaskDrawer {
+            metrics: metrics.clone(),
+            today: now.date_naive(),
+        };
+        canvas
+            .display_list
+            .drawings
+            .append(&mut task_drawer.draw(&model.tasks, &panel));
+    }
+    let mut drawings = drawer.draw(&model.events, &bounds);
+    canvas.display_list.drawings.append(&mut drawings);
+    canvas
+}
+
+/// Instructions for logging in, shown until the user has finished logging in on their phone
+pub fn login_frame(authorization: &DeviceAuthorization, profile: &DisplayProfile) -> Canvas {
+    let metrics = profile.metrics();
+    let drawer = LoginDrawer {
+        metrics: metrics.clone(),
+    };
+    let mut canvas = blank_canvas(profile, &metrics);
+    let mut drawings = drawer.draw(authorization, &profile.bounds());
+    canvas.display_list.drawings.append(&mut drawings);
+    canvas
+}
+
+/// What went wrong, shown when there's no schedule to show instead
+pub fn error_frame(message: &str, profile: &DisplayProfile) -> Canvas {
+    let metrics = profile.metrics();
+    let drawer = ErrorDrawer {
+        metrics: metrics.clone(),
+    };
+    let mut canvas = blank_canvas(profile, &metrics);
+    let mut drawings = drawer.draw(message, &profile.bounds());
+    canvas.display_list.drawings.append(&mut drawings);
+    canvas
+}
+
+fn blank_canvas(profile: &DisplayProfile, metrics: &Metrics) -> Canvas {
+    let mut canvas = Canvas::new(profile.width, profile.height);
+    canvas.display_list.add(
+        Drawing::new()
+            .with_shape(Shape::Rectangle {
+                width: profile.width,
+                height: profile.height,
+            })
+            .with_style(Style {
+                fill: Some(Fill {
+                    color: RGB::new(255, 255, 255),
+                }),
+                stroke: Some(Stroke {
+                    width: 5 * metrics.line_width,
+                    color: RGB::new(0, 0, 0),
+                }),
+            }),
+    );
+    canvas
+}
+
+/// Renders a frame to PNG bytes, ready to be served to a cast device
+pub fn to_png(canvas: &Canvas) -> std::io::Result<Vec<u8>> {
+    // The renderer only knows how to write to files
+    let tempdir = TempDir::new("cast-frame")?;
+    let path = tempdir.path().join("frame.png");
+    save(canvas, path.to_str().unwrap(), PngRenderer::new())?;
+    std::fs::read(&path)
+}
@@ -2,6 +2,15 @@
 #![feature(const_option)]
 
 pub mod calendar;
+pub mod cast;
+pub mod colors;
+pub mod config;
+pub mod display;
 pub mod draw;
+pub mod frames;
 pub mod model;
+pub mod redaction;
 pub mod scan;
+
+#[cfg(test)]
+mod testing;
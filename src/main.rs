@@ -1,17 +1,676 @@
+use async_std::task::sleep;
+use cast_schedule::calendar::annotations::AnnotatingCalendar;
+use cast_schedule::calendar::caching::CachingCalendar;
+use cast_schedule::calendar::caldav::CalDavCalendar;
+use cast_schedule::calendar::filter::*;
+use cast_schedule::calendar::google::*;
+use cast_schedule::calendar::holidays::HolidayCalendar;
+use cast_schedule::calendar::ics::IcsCalendar;
+use cast_schedule::calendar::keyring::SecretServiceTokenStorage;
+use cast_schedule::calendar::merged::*;
+use cast_schedule::calendar::microsoft::*;
+use cast_schedule::calendar::oauth::DeviceLoginProvider;
+use cast_schedule::calendar::tasks::*;
+use cast_schedule::calendar::tokens::*;
+use cast_schedule::calendar::*;
+use cast_schedule::cast::*;
+use cast_schedule::colors::EventColors;
+use cast_schedule::config::*;
+use cast_schedule::display::*;
+use cast_schedule::frames::*;
+use cast_schedule::model::*;
+use cast_schedule::redaction::RedactionPolicy;
 use cast_schedule::scan::*;
+use chrono::prelude::*;
+use chrono::Duration;
+
+const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// How long to wait after the first failure that might go away on its own; it doubles with each failure after that
+const FIRST_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+const MAX_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+const ICS_CACHE_NAME: &str = "ics";
+const CALDAV_CACHE_NAME: &str = "caldav";
 
 #[async_std::main]
 async fn main() {
-    let _devices = match scan_once_for_devices().await {
-        Ok(devices) => { report_devices(&devices); devices }
-        Err(e) => { eprintln!("error polling: {:?}", e); Vec::new() },
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("could not read config: {:?}", e);
+            return;
+        }
+    };
+
+    let devices = match scan_once_for_devices().await {
+        Ok(devices) => {
+            report_devices(&devices);
+            devices
+        }
+        Err(e) => {
+            eprintln!("error polling: {:?}", e);
+            Vec::new()
+        }
+    };
+
+    let Some(device) = pick_device(&devices, config.device.as_deref()) else {
+        eprintln!("no cast device with a screen found");
+        return;
+    };
+    println!("Showing schedule on {}", device.name);
+
+    let filter = match config.filter.filter() {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("invalid filter: {:?}", e);
+            return;
+        }
+    };
+
+    let annotations = match config.annotations.patterns() {
+        Ok(annotations) => annotations,
+        Err(e) => {
+            eprintln!("invalid annotation calendars: {:?}", e);
+            return;
+        }
+    };
+
+    let holidays = match config.annotations.holidays() {
+        Ok(holidays) => holidays,
+        Err(e) => {
+            eprintln!("no holidays for country: {:?}", e);
+            return;
+        }
+    };
+
+    let redaction = match config.redaction.policy_for(&device.name) {
+        Ok(redaction) => redaction,
+        Err(e) => {
+            eprintln!("invalid redaction patterns: {:?}", e);
+            return;
+        }
+    };
+
+    let colors = match config.colors.colors() {
+        Ok(colors) => colors,
+        Err(e) => {
+            eprintln!("invalid colors: {:?}", e);
+            return;
+        }
+    };
+
+    let tasks = match config.tasks.lists(filter.clone()) {
+        Ok(tasks) => tasks,
+        Err(e) => {
+            eprintln!("invalid task list: {:?}", e);
+            return;
+        }
+    };
+
+    let screen = match Screen::connect(device, redaction, colors, tasks) {
+        Ok(screen) => screen,
+        Err(e) => {
+            eprintln!("could not serve frames: {:?}", e);
+            return;
+        }
+    };
+
+    let configured = [
+        config.google.is_some(),
+        config.microsoft.is_some(),
+        config.ics.is_some(),
+        config.caldav.is_some(),
+    ];
+    // Holidays are only shown merged into a schedule, even one with nothing else in it
+    if configured.iter().filter(|&&c| c).count() + config.command.len() > 1 || !holidays.is_empty()
+    {
+        show_merged_calendar(&config, holidays, &filter, &annotations, &screen).await;
+    } else if let Some(google) = &config.google {
+        let provider = google_provider(google, config.token_storage);
+        let configure = |calendar| configure_google_calendar(calendar, google);
+        show_device_login_calendar(&provider, configure, &filter, &annotations, &screen).await;
+    } else if let Some(microsoft) = &config.microsoft {
+        let provider = microsoft_provider(microsoft, config.token_storage);
+        let configure = |calendar| configure_microsoft_calendar(calendar, microsoft);
+        show_device_login_calendar(&provider, configure, &filter, &annotations, &screen).await;
+    } else if let Some(ics) = &config.ics {
+        show_ics_calendar(ics, &filter, &annotations, &screen).await;
+    } else if let Some(caldav) = &config.caldav {
+        show_caldav_calendar(caldav, &filter, &annotations, &screen).await;
+    } else if let Some(command) = config.command.first() {
+        keep_showing(
+            &shown(
+                cached(command.calendar(), &command.name),
+                &filter,
+                &annotations,
+            ),
+            &screen,
+        )
+        .await;
+    } else {
+        eprintln!("no calendar configured");
+    }
+}
+
+/// Shows a calendar that has to be logged in to, logging in again whenever the login stops working
+async fn show_device_login_calendar<P: DeviceLoginProvider>(
+    provider: &P,
+    configure: impl Fn(P::Calendar) -> Result<P::Calendar, CalendarError>,
+    filter: &EventFilter,
+    annotations: &[EventPattern],
+    screen: &Screen,
+) {
+    let Some(calendar) = logged_in_calendar(provider, &configure, screen).await else {
+        return;
+    };
+    let mut calendar = shown(cached(calendar, provider.name()), filter, annotations);
+    let mut failures = 0;
+
+    loop {
+        match refresh(&calendar, screen, &mut failures).await {
+            Ok(wait) => sleep(wait).await,
+            Err(e) => {
+                eprintln!("logged out of {}; logging in again: {}", provider.name(), e);
+                let Some(logged_in) = logged_in_again(provider, &configure, screen).await else {
+                    return;
+                };
+                calendar = shown(cached(logged_in, provider.name()), filter, annotations);
+            }
+        }
+    }
+}
+
+/// Resumes the last session, or else logs in with a QR code
+async fn logged_in_calendar<P: DeviceLoginProvider>(
+    provider: &P,
+    configure: impl Fn(P::Calendar) -> Result<P::Calendar, CalendarError>,
+    screen: &Screen,
+) -> Option<P::Calendar> {
+    let logged_in = match provider.resume_session().await {
+        Some(calendar) => Ok(calendar),
+        None => log_in_with_qr_code(provider, screen).await,
     };
+    match logged_in.and_then(configure) {
+        Ok(calendar) => Some(calendar),
+        Err(e) => {
+            eprintln!("could not log in to {}: {}", provider.name(), e);
+            None
+        }
+    }
+}
+
+/// Logs in with a QR code after the last login stopped working
+async fn logged_in_again<P: DeviceLoginProvider>(
+    provider: &P,
+    configure: impl Fn(P::Calendar) -> Result<P::Calendar, CalendarError>,
+    screen: &Screen,
+) -> Option<P::Calendar> {
+    match log_in_with_qr_code(provider, screen)
+        .await
+        .and_then(configure)
+    {
+        Ok(calendar) => Some(calendar),
+        Err(e) => {
+            eprintln!("could not log in to {}: {}", provider.name(), e);
+            None
+        }
+    }
+}
+
+async fn show_ics_calendar(
+    ics: &IcsConfig,
+    filter: &EventFilter,
+    annotations: &[EventPattern],
+    screen: &Screen,
+) {
+    if let Some(calendar) = ics_calendar(ics) {
+        keep_showing(
+            &shown(cached(calendar, ICS_CACHE_NAME), filter, annotations),
+            screen,
+        )
+        .await;
+    }
+}
+
+async fn show_caldav_calendar(
+    caldav: &CalDavConfig,
+    filter: &EventFilter,
+    annotations: &[EventPattern],
+    screen: &Screen,
+) {
+    if let Some(calendar) = caldav_calendar(caldav) {
+        keep_showing(
+            &shown(cached(calendar, CALDAV_CACHE_NAME), filter, annotations),
+            screen,
+        )
+        .await;
+    }
+}
+
+/// Shows every configured calendar together, logging in to each one that needs it first.
+/// A calendar that gets logged out is logged in to again with a QR code, and left out if that fails.
+async fn show_merged_calendar(
+    config: &Config,
+    holidays: Vec<HolidayCalendar>,
+    filter: &EventFilter,
+    annotations: &[EventPattern],
+    screen: &Screen,
+) {
+    let mut merged = MergedCalendar::new();
+    if let Some(google) = &config.google {
+        let provider = google_provider(google, config.token_storage);
+        if let Some(calendar) = logged_in_calendar(
+            &provider,
+            |calendar| configure_google_calendar(calendar, google),
+            screen,
+        )
+        .await
+        {
+            merged = merged.with_calendar(
+                provider.name(),
+                MergeableCalendar::Cached(cached(
+                    AnyCalendar::Google(Box::new(calendar)),
+                    provider.name(),
+                )),
+            );
+        }
+    }
+    if let Some(microsoft) = &config.microsoft {
+        let provider = microsoft_provider(microsoft, config.token_storage);
+        if let Some(calendar) = logged_in_calendar(
+            &provider,
+            |calendar| configure_microsoft_calendar(calendar, microsoft),
+            screen,
+        )
+        .await
+        {
+            merged = merged.with_calendar(
+                provider.name(),
+                MergeableCalendar::Cached(cached(
+                    AnyCalendar::Microsoft(Box::new(calendar)),
+                    provider.name(),
+                )),
+            );
+        }
+    }
+    if let Some(calendar) = config.ics.as_ref().and_then(ics_calendar) {
+        merged = merged.with_calendar(
+            "iCalendar files",
+            MergeableCalendar::Cached(cached(AnyCalendar::Ics(calendar), ICS_CACHE_NAME)),
+        );
+    }
+    if let Some(calendar) = config.caldav.as_ref().and_then(caldav_calendar) {
+        merged = merged.with_calendar(
+            "CalDAV",
+            MergeableCalendar::Cached(cached(AnyCalendar::CalDav(calendar), CALDAV_CACHE_NAME)),
+        );
+    }
+    for command in &config.command {
+        merged = merged.with_calendar(
+            &command.name,
+            MergeableCalendar::Cached(cached(
+                AnyCalendar::Command(command.calendar()),
+                &command.name,
+            )),
+        );
+    }
+    for calendar in holidays {
+        merged = merged.with_calendar(&calendar.name(), MergeableCalendar::Holidays(calendar));
+    }
+
+    let mut failures = 0;
+    loop {
+        if merged.is_empty() {
+            eprintln!("none of the configured calendars could be used");
+            return;
+        }
+        // Only fails when every calendar was logged out, and those are logged in to below
+        let wait = refresh(&shown(&merged, filter, annotations), screen, &mut failures)
+            .await
+            .unwrap_or(REFRESH_INTERVAL);
+        let logged_out = merged.take_logged_out().await;
+        if logged_out.is_empty() {
+            sleep(wait).await;
+            continue;
+        }
+        for name in logged_out {
+            eprintln!("logged out of {}; logging in again", name);
+            match log_in_again(&name, config, screen).await {
+                Some(calendar) => merged
+                    .replace_calendar(&name, MergeableCalendar::Cached(cached(calendar, &name))),
+                None => merged.remove_calendar(&name),
+            }
+        }
+    }
+}
+
+/// Logs in again to the merged calendar's calendar called `name`
+async fn log_in_again(name: &str, config: &Config, screen: &Screen) -> Option<AnyCalendar> {
+    if let Some(google) = &config.google {
+        let provider = google_provider(google, config.token_storage);
+        if provider.name() == name {
+            let calendar = logged_in_again(
+                &provider,
+                |calendar| configure_google_calendar(calendar, google),
+                screen,
+            )
+            .await?;
+            return Some(AnyCalendar::Google(Box::new(calendar)));
+        }
+    }
+    if let Some(microsoft) = &config.microsoft {
+        let provider = microsoft_provider(microsoft, config.token_storage);
+        if provider.name() == name {
+            let calendar = logged_in_again(
+                &provider,
+                |calendar| configure_microsoft_calendar(calendar, microsoft),
+                screen,
+            )
+            .await?;
+            return Some(AnyCalendar::Microsoft(Box::new(calendar)));
+        }
+    }
+    None
+}
+
+fn ics_calendar(ics: &IcsConfig) -> Option<IcsCalendar> {
+    match ics.sources() {
+        Ok(sources) => Some(IcsCalendar::new(sources)),
+        Err(e) => {
+            eprintln!("invalid calendar: {:?}", e);
+            None
+        }
+    }
+}
+
+fn caldav_calendar(caldav: &CalDavConfig) -> Option<CalDavCalendar> {
+    match (caldav.base_url(), caldav.auth(), caldav.selectors()) {
+        (Ok(url), Ok(auth), Ok(selectors)) => {
+            Some(CalDavCalendar::new(url, auth).with_calendars(selectors))
+        }
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            eprintln!("invalid caldav config: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Marks the events of the calendars that are annotations, then leaves out the events the filter hides
+fn shown<C: Calendar>(
+    calendar: C,
+    filter: &EventFilter,
+    annotations: &[EventPattern],
+) -> FilteredCalendar<AnnotatingCalendar<C>> {
+    FilteredCalendar::new(
+        AnnotatingCalendar::new(calendar).with_patterns(annotations.to_vec()),
+        filter.clone(),
+    )
+}
+
+/// Keeps the last events fetched in the cache directory, to show if the network goes down
+fn cached<C: Calendar>(calendar: C, name: &str) -> CachingCalendar<C> {
+    let calendar = CachingCalendar::new(calendar);
+    match cache_dir() {
+        Some(dir) => calendar
+            .with_cache_file(dir.join(format!("{}.json", name.to_lowercase().replace(' ', "-")))),
+        None => calendar,
+    }
+}
+
+/// Refreshes the schedule forever, for calendars that don't need logging in again
+async fn keep_showing<C: Calendar>(calendar: &C, screen: &Screen) {
+    let mut failures = 0;
+    loop {
+        let wait = match refresh(calendar, screen, &mut failures).await {
+            Ok(wait) => wait,
+            Err(e) => {
+                show_error(&e, screen);
+                REFRESH_INTERVAL
+            }
+        };
+        sleep(wait).await;
+    }
+}
+
+/// Shows the schedule, and says how long to wait before showing it again.
+/// Failures that might go away on their own leave the last schedule up and back off;
+/// anything else is shown on the screen. Only fails if the calendar has to be logged in to again.
+async fn refresh<C: Calendar>(
+    calendar: &C,
+    screen: &Screen,
+    failures: &mut u32,
+) -> Result<std::time::Duration, CalendarError> {
+    let e = match show_schedule(calendar, screen).await {
+        Ok(()) => {
+            *failures = 0;
+            return Ok(REFRESH_INTERVAL);
+        }
+        Err(e) if e.kind() == ErrorKind::LoggedOut => return Err(e),
+        Err(e) => e,
+    };
+
+    if !e.is_retryable() {
+        *failures = 0;
+        show_error(&e, screen);
+        return Ok(REFRESH_INTERVAL);
+    }
+    let backoff = FIRST_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(*failures))
+        .min(MAX_RETRY_DELAY);
+    *failures += 1;
+    let wait = e.retry_after().unwrap_or(backoff);
+    eprintln!(
+        "could not fetch events, trying again in {}s: {}",
+        wait.as_secs(),
+        e
+    );
+    Ok(wait)
+}
+
+fn show_error(e: &CalendarError, screen: &Screen) {
+    eprintln!("could not fetch events: {}", e);
+    screen.show(error_frame(&e.to_string(), &screen.profile));
 }
 
 fn report_devices(devices: &[FoundDevice]) {
     println!("Found devices:");
     let width = devices.iter().map(|d| d.name.len()).max().unwrap_or(0);
     for device in devices {
-        println!("\t{:width$}\t{}", device.name, device.addr, width = width)
+        let display = match DisplayProfile::for_device(device) {
+            Some(profile) => format!("{}x{}", profile.width, profile.height),
+            None => "no display".to_string(),
+        };
+        let model = device.model.as_deref().unwrap_or("unknown model");
+        println!(
+            "\t{:width$}\t{}\t{} ({})",
+            device.name,
+            device.addr,
+            model,
+            display,
+            width = width
+        )
+    }
+}
+
+/// The device named in the config, or else the first one that can show anything
+fn pick_device<'a>(devices: &'a [FoundDevice], name: Option<&str>) -> Option<&'a FoundDevice> {
+    match name {
+        Some(name) => devices.iter().find(|d| d.name == name),
+        None => devices
+            .iter()
+            .find(|d| DisplayProfile::for_device(d).is_some()),
+    }
+}
+
+/// A cast device, and the server it loads our frames from
+struct Screen {
+    profile: DisplayProfile,
+    /// What people who can see this screen shouldn't see
+    redaction: RedactionPolicy,
+    colors: EventColors,
+    /// What to list next to the schedule
+    tasks: TaskLists<AnyTaskList>,
+    server: FrameServer,
+    cast: CastScreen,
+}
+
+impl Screen {
+    fn connect(
+        device: &FoundDevice,
+        redaction: RedactionPolicy,
+        colors: EventColors,
+        tasks: TaskLists<AnyTaskList>,
+    ) -> std::io::Result<Screen> {
+        Ok(Screen {
+            profile: DisplayProfile::for_device(device).unwrap_or_default(),
+            redaction,
+            colors,
+            tasks,
+            server: FrameServer::start()?,
+            cast: CastScreen::connect(device),
+        })
+    }
+
+    fn show(&self, canvas: draw::Canvas) {
+        match to_png(&canvas) {
+            Ok(png) => self.cast.show(self.server.publish(png)),
+            Err(e) => eprintln!("could not render frame: {:?}", e),
+        }
     }
 }
+
+fn google_provider(config: &GoogleConfig, storage: TokenStorageKind) -> GoogleCalendarProvider {
+    let provider =
+        GoogleCalendarProvider::new(config.client_id.clone(), config.client_secret.clone());
+    match token_store(storage, GOOGLE_TOKEN_FILE, GOOGLE_KEYRING_ACCOUNT) {
+        Some(store) => provider.with_token_store(store),
+        None => provider,
+    }
+}
+
+fn microsoft_provider(
+    config: &MicrosoftConfig,
+    storage: TokenStorageKind,
+) -> MicrosoftCalendarProvider {
+    let mut provider = MicrosoftCalendarProvider::new(config.client_id.clone());
+    if let Some(tenant) = &config.tenant {
+        provider = provider.with_tenant(tenant);
+    }
+    match token_store(storage, MICROSOFT_TOKEN_FILE, MICROSOFT_KEYRING_ACCOUNT) {
+        Some(store) => provider.with_token_store(store),
+        None => provider,
+    }
+}
+
+fn token_store(
+    storage: TokenStorageKind,
+    file_name: &str,
+    keyring_account: &str,
+) -> Option<TokenStore> {
+    match storage {
+        TokenStorageKind::File => FileTokenStorage::in_config_dir(file_name).map(TokenStore::File),
+        TokenStorageKind::SecretService => Some(TokenStore::SecretService(
+            SecretServiceTokenStorage::new(keyring_account),
+        )),
+    }
+}
+
+/// Puts a QR code on the screen to log in with, then waits for the user to scan it and log in
+async fn log_in_with_qr_code<P: DeviceLoginProvider>(
+    provider: &P,
+    screen: &Screen,
+) -> Result<P::Calendar, CalendarError> {
+    let authorization = provider.start_login().await?;
+    println!(
+        "To show your {}, scan the code on the TV, or visit {} and enter the code {}",
+        provider.name(),
+        authorization.verification_url,
+        authorization.user_code
+    );
+    screen.show(login_frame(&authorization, &screen.profile));
+    provider.finish_login(&authorization).await
+}
+
+fn configure_google_calendar(
+    calendar: GoogleCalendar,
+    config: &GoogleConfig,
+) -> Result<GoogleCalendar, CalendarError> {
+    let selectors = config.selectors().map_err(|e| {
+        CalendarError::login("invalid google calendar selection").with_cause(format!("{:?}", e))
+    })?;
+    let mut calendar = calendar.with_calendars(selectors);
+    if let Some(max_events) = config.max_events {
+        calendar = calendar.with_max_events(max_events);
+    }
+    Ok(calendar)
+}
+
+fn configure_microsoft_calendar(
+    calendar: MicrosoftCalendar,
+    config: &MicrosoftConfig,
+) -> Result<MicrosoftCalendar, CalendarError> {
+    let selectors = config.selectors().map_err(|e| {
+        CalendarError::login("invalid outlook calendar selection").with_cause(format!("{:?}", e))
+    })?;
+    let mut calendar = calendar.with_calendars(selectors);
+    if let Some(max_events) = config.max_events {
+        calendar = calendar.with_max_events(max_events);
+    }
+    Ok(calendar)
+}
+
+/// Today's and tomorrow's working hours
+fn schedule_range() -> DefiniteTimeRange<Local> {
+    let midnight_today = Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(Local)
+        .unwrap();
+    DefiniteTimeRange {
+        start: midnight_today + Duration::hours(8),
+        end: midnight_today + Duration::hours(18) + Duration::days(1),
+    }
+}
+
+async fn show_schedule<C: Calendar>(calendar: &C, screen: &Screen) -> Result<(), CalendarError> {
+    let range = schedule_range();
+    let cached = calendar.get_cached_events_on(range.clone()).await?;
+
+    let now = Local::now();
+    let tasks = match screen.tasks.get_tasks_due(now.date_naive()).await {
+        Ok(tasks) => tasks,
+        Err(e) => {
+            eprintln!("could not read tasks: {}", e);
+            Vec::new()
+        }
+    };
+    // Annotations are noted on their days rather than drawn as events
+    let dates = DateRange {
+        start: range.start.date_naive(),
+        end: range.end.date_naive() + Duration::days(1),
+    };
+    let (events, annotations) = screen.redaction.apply_to_schedule(cached.events, &dates);
+    let model = Model {
+        status: CurrentStatus {
+            has_meeting: events.iter().any(|e| {
+                !e.is_all_day()
+                    && e.times.start.is_some_and(|start| start <= now)
+                    && e.times.end.is_some_and(|end| now < end)
+            }),
+            mic_active: false,
+            in_meeting: false,
+        },
+        events,
+        tasks: screen.redaction.apply_to_tasks(tasks),
+        annotations,
+        stale_since: cached.stale_since,
+        failed_calendars: cached.failed_calendars,
+    };
+    screen.show(schedule_frame(
+        &model,
+        range,
+        &screen.profile,
+        &screen.colors,
+    ));
+    Ok(())
+}
@@ -1,9 +1,14 @@
 use chrono::offset::*;
 use chrono::prelude::*;
 use chrono::Duration;
+use serde::{Deserialize, Serialize};
 use std::cmp::{max, min};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "DateTime<TZ>: Serialize",
+    deserialize = "DateTime<TZ>: Deserialize<'de>"
+))]
 pub struct DefiniteTimeRange<TZ: TimeZone> {
     pub start: DateTime<TZ>,
     pub end: DateTime<TZ>,
@@ -32,7 +37,11 @@ where
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "DateTime<TZ>: Serialize",
+    deserialize = "DateTime<TZ>: Deserialize<'de>"
+))]
 pub struct IndefiniteTimeRange<TZ: TimeZone> {
     pub start: Option<DateTime<TZ>>,
     pub end: Option<DateTime<TZ>>,
@@ -63,10 +72,536 @@ where
     }
 }
 
-#[derive(Debug)]
+impl<TZ: TimeZone> Default for IndefiniteTimeRange<TZ> {
+    fn default() -> Self {
+        IndefiniteTimeRange {
+            start: None,
+            end: None,
+        }
+    }
+}
+
+/// A span of whole days, like an all-day event.
+/// As with iCalendar and Google, the `end` date is exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    pub fn single_day(date: NaiveDate) -> DateRange {
+        DateRange {
+            start: date,
+            end: date + Duration::days(1),
+        }
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date < self.end
+    }
+
+    pub fn overlaps(&self, other: &DateRange) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// From local midnight on the first day to local midnight after the last
+    pub fn in_local_time(&self) -> Option<DefiniteTimeRange<Local>> {
+        let midnight = |date: NaiveDate| {
+            date.and_time(NaiveTime::MIN)
+                .and_local_timezone(Local)
+                .earliest()
+        };
+        Some(DefiniteTimeRange {
+            start: midnight(self.start)?,
+            end: midnight(self.end)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Person {
+    pub email: String,
+    pub display_name: Option<String>,
+}
+
+/// How an attendee answered the invitation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ResponseStatus {
+    #[default]
+    NeedsAction,
+    Declined,
+    Tentative,
+    Accepted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Attendee {
+    pub person: Person,
+    pub response_status: ResponseStatus,
+    /// Whether this attendee is the owner of the calendar the event was read from
+    pub is_self: bool,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Visibility {
+    /// Whatever the calendar's default visibility is
+    #[default]
+    Default,
+    Public,
+    Private,
+    Confidential,
+}
+
+/// Whether an event blocks time on the calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Transparency {
+    /// Busy
+    #[default]
+    Opaque,
+    /// Free
+    Transparent,
+}
+
+/// Whether an event is still happening
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EventStatus {
+    #[default]
+    Confirmed,
+    Tentative,
+    /// Calendars keep cancelled occurrences of repeating events around, marked like this
+    Cancelled,
+}
+
+/// The kinds of event that are drawn differently from ordinary ones
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EventKind {
+    #[default]
+    Default,
+    OutOfOffice,
+    /// Time blocked off for working without meetings
+    FocusTime,
+    /// Says where the calendar's owner is working, rather than taking up their time
+    WorkingLocation(WorkingLocation),
+    /// An all-day entry that says something about the day, like that it's a public holiday
+    Annotation,
+}
+
+/// What a working location that isn't home or an office is called when it has no name to show
+pub const ELSEWHERE: &str = "Elsewhere";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorkingLocation {
+    Home,
+    /// An office, and its name if it has one
+    Office(Option<String>),
+    Custom(String),
+}
+
+impl WorkingLocation {
+    /// What to call the place on screen
+    pub fn label(&self) -> &str {
+        match self {
+            WorkingLocation::Home => "Home",
+            WorkingLocation::Office(Some(name)) | WorkingLocation::Custom(name) => name,
+            WorkingLocation::Office(None) => "Office",
+        }
+    }
+}
+
+/// The video call service a meeting is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConferencePlatform {
+    Meet,
+    Zoom,
+    Teams,
+    Other,
+}
+
+impl ConferencePlatform {
+    /// Works out the service from the link's host, e.g. `us02web.zoom.us`
+    pub fn of_url(url: &str) -> ConferencePlatform {
+        let host = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let host = host.split(['/', '?', '#']).next().unwrap_or_default();
+        let host = host
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let is = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+        if is("meet.google.com") {
+            ConferencePlatform::Meet
+        } else if is("zoom.us") || is("zoomgov.com") {
+            ConferencePlatform::Zoom
+        } else if is("teams.microsoft.com") || is("teams.live.com") {
+            ConferencePlatform::Teams
+        } else {
+            ConferencePlatform::Other
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConferencePlatform::Meet => "Google Meet",
+            ConferencePlatform::Zoom => "Zoom",
+            ConferencePlatform::Teams => "Microsoft Teams",
+            ConferencePlatform::Other => "Video call",
+        }
+    }
+}
+
+/// How to join a meeting's video call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConferenceLink {
+    pub platform: ConferencePlatform,
+    pub url: String,
+    /// A phone number to join by instead, with the PIN to enter if there is one
+    pub dial_in: Option<String>,
+}
+
+impl ConferenceLink {
+    pub fn new(url: &str) -> ConferenceLink {
+        ConferenceLink {
+            platform: ConferencePlatform::of_url(url),
+            url: url.to_string(),
+            dial_in: None,
+        }
+    }
+
+    pub fn with_dial_in(self, number: &str, pin: Option<&str>) -> ConferenceLink {
+        let dial_in = match pin {
+            Some(pin) => format!("{} PIN: {}#", number, pin),
+            None => number.to_string(),
+        };
+        ConferenceLink {
+            dial_in: Some(dial_in),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    /// Parses colors like `#9fe1e7`, the way calendar providers send them
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+/// The calendar an event was read from
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct EventSource {
+    pub calendar_id: String,
+    pub calendar_name: String,
+    pub background_color: Option<Color>,
+    /// Set only for the holidays bundled with this crate, rather than read from a calendar someone configured
+    #[serde(default)]
+    pub bundled: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CalendarEvent {
-    pub name: String,
+    /// The provider's ID for the event, which is different for each occurrence of a repeating event
+    pub id: Option<String>,
+    /// The event's title
+    pub summary: String,
     pub times: IndefiniteTimeRange<Local>,
+    /// Set for all-day events, which have dates but no times.
+    /// `times` then spans local midnight to midnight of those dates.
+    pub all_day: Option<DateRange>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub organizer: Option<Person>,
+    pub attendees: Vec<Attendee>,
+    pub visibility: Visibility,
+    pub transparency: Transparency,
+    #[serde(default)]
+    pub status: EventStatus,
+    #[serde(default)]
+    pub kind: EventKind,
+    /// The provider's identifier for the event's color, if it overrides the calendar's color
+    pub color_id: Option<String>,
+    /// The color `color_id` stands for, or the event's own color if the provider says it directly.
+    /// Events without one are shown in their calendar's color.
+    pub color: Option<Color>,
+    /// A link to the event in the provider's web UI
+    pub html_link: Option<String>,
+    /// How to join the event's video call, if it has one
+    pub conference_link: Option<ConferenceLink>,
+    /// The iCalendar UID, which an event keeps in every calendar it's copied or invited to.
+    /// Every occurrence of a repeating event has the same one.
+    pub ical_uid: Option<String>,
+    pub source: Option<EventSource>,
+}
+
+impl CalendarEvent {
+    pub fn is_all_day(&self) -> bool {
+        self.all_day.is_some()
+    }
+
+    /// How the calendar's owner responded to this event, if they were invited to it
+    pub fn self_response(&self) -> Option<ResponseStatus> {
+        self.attendees
+            .iter()
+            .find(|a| a.is_self)
+            .map(|a| a.response_status)
+    }
+
+    pub fn working_location(&self) -> Option<&WorkingLocation> {
+        match &self.kind {
+            EventKind::WorkingLocation(location) => Some(location),
+            _ => None,
+        }
+    }
+}
+
+/// Something to note on a day's header, like a public holiday, from an [`EventKind::Annotation`] event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayAnnotation {
+    pub date: NaiveDate,
+    pub label: String,
+    /// The event's or its calendar's color, to tint the day with
+    pub color: Option<Color>,
+}
+
+impl DayAnnotation {
+    /// An annotation for each day of each annotation event that's in `dates`.
+    /// The same holiday from more than one calendar is only noted once.
+    pub fn of_events(events: &[CalendarEvent], dates: &DateRange) -> Vec<DayAnnotation> {
+        let mut annotations: Vec<DayAnnotation> = Vec::new();
+        for event in events
+            .iter()
+            .filter(|event| event.kind == EventKind::Annotation)
+        {
+            let Some(event_dates) = &event.all_day else {
+                continue;
+            };
+            let mut date = max(event_dates.start, dates.start);
+            while date < min(event_dates.end, dates.end) {
+                let noted = annotations.iter().any(|annotation| {
+                    annotation.date == date && annotation.label.eq_ignore_ascii_case(&event.summary)
+                });
+                if !noted {
+                    annotations.push(DayAnnotation {
+                        date,
+                        label: event.summary.clone(),
+                        color: event
+                            .color
+                            .or_else(|| event.source.as_ref()?.background_color),
+                    });
+                }
+                date += Duration::days(1);
+            }
+        }
+        annotations
+    }
+}
+
+/// Something to do, from a to-do list rather than a calendar
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Task {
+    pub id: Option<String>,
+    pub summary: String,
+    pub description: Option<String>,
+    /// The day it's due by; tasks without one are never shown
+    pub due: Option<NaiveDate>,
+    /// 1 is the most important, as in iCalendar. Tasks without one come after those with one.
+    pub priority: Option<u8>,
+    pub visibility: Visibility,
+    /// The list it was read from, which filters and redaction treat like an event's calendar
+    pub source: Option<EventSource>,
+}
+
+impl Task {
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        self.due.is_some_and(|due| due < today)
+    }
+}
+
+/// The part of each day to look for free time in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Default for WorkingHours {
+    fn default() -> Self {
+        WorkingHours {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        }
+    }
+}
+
+impl WorkingHours {
+    fn on(&self, date: NaiveDate) -> Option<DefiniteTimeRange<Local>> {
+        Some(DefiniteTimeRange {
+            start: date
+                .and_time(self.start)
+                .and_local_timezone(Local)
+                .earliest()?,
+            end: date.and_time(self.end).and_local_timezone(Local).latest()?,
+        })
+    }
+
+    /// The working hours of each day that overlaps `range`, cut down to fit in it
+    pub fn within(&self, range: &DefiniteTimeRange<Local>) -> Vec<DefiniteTimeRange<Local>> {
+        range
+            .start
+            .date_naive()
+            .iter_days()
+            .take_while(|date| *date <= range.end.date_naive())
+            .filter_map(|date| self.on(date))
+            .map(|hours| DefiniteTimeRange {
+                start: max(hours.start, range.start),
+                end: min(hours.end, range.end),
+            })
+            .filter(|hours| hours.start < hours.end)
+            .collect()
+    }
+}
+
+/// Times when the calendar's owner is busy, in order and without overlaps
+#[derive(Debug, Clone, Default)]
+pub struct BusyTimes {
+    times: Vec<DefiniteTimeRange<Local>>,
+}
+
+impl BusyTimes {
+    pub fn new(mut times: Vec<DefiniteTimeRange<Local>>) -> BusyTimes {
+        times.sort_by_key(|time| time.start);
+        let mut merged: Vec<DefiniteTimeRange<Local>> = Vec::with_capacity(times.len());
+        for time in times.into_iter().filter(|time| time.start < time.end) {
+            match merged.last_mut() {
+                Some(last) if time.start <= last.end => last.end = max(last.end, time.end),
+                _ => merged.push(time),
+            }
+        }
+        BusyTimes { times: merged }
+    }
+
+    /// The times of events that block time: ones that aren't free, cancelled, declined, or working locations.
+    /// All-day events are left out, since they're mostly holidays, birthdays and reminders,
+    /// except for out-of-office ones, which block each of their days as they would with free/busy queries.
+    pub fn of_events(events: &[CalendarEvent]) -> BusyTimes {
+        BusyTimes::new(
+            events
+                .iter()
+                .filter(|event| {
+                    (!event.is_all_day() || event.kind == EventKind::OutOfOffice)
+                        && event.transparency == Transparency::Opaque
+                        && event.status != EventStatus::Cancelled
+                        && event.self_response() != Some(ResponseStatus::Declined)
+                        && event.working_location().is_none()
+                })
+                .filter_map(|event| match &event.all_day {
+                    Some(dates) => dates.in_local_time(),
+                    None => Some(DefiniteTimeRange {
+                        start: event.times.start?,
+                        end: event.times.end?,
+                    }),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn times(&self) -> &[DefiniteTimeRange<Local>] {
+        &self.times
+    }
+
+    /// Busy whenever either is
+    pub fn merged_with(self, other: BusyTimes) -> BusyTimes {
+        BusyTimes::new(self.times.into_iter().chain(other.times).collect())
+    }
+
+    pub fn is_busy_at(&self, time: DateTime<Local>) -> bool {
+        self.times
+            .iter()
+            .any(|busy| busy.start <= time && time < busy.end)
+    }
+
+    /// The gaps of at least `min_length` between busy times, within working hours in `range`
+    pub fn free_slots(
+        &self,
+        range: &DefiniteTimeRange<Local>,
+        hours: &WorkingHours,
+        min_length: Duration,
+    ) -> Vec<DefiniteTimeRange<Local>> {
+        let mut slots = Vec::new();
+        for window in hours.within(range) {
+            let mut free_from = window.start;
+            for busy in self
+                .times
+                .iter()
+                .filter(|busy| busy.end > window.start && busy.start < window.end)
+            {
+                if busy.start - free_from >= min_length {
+                    slots.push(DefiniteTimeRange {
+                        start: free_from,
+                        end: busy.start,
+                    });
+                }
+                free_from = max(free_from, busy.end);
+            }
+            if window.end - free_from >= min_length {
+                slots.push(DefiniteTimeRange {
+                    start: free_from,
+                    end: window.end,
+                });
+            }
+        }
+        slots
+    }
+
+    /// The first time in `range` that's free for `length`, for "Next free 45-minute slot: 15:15"
+    pub fn next_free_slot(
+        &self,
+        range: &DefiniteTimeRange<Local>,
+        hours: &WorkingHours,
+        length: Duration,
+    ) -> Option<DefiniteTimeRange<Local>> {
+        let slot = self.free_slots(range, hours, length).into_iter().next()?;
+        Some(DefiniteTimeRange {
+            start: slot.start,
+            end: slot.start + length,
+        })
+    }
+
+    /// When the free time that `now` is in ends, for "Free until 14:30".
+    /// Nothing if `now` is busy, or outside working hours.
+    pub fn free_until(
+        &self,
+        now: DateTime<Local>,
+        hours: &WorkingHours,
+    ) -> Option<DateTime<Local>> {
+        let today = hours.on(now.date_naive())?;
+        if now < today.start || today.end <= now || self.is_busy_at(now) {
+            return None;
+        }
+        let next_busy = self
+            .times
+            .iter()
+            .map(|busy| busy.start)
+            .find(|start| *start > now);
+        Some(next_busy.map_or(today.end, |start| min(start, today.end)))
+    }
 }
 
 // impl CalendarEvent {
@@ -85,5 +620,242 @@ pub struct CurrentStatus {
 #[derive(Debug)]
 pub struct Model {
     pub events: Vec<CalendarEvent>,
+    /// Tasks due today or overdue, most important first
+    pub tasks: Vec<Task>,
+    /// Holidays and the like, noted on the days they're on rather than drawn as events
+    pub annotations: Vec<DayAnnotation>,
     pub status: CurrentStatus,
+    /// When the events were fetched, if they're from a cache because fetching them just failed
+    pub stale_since: Option<DateTime<Utc>>,
+    /// The calendars whose events are missing because they couldn't be fetched
+    pub failed_calendars: Vec<String>,
+}
+
+impl Model {
+    /// The meeting with a video call that's on now, or else the next one
+    pub fn meeting_to_join(&self, now: DateTime<Local>) -> Option<&CalendarEvent> {
+        self.events
+            .iter()
+            .filter(|event| event.conference_link.is_some() && !event.is_all_day())
+            .filter(|event| {
+                event.status != EventStatus::Cancelled
+                    && event.self_response() != Some(ResponseStatus::Declined)
+            })
+            .filter(|event| event.times.end.is_some_and(|end| now < end))
+            .min_by_key(|event| event.times.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2023, 9, 5, hour, minute, 0).unwrap()
+    }
+
+    fn span(start: DateTime<Local>, end: DateTime<Local>) -> DefiniteTimeRange<Local> {
+        DefiniteTimeRange { start, end }
+    }
+
+    fn times(ranges: &[DefiniteTimeRange<Local>]) -> Vec<(String, String)> {
+        ranges
+            .iter()
+            .map(|range| {
+                (
+                    range.start.format("%d %H:%M").to_string(),
+                    range.end.format("%d %H:%M").to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_hex_colors_with_or_without_a_hash() {
+        let teal = Some(Color {
+            r: 0x9f,
+            g: 0xe1,
+            b: 0xe7,
+        });
+        assert_eq!(teal, Color::from_hex("#9fe1e7"));
+        assert_eq!(teal, Color::from_hex("9FE1E7"));
+        assert_eq!(None, Color::from_hex(""));
+        assert_eq!(None, Color::from_hex("#fff"));
+        assert_eq!(None, Color::from_hex("#9fe1e7ff"));
+        assert_eq!(None, Color::from_hex("#9fe1eg"));
+        assert_eq!(None, Color::from_hex("#9fé1e7"));
+    }
+
+    #[test]
+    fn finds_free_slots_between_meetings_in_working_hours() {
+        let busy = BusyTimes::new(vec![
+            span(at(10, 0), at(11, 0)),
+            span(at(10, 30), at(11, 30)),
+            span(at(13, 0), at(14, 30)),
+            span(at(15, 0), at(15, 15)),
+        ]);
+        let range = span(at(0, 0), at(0, 0) + Duration::days(2));
+        let hours = WorkingHours::default();
+
+        let slots = busy.free_slots(&range, &hours, Duration::minutes(45));
+        let expected = [
+            ("05 09:00", "05 10:00"),
+            ("05 11:30", "05 13:00"),
+            ("05 15:15", "05 17:00"),
+            ("06 09:00", "06 17:00"),
+        ];
+        assert_eq!(
+            expected
+                .map(|(s, e)| (s.to_string(), e.to_string()))
+                .to_vec(),
+            times(&slots)
+        );
+
+        let after_lunch = span(at(12, 30), range.end);
+        let slot = busy
+            .next_free_slot(&after_lunch, &hours, Duration::minutes(45))
+            .unwrap();
+        assert_eq!((at(15, 15), at(16, 0)), (slot.start, slot.end));
+    }
+
+    #[test]
+    fn all_day_out_of_office_is_busy_but_other_all_day_events_are_not() {
+        let all_day = |summary: &str, kind: EventKind| CalendarEvent {
+            summary: summary.to_string(),
+            all_day: Some(DateRange {
+                start: at(0, 0).date_naive(),
+                end: at(0, 0).date_naive() + Duration::days(5),
+            }),
+            kind,
+            ..Default::default()
+        };
+        let range = span(at(0, 0), at(0, 0) + Duration::days(7));
+        let hours = WorkingHours::default();
+        let hour = Duration::hours(1);
+
+        let birthday = BusyTimes::of_events(&[all_day("Birthday", EventKind::Default)]);
+        let slot = birthday.next_free_slot(&range, &hours, hour).unwrap();
+        assert_eq!((at(9, 0), at(10, 0)), (slot.start, slot.end));
+
+        let vacation = BusyTimes::of_events(&[
+            all_day("Birthday", EventKind::Default),
+            all_day("Vacation", EventKind::OutOfOffice),
+        ]);
+        let slots = vacation.free_slots(&range, &hours, hour);
+        assert_eq!(
+            vec![
+                ("10 09:00".to_string(), "10 17:00".to_string()),
+                ("11 09:00".to_string(), "11 17:00".to_string()),
+            ],
+            times(&slots)
+        );
+        let slot = vacation.next_free_slot(&range, &hours, hour).unwrap();
+        assert_eq!(
+            (at(9, 0) + Duration::days(5), at(10, 0) + Duration::days(5)),
+            (slot.start, slot.end)
+        );
+    }
+
+    #[test]
+    fn says_how_long_until_the_next_meeting() {
+        let busy = BusyTimes::of_events(&[CalendarEvent {
+            times: span(at(14, 30), at(15, 0)).to_indefinite(),
+            ..Default::default()
+        }]);
+        let hours = WorkingHours::default();
+
+        assert_eq!(Some(at(14, 30)), busy.free_until(at(12, 0), &hours));
+        assert_eq!(None, busy.free_until(at(14, 45), &hours));
+        assert_eq!(Some(at(17, 0)), busy.free_until(at(15, 0), &hours));
+        assert_eq!(None, busy.free_until(at(20, 0), &hours));
+    }
+
+    #[test]
+    fn picks_the_current_or_next_meeting_to_join() {
+        let meeting = |summary: &str, start, end, url: Option<&str>| CalendarEvent {
+            summary: summary.to_string(),
+            times: span(start, end).to_indefinite(),
+            conference_link: url.map(ConferenceLink::new),
+            ..Default::default()
+        };
+        let model = Model {
+            events: vec![
+                meeting(
+                    "Planning",
+                    at(13, 0),
+                    at(14, 0),
+                    Some("https://us02web.zoom.us/j/123456789"),
+                ),
+                meeting(
+                    "Standup",
+                    at(9, 0),
+                    at(9, 15),
+                    Some("https://meet.google.com/abc-defg-hij"),
+                ),
+                meeting("Lunch", at(12, 0), at(13, 0), None),
+            ],
+            tasks: Vec::new(),
+            annotations: Vec::new(),
+            status: CurrentStatus {
+                has_meeting: false,
+                mic_active: false,
+                in_meeting: false,
+            },
+            stale_since: None,
+            failed_calendars: Vec::new(),
+        };
+
+        assert_eq!("Standup", model.meeting_to_join(at(9, 10)).unwrap().summary);
+        let planning = model.meeting_to_join(at(12, 30)).unwrap();
+        assert_eq!("Planning", planning.summary);
+        assert_eq!(
+            ConferencePlatform::Zoom,
+            planning.conference_link.as_ref().unwrap().platform
+        );
+        assert!(model.meeting_to_join(at(14, 0)).is_none());
+    }
+
+    #[test]
+    fn notes_each_day_of_annotation_events_once() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2023, 12, d).unwrap();
+        let holiday = |summary: &str, start: u32, end: u32| CalendarEvent {
+            summary: summary.to_string(),
+            all_day: Some(DateRange {
+                start: day(start),
+                end: day(end),
+            }),
+            kind: EventKind::Annotation,
+            ..Default::default()
+        };
+        let events = vec![
+            holiday("Christmas Day", 25, 26),
+            holiday("Christmas day", 25, 26),
+            holiday("Office closed", 22, 30),
+            CalendarEvent {
+                summary: "Party".to_string(),
+                all_day: Some(DateRange::single_day(day(25))),
+                ..Default::default()
+            },
+        ];
+
+        let annotations = DayAnnotation::of_events(
+            &events,
+            &DateRange {
+                start: day(25),
+                end: day(27),
+            },
+        );
+        let noted: Vec<(u32, &str)> = annotations
+            .iter()
+            .map(|a| (a.date.day(), a.label.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (25, "Christmas Day"),
+                (25, "Office closed"),
+                (26, "Office closed")
+            ],
+            noted
+        );
+    }
 }
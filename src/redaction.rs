@@ -0,0 +1,353 @@
+use crate::calendar::filter::EventPattern;
+use crate::calendar::holidays::HolidayCalendar;
+use crate::model::*;
+use serde::Deserialize;
+
+/// What to show instead of a hidden title
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placeholder {
+    #[default]
+    Busy,
+    /// The name of the calendar the event is from, or "Busy" if it doesn't have one
+    CalendarName,
+}
+
+/// Which events' details to hide from people walking past the screen.
+/// Hidden events keep their times, so the schedule still shows when people are busy.
+#[derive(Debug, Clone, Default)]
+pub struct RedactionPolicy {
+    placeholder: Placeholder,
+    patterns: Vec<EventPattern>,
+    everything: bool,
+}
+
+impl RedactionPolicy {
+    /// Only hides private and confidential events
+    pub fn new() -> RedactionPolicy {
+        RedactionPolicy::default()
+    }
+
+    pub fn with_placeholder(self, placeholder: Placeholder) -> RedactionPolicy {
+        RedactionPolicy {
+            placeholder,
+            ..self
+        }
+    }
+
+    /// Also hides events that match the pattern
+    pub fn with_pattern(mut self, pattern: EventPattern) -> RedactionPolicy {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Hides every event, for screens anyone could be looking at
+    pub fn hiding_everything(self) -> RedactionPolicy {
+        RedactionPolicy {
+            everything: true,
+            ..self
+        }
+    }
+
+    pub fn hides(&self, event: &CalendarEvent) -> bool {
+        self.everything || self.hides_anywhere(event)
+    }
+
+    /// Whether the event is hidden even on screens that aren't hiding everything
+    fn hides_anywhere(&self, event: &CalendarEvent) -> bool {
+        matches!(
+            event.visibility,
+            Visibility::Private | Visibility::Confidential
+        ) || self.patterns.iter().any(|pattern| pattern.matches(event))
+    }
+
+    pub fn apply(&self, events: Vec<CalendarEvent>) -> Vec<CalendarEvent> {
+        events
+            .into_iter()
+            .map(|event| match self.hides(&event) {
+                true => self.redact(event),
+                false => event,
+            })
+            .collect()
+    }
+
+    /// Splits the annotations off `events`, hiding them like any other event.
+    /// Bundled holidays don't say anything about anyone, so they're only hidden if they're private or match a pattern.
+    pub fn apply_to_schedule(
+        &self,
+        events: Vec<CalendarEvent>,
+        dates: &DateRange,
+    ) -> (Vec<CalendarEvent>, Vec<DayAnnotation>) {
+        let (annotations, events): (Vec<_>, Vec<_>) = events
+            .into_iter()
+            .partition(|event| event.kind == EventKind::Annotation);
+        let annotations: Vec<_> = annotations
+            .into_iter()
+            .map(|annotation| {
+                let hidden = match HolidayCalendar::made(&annotation) {
+                    true => self.hides_anywhere(&annotation),
+                    false => self.hides(&annotation),
+                };
+                match hidden {
+                    true => self.redact(annotation),
+                    false => annotation,
+                }
+            })
+            .collect();
+        (
+            self.apply(events),
+            DayAnnotation::of_events(&annotations, dates),
+        )
+    }
+
+    pub fn hides_task(&self, task: &Task) -> bool {
+        self.everything
+            || matches!(
+                task.visibility,
+                Visibility::Private | Visibility::Confidential
+            )
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches_task(task))
+    }
+
+    pub fn apply_to_tasks(&self, tasks: Vec<Task>) -> Vec<Task> {
+        tasks
+            .into_iter()
+            .map(|task| match self.hides_task(&task) {
+                true => Task {
+                    summary: self.placeholder_for(&task.source, "Task"),
+                    description: None,
+                    ..task
+                },
+                false => task,
+            })
+            .collect()
+    }
+
+    /// `busy` is what `Placeholder::Busy` says, which for tasks is "Task"
+    fn placeholder_for(&self, source: &Option<EventSource>, busy: &str) -> String {
+        match (self.placeholder, source) {
+            (Placeholder::CalendarName, Some(source)) if !source.calendar_name.is_empty() => {
+                source.calendar_name.clone()
+            }
+            _ => busy.to_string(),
+        }
+    }
+
+    /// Keeps when the event is and what calendar it's from, and nothing that says what it's about
+    fn redact(&self, event: CalendarEvent) -> CalendarEvent {
+        CalendarEvent {
+            summary: self.placeholder_for(&event.source, "Busy"),
+            description: None,
+            location: None,
+            organizer: None,
+            attendees: Vec::new(),
+            html_link: None,
+            conference_link: None,
+            kind: redact_kind(event.kind),
+            ..event
+        }
+    }
+}
+
+/// Working locations still say whether someone's at home or in an office, but not which office or where else they are
+fn redact_kind(kind: EventKind) -> EventKind {
+    match kind {
+        EventKind::WorkingLocation(location) => EventKind::WorkingLocation(match location {
+            WorkingLocation::Home => WorkingLocation::Home,
+            WorkingLocation::Office(_) => WorkingLocation::Office(None),
+            WorkingLocation::Custom(_) => WorkingLocation::Custom(ELSEWHERE.to_string()),
+        }),
+        kind => kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::prelude::*;
+    use regex::Regex;
+
+    fn event(summary: &str, visibility: Visibility) -> CalendarEvent {
+        let start = Local.with_ymd_and_hms(2023, 9, 5, 14, 0, 0).unwrap();
+        CalendarEvent {
+            summary: summary.to_string(),
+            visibility,
+            location: Some("Room 4".to_string()),
+            times: IndefiniteTimeRange {
+                start: Some(start),
+                end: Some(start + chrono::Duration::minutes(45)),
+            },
+            source: Some(EventSource {
+                calendar_name: "Recruiting".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn summaries(events: &[CalendarEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.summary.as_str()).collect()
+    }
+
+    #[test]
+    fn hides_private_events_and_ones_matching_patterns() {
+        let events = vec![
+            event("Interview: Jane Doe", Visibility::Default),
+            event("Therapy", Visibility::Private),
+            event("Standup", Visibility::Public),
+        ];
+        let policy = RedactionPolicy::new()
+            .with_pattern(EventPattern::Title(Regex::new("(?i)interview").unwrap()));
+
+        let redacted = policy.apply(events.clone());
+        assert_eq!(vec!["Busy", "Busy", "Standup"], summaries(&redacted));
+        assert_eq!(events[0].times.start, redacted[0].times.start);
+        assert_eq!(events[0].times.end, redacted[0].times.end);
+        assert_eq!(None, redacted[0].location);
+        assert_eq!(Some("Room 4"), redacted[2].location.as_deref());
+
+        let redacted = policy
+            .with_placeholder(Placeholder::CalendarName)
+            .apply(events);
+        assert_eq!(
+            vec!["Recruiting", "Recruiting", "Standup"],
+            summaries(&redacted)
+        );
+    }
+
+    #[test]
+    fn hides_everything_on_public_displays() {
+        let events = vec![
+            event("Standup", Visibility::Public),
+            event("1:1", Visibility::Default),
+        ];
+        let redacted = RedactionPolicy::new().hiding_everything().apply(events);
+        assert_eq!(vec!["Busy", "Busy"], summaries(&redacted));
+    }
+
+    #[test]
+    fn hides_annotations_but_not_holidays_on_public_displays() {
+        let labor_day = NaiveDate::from_ymd_opt(2023, 9, 4).unwrap();
+        let annotation =
+            |summary: &str, calendar_id: &str, calendar_name: &str, visibility| CalendarEvent {
+                kind: EventKind::Annotation,
+                all_day: Some(DateRange::single_day(labor_day)),
+                source: Some(EventSource {
+                    calendar_id: calendar_id.to_string(),
+                    calendar_name: calendar_name.to_string(),
+                    ..Default::default()
+                }),
+                ..event(summary, visibility)
+            };
+        let holiday = |summary: &str| CalendarEvent {
+            source: Some(EventSource {
+                calendar_id: "holidays:US".to_string(),
+                calendar_name: "Holidays in the United States".to_string(),
+                bundled: true,
+                ..Default::default()
+            }),
+            ..annotation(summary, "", "", Visibility::Default)
+        };
+        let events = || {
+            vec![
+                holiday("Labor Day"),
+                // Configured calendars are hidden whatever they're called
+                annotation(
+                    "Company offsite",
+                    "holidays:offsite.ics",
+                    "Offsite",
+                    Visibility::Default,
+                ),
+                annotation(
+                    "Dana on leave",
+                    "team-ooo@example.com",
+                    "Team OOO",
+                    Visibility::Default,
+                ),
+                annotation(
+                    "Dana at the clinic",
+                    "team-ooo@example.com",
+                    "Team OOO",
+                    Visibility::Private,
+                ),
+                event("Standup", Visibility::Public),
+            ]
+        };
+        let dates = DateRange {
+            start: labor_day,
+            end: labor_day + chrono::Duration::days(2),
+        };
+        let labels = |annotations: &[DayAnnotation]| {
+            annotations
+                .iter()
+                .map(|a| a.label.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let policy = RedactionPolicy::new()
+            .with_placeholder(Placeholder::CalendarName)
+            .hiding_everything();
+        let (events_shown, annotations) = policy.apply_to_schedule(events(), &dates);
+        assert_eq!(vec!["Recruiting"], summaries(&events_shown));
+        assert_eq!(
+            vec!["Labor Day", "Offsite", "Team OOO"],
+            labels(&annotations)
+        );
+
+        let policy = RedactionPolicy::new()
+            .with_pattern(EventPattern::Title(Regex::new("(?i)labor").unwrap()));
+        let (events_shown, annotations) = policy.apply_to_schedule(events(), &dates);
+        assert_eq!(vec!["Standup"], summaries(&events_shown));
+        assert_eq!(
+            vec!["Busy", "Company offsite", "Dana on leave"],
+            labels(&annotations)
+        );
+    }
+
+    #[test]
+    fn hides_which_office_or_place_someone_is_working_from() {
+        let working_from = |location| CalendarEvent {
+            kind: EventKind::WorkingLocation(location),
+            ..event("Working location", Visibility::Public)
+        };
+        let events = vec![
+            working_from(WorkingLocation::Office(Some("London, floor 3".to_string()))),
+            working_from(WorkingLocation::Custom("Client site in Leeds".to_string())),
+            working_from(WorkingLocation::Home),
+        ];
+
+        let redacted = RedactionPolicy::new().hiding_everything().apply(events);
+        let labels: Vec<&str> = redacted
+            .iter()
+            .filter_map(|e| e.working_location())
+            .map(|l| l.label())
+            .collect();
+        assert_eq!(vec!["Office", "Elsewhere", "Home"], labels);
+    }
+
+    #[test]
+    fn hides_private_tasks_and_ones_matching_patterns() {
+        let task = |summary: &str, visibility| Task {
+            summary: summary.to_string(),
+            description: Some("Details".to_string()),
+            visibility,
+            ..Default::default()
+        };
+        let tasks = vec![
+            task("Prepare interview questions", Visibility::Default),
+            task("Pick up prescription", Visibility::Private),
+            task("Send the invoice", Visibility::Default),
+        ];
+        let policy = RedactionPolicy::new()
+            .with_pattern(EventPattern::Title(Regex::new("(?i)interview").unwrap()));
+
+        let redacted = policy.apply_to_tasks(tasks);
+        let summaries: Vec<&str> = redacted.iter().map(|task| task.summary.as_str()).collect();
+        assert_eq!(vec!["Task", "Task", "Send the invoice"], summaries);
+        assert_eq!(None, redacted[0].description);
+        assert_eq!(Some("Details"), redacted[2].description.as_deref());
+    }
+}
@@ -1,13 +1,13 @@
-use std::fmt::Debug;
-use regex::Regex;
-use std::hash::Hash;
 use futures_core::Stream;
-use futures_util::stream::StreamExt;
 use futures_time::prelude::*;
+use futures_util::stream::StreamExt;
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::collections::hash_set::*;
+use std::fmt::Debug;
+use std::hash::Hash;
 use std::net::*;
 use std::time::Duration;
-    use once_cell::sync::Lazy;
 
 const CAST_PORT: u16 = 8009;
 const SERVICE_NAME: &'static str = "_googlecast._tcp.local";
@@ -17,16 +17,22 @@ const MAX_POLL_TIME_TOTAL: Duration = Duration::from_millis(1500);
 pub type MdnsResult<T> = Result<T, mdns::Error>;
 
 #[derive(Debug, Hash, PartialEq, Eq)]
-pub struct  FoundDevice {
+pub struct FoundDevice {
     pub addr: SocketAddr,
     pub name: String,
     pub hostname: String,
+    /// The model name advertised in the `md` TXT record, e.g. "Chromecast Ultra" or "Google Nest Hub"
+    pub model: Option<String>,
+    /// The capability bitmask advertised in the `ca` TXT record
+    pub capabilities: Option<u32>,
 }
 
 pub async fn scan_once_for_devices() -> MdnsResult<Vec<FoundDevice>> {
     let stream = scan_for_devices()?
-    .timeout_once(futures_time::time::Duration::from_millis(MAX_POLL_TIME_TOTAL.as_millis() as u64))
-    .fuse();
+        .timeout_once(futures_time::time::Duration::from_millis(
+            MAX_POLL_TIME_TOTAL.as_millis() as u64,
+        ))
+        .fuse();
     deduplicate(stream).await
 }
 
@@ -42,30 +48,66 @@ async fn get_found_device(response: MdnsResult<mdns::Response>) -> Option<MdnsRe
         Ok(r) => r,
         Err(e) => return Some(Err(e)),
     };
-    let addr = r.ip_addr().map(|ip_addr| SocketAddr::new(ip_addr, CAST_PORT));
+    let addr = r
+        .ip_addr()
+        .map(|ip_addr| SocketAddr::new(ip_addr, CAST_PORT));
     let hostname = r.hostname().map(|s| s.to_string());
-    let name = find_friendly_name(r.txt_records()).unwrap_or("UNNAMED".to_string());
-   addr.zip(hostname).map(|(addr, hostname)| Ok(FoundDevice{ addr: addr, hostname: hostname, name: name }))
+    let txt_records = r.txt_records().collect::<Vec<_>>();
+    let name = find_friendly_name(txt_records.iter().copied()).unwrap_or("UNNAMED".to_string());
+    let model = find_model_name(txt_records.iter().copied());
+    let capabilities = find_capabilities(txt_records.iter().copied());
+    addr.zip(hostname).map(|(addr, hostname)| {
+        Ok(FoundDevice {
+            addr: addr,
+            hostname: hostname,
+            name: name,
+            model: model,
+            capabilities: capabilities,
+        })
+    })
 }
 
-fn find_friendly_name<'a, I : Iterator<Item = &'a str>>(txt_records: I) -> Option<String> {
+fn find_friendly_name<'a, I: Iterator<Item = &'a str>>(txt_records: I) -> Option<String> {
     static FN_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("\\bfn=([^;\"]+)").unwrap());
 
+    find_txt_value(&FN_REGEX, txt_records)
+}
+
+fn find_model_name<'a, I: Iterator<Item = &'a str>>(txt_records: I) -> Option<String> {
+    static MD_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("\\bmd=([^;\"]+)").unwrap());
+
+    find_txt_value(&MD_REGEX, txt_records)
+}
+
+fn find_capabilities<'a, I: Iterator<Item = &'a str>>(txt_records: I) -> Option<u32> {
+    static CA_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("\\bca=([0-9]+)").unwrap());
+
+    find_txt_value(&CA_REGEX, txt_records).and_then(|ca| ca.parse().ok())
+}
+
+fn find_txt_value<'a, I: Iterator<Item = &'a str>>(
+    regex: &Regex,
+    txt_records: I,
+) -> Option<String> {
     txt_records
-        .filter_map(|s| FN_REGEX.captures(s))
+        .filter_map(|s| regex.captures(s))
         .filter_map(|c| c.get(1))
         .map(|m| m.as_str().to_string())
         .next()
 }
 
-async fn deduplicate<T: Hash + Eq, S: Stream<Item=MdnsResult<T>>>(finite_stream: S) -> MdnsResult<Vec<T>> {
-    let seen = finite_stream.fold(HashSet::new(), |mut set, elem| async move {
-        if let Ok(elem) = elem {
-            set.insert(elem);
-        }
+async fn deduplicate<T: Hash + Eq, S: Stream<Item = MdnsResult<T>>>(
+    finite_stream: S,
+) -> MdnsResult<Vec<T>> {
+    let seen = finite_stream
+        .fold(HashSet::new(), |mut set, elem| async move {
+            if let Ok(elem) = elem {
+                set.insert(elem);
+            }
 
-        set
-    }).await;
+            set
+        })
+        .await;
 
     Ok(seen.into_iter().collect::<Vec<_>>())
 }
@@ -0,0 +1,369 @@
+//! Saves tokens through a stand-in for the freedesktop Secret Service,
+//! running on a session bus of our own so the real keyring is never touched.
+//! Needs `dbus-daemon` on PATH, and fails without it rather than passing untested.
+
+use aes::Aes128;
+use block_modes::block_padding::Pkcs7;
+use block_modes::{BlockMode, Cbc};
+use cast_schedule::calendar::keyring::SecretServiceTokenStorage;
+use cast_schedule::calendar::tokens::*;
+use chrono::{Duration, Utc};
+use hkdf::Hkdf;
+use num_bigint::BigUint;
+use rand::Rng;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::{dbus_interface, fdo, ConnectionBuilder};
+
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const COLLECTION_PATH: &str = "/org/freedesktop/secrets/collection/login";
+/// Followed by the session's number
+const SESSION_PATH_PREFIX: &str = "/org/freedesktop/secrets/session/";
+const ATTRIBUTES_PROPERTY: &str = "org.freedesktop.Secret.Item.Attributes";
+/// Items are registered up front, so the stand-in never has to change its objects mid-call
+const ITEM_SLOTS: usize = 8;
+
+/// Session path, parameters, value, content type
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// The only kind of session the stand-in opens, so secrets are never sent in plain text
+const DH_ALGORITHM: &str = "dh-ietf1024-sha256-aes128-cbc-pkcs7";
+/// The 1024-bit MODP group from RFC 2409, whose generator is 2
+const DH_PRIME: &str = "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7EDEE386BFB5A899FA5AE9F24117C4B1FE649286651ECE65381FFFFFFFFFFFFFFFF";
+
+type Aes128Cbc = Cbc<Aes128, Pkcs7>;
+
+/// A `dbus-daemon` that's killed when the test is done with it
+struct SessionBus {
+    daemon: Child,
+    address: String,
+}
+
+impl SessionBus {
+    fn start() -> SessionBus {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--print-address", "--nofork"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|e| {
+                panic!(
+                    "could not start dbus-daemon, which the keyring test needs: {}",
+                    e
+                )
+            });
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .expect("could not read the session bus address");
+        SessionBus {
+            daemon,
+            address: address.trim().to_string(),
+        }
+    }
+}
+
+impl Drop for SessionBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+struct StoredItem {
+    attributes: HashMap<String, String>,
+    secret: Vec<u8>,
+}
+
+type Keyring = Arc<Mutex<Vec<Option<StoredItem>>>>;
+
+/// Each open session's AES key, by session number
+type Sessions = Arc<Mutex<Vec<[u8; 16]>>>;
+
+fn path(path: &str) -> OwnedObjectPath {
+    OwnedObjectPath::try_from(path).unwrap()
+}
+
+fn item_path(slot: usize) -> String {
+    format!("{}/{}", COLLECTION_PATH, slot)
+}
+
+fn matches(item: &StoredItem, attributes: &HashMap<String, String>) -> bool {
+    attributes
+        .iter()
+        .all(|(key, value)| item.attributes.get(key) == Some(value))
+}
+
+/// Diffie-Hellman with the client's public key, then HKDF-SHA256 down to an AES-128 key.
+/// Returns our public key to send back, and the key.
+fn agree_on_key(client_public_key: &[u8]) -> (Vec<u8>, [u8; 16]) {
+    let prime = BigUint::parse_bytes(DH_PRIME.as_bytes(), 16).unwrap();
+    let mut private_key = [0u8; 128];
+    rand::thread_rng().fill(&mut private_key[..]);
+    let private_key = BigUint::from_bytes_be(&private_key);
+    let public_key = BigUint::from(2u32).modpow(&private_key, &prime);
+
+    let shared = BigUint::from_bytes_be(client_public_key)
+        .modpow(&private_key, &prime)
+        .to_bytes_be();
+    let mut padded = vec![0; 128 - shared.len()];
+    padded.extend(shared);
+    let mut key = [0; 16];
+    Hkdf::<Sha256>::new(None, &padded)
+        .expand(&[], &mut key)
+        .unwrap();
+    (public_key.to_bytes_be(), key)
+}
+
+fn session_key(sessions: &Sessions, session: &str) -> fdo::Result<[u8; 16]> {
+    session
+        .strip_prefix(SESSION_PATH_PREFIX)
+        .and_then(|number| number.parse::<usize>().ok())
+        .and_then(|number| sessions.lock().unwrap().get(number).copied())
+        .ok_or_else(|| fdo::Error::InvalidArgs(format!("no session at {}", session)))
+}
+
+fn invalid_secret(e: impl std::fmt::Display) -> fdo::Error {
+    fdo::Error::InvalidArgs(format!("could not decrypt the secret: {}", e))
+}
+
+struct StandInService {
+    sessions: Sessions,
+}
+
+#[dbus_interface(name = "org.freedesktop.Secret.Service")]
+impl StandInService {
+    fn open_session(
+        &self,
+        algorithm: &str,
+        input: OwnedValue,
+    ) -> fdo::Result<(OwnedValue, OwnedObjectPath)> {
+        if algorithm != DH_ALGORITHM {
+            return Err(fdo::Error::NotSupported(format!(
+                "no {} sessions",
+                algorithm
+            )));
+        }
+        let client_public_key =
+            Vec::<u8>::try_from(input).map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+        let (public_key, key) = agree_on_key(&client_public_key);
+        let output = Value::from(public_key).into();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.push(key);
+        let session = format!("{}{}", SESSION_PATH_PREFIX, sessions.len() - 1);
+        Ok((output, path(&session)))
+    }
+
+    fn read_alias(&self, name: &str) -> OwnedObjectPath {
+        match name {
+            "default" => path(COLLECTION_PATH),
+            _ => path("/"),
+        }
+    }
+}
+
+struct StandInCollection {
+    keyring: Keyring,
+    sessions: Sessions,
+}
+
+#[dbus_interface(name = "org.freedesktop.Secret.Collection")]
+impl StandInCollection {
+    fn search_items(&self, attributes: HashMap<String, String>) -> Vec<OwnedObjectPath> {
+        let items = self.keyring.lock().unwrap();
+        (0..ITEM_SLOTS)
+            .filter(|&slot| {
+                items[slot]
+                    .as_ref()
+                    .is_some_and(|item| matches(item, &attributes))
+            })
+            .map(|slot| path(&item_path(slot)))
+            .collect()
+    }
+
+    fn create_item(
+        &self,
+        properties: HashMap<String, OwnedValue>,
+        secret: Secret,
+        replace: bool,
+    ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        let attributes = properties
+            .get(ATTRIBUTES_PROPERTY)
+            .cloned()
+            .ok_or_else(|| fdo::Error::InvalidArgs("no attributes".to_string()))?;
+        let attributes = HashMap::<String, String>::try_from(attributes)
+            .map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+        let (session, parameters, value, _) = secret;
+        let key = session_key(&self.sessions, session.as_str())?;
+        let value = Aes128Cbc::new_from_slices(&key, &parameters)
+            .map_err(invalid_secret)?
+            .decrypt_vec(&value)
+            .map_err(invalid_secret)?;
+
+        let mut items = self.keyring.lock().unwrap();
+        let existing = (0..ITEM_SLOTS).find(|&slot| {
+            replace
+                && items[slot]
+                    .as_ref()
+                    .is_some_and(|item| item.attributes == attributes)
+        });
+        let slot = existing
+            .or_else(|| (0..ITEM_SLOTS).find(|&slot| items[slot].is_none()))
+            .ok_or_else(|| fdo::Error::LimitsExceeded("stand-in keyring is full".to_string()))?;
+        items[slot] = Some(StoredItem {
+            attributes,
+            secret: value,
+        });
+        Ok((path(&item_path(slot)), path("/")))
+    }
+
+    #[dbus_interface(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+}
+
+struct StandInItem {
+    slot: usize,
+    keyring: Keyring,
+    sessions: Sessions,
+}
+
+#[dbus_interface(name = "org.freedesktop.Secret.Item")]
+impl StandInItem {
+    /// A 1-tuple, because the secret is a single struct argument
+    fn get_secret(&self, session: ObjectPath<'_>) -> fdo::Result<(Secret,)> {
+        let items = self.keyring.lock().unwrap();
+        let item = items[self.slot]
+            .as_ref()
+            .ok_or_else(|| fdo::Error::UnknownObject(item_path(self.slot)))?;
+        let key = session_key(&self.sessions, session.as_str())?;
+        let mut iv = [0u8; 16];
+        rand::thread_rng().fill(&mut iv[..]);
+        let value = Aes128Cbc::new_from_slices(&key, &iv)
+            .unwrap()
+            .encrypt_vec(&item.secret);
+        Ok(((
+            session.into(),
+            iv.to_vec(),
+            value,
+            "application/json".to_string(),
+        ),))
+    }
+
+    fn delete(&self) -> OwnedObjectPath {
+        self.keyring.lock().unwrap()[self.slot] = None;
+        path("/")
+    }
+
+    #[dbus_interface(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+}
+
+async fn serve_stand_in(keyring: Keyring) -> zbus::Result<zbus::Connection> {
+    let sessions = Sessions::default();
+    let mut builder = ConnectionBuilder::session()?
+        .name("org.freedesktop.secrets")?
+        .serve_at(
+            SERVICE_PATH,
+            StandInService {
+                sessions: sessions.clone(),
+            },
+        )?
+        .serve_at(
+            COLLECTION_PATH,
+            StandInCollection {
+                keyring: keyring.clone(),
+                sessions: sessions.clone(),
+            },
+        )?;
+    for slot in 0..ITEM_SLOTS {
+        builder = builder.serve_at(
+            item_path(slot),
+            StandInItem {
+                slot,
+                keyring: keyring.clone(),
+                sessions: sessions.clone(),
+            },
+        )?;
+    }
+    builder.build().await
+}
+
+fn token(access_token: &str) -> StoredToken {
+    StoredToken {
+        access_token: access_token.to_string(),
+        refresh_token: Some("refresh".to_string()),
+        expires_at: Utc::now() + Duration::hours(1),
+    }
+}
+
+// Everything happens in one test, because the bus address is process-wide
+#[async_std::test]
+async fn tokens_are_saved_replaced_and_cleared_in_the_keyring() {
+    let bus = SessionBus::start();
+    std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+
+    let keyring: Keyring = Arc::new(Mutex::new((0..ITEM_SLOTS).map(|_| None).collect()));
+    let _service = serve_stand_in(keyring.clone()).await.unwrap();
+    let store = SecretServiceTokenStorage::new("google");
+    let other_store = SecretServiceTokenStorage::new("microsoft");
+
+    assert_eq!(None, store.load().await);
+
+    store.save(&token("first")).await.unwrap();
+    assert_eq!(
+        Some("first".to_string()),
+        store.load().await.map(|t| t.access_token)
+    );
+    assert_eq!(None, other_store.load().await);
+    other_store.save(&token("other")).await.unwrap();
+    assert_eq!(
+        Some("other".to_string()),
+        other_store.load().await.map(|t| t.access_token)
+    );
+    other_store.clear().await.unwrap();
+
+    store.save(&token("second")).await.unwrap();
+    assert_eq!(
+        Some("second".to_string()),
+        store.load().await.map(|t| t.access_token)
+    );
+    let saved: Vec<_> = keyring
+        .lock()
+        .unwrap()
+        .iter()
+        .flatten()
+        .map(|item| item.attributes.clone())
+        .collect();
+    assert_eq!(
+        vec![HashMap::from([
+            ("application".to_string(), "cast-schedule".to_string()),
+            ("account".to_string(), "google".to_string()),
+        ])],
+        saved
+    );
+    // What's kept is the token itself, not what was sent over the bus to keep it
+    let secrets: Vec<String> = keyring
+        .lock()
+        .unwrap()
+        .iter()
+        .flatten()
+        .map(|item| {
+            serde_json::from_slice::<StoredToken>(&item.secret)
+                .unwrap()
+                .access_token
+        })
+        .collect();
+    assert_eq!(vec!["second".to_string()], secrets);
+
+    store.clear().await.unwrap();
+    assert_eq!(None, store.load().await);
+    assert!(keyring.lock().unwrap().iter().all(Option::is_none));
+}